@@ -0,0 +1,694 @@
+use std::{
+    ffi::c_void,
+    fmt::Debug,
+    ptr::{null, null_mut, NonNull},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use block::ConcreteBlock;
+use cocoa_foundation::{
+    base::{id, nil, BOOL, NO},
+    foundation::{NSArray, NSInteger, NSString},
+};
+use core_graphics_types::geometry::CGRect;
+use objc::{
+    declare::ClassDecl,
+    rc::StrongPtr,
+    runtime::{Class, Object, Sel},
+};
+use once_cell::sync::Lazy;
+use tracing::{debug, info_span, Span};
+
+use framework_sys as fw_sys;
+
+use crate::{error::Error, status::FrameStatus, time::CMTime};
+
+static STREAM_OUTPUT_DELEGATE: Lazy<&'static Class> = Lazy::new(|| {
+    let mut decl = ClassDecl::new("StreamOutputDelegate", class!(NSObject)).unwrap();
+    decl.add_ivar::<*const c_void>("_inner");
+    unsafe {
+        decl.add_method(sel!(setInner:), set_inner as extern "C" fn(&mut _, _, _));
+        decl.add_method(
+            sel!(stream:didOutputSampleBuffer:ofType:),
+            did_output_sample_buffer_of_type as extern "C" fn(&_, _, _, _, _),
+        );
+        decl.add_method(
+            sel!(dealloc),
+            dealloc_stream_output_delegate as extern "C" fn(&_, _),
+        );
+    }
+    decl.register()
+});
+
+static STREAM_DELEGATE: Lazy<&'static Class> = Lazy::new(|| {
+    let mut decl = ClassDecl::new("StreamDelegate", class!(NSObject)).unwrap();
+    decl.add_ivar::<*const c_void>("_inner");
+    unsafe {
+        decl.add_method(sel!(setInner:), set_inner as extern "C" fn(&mut _, _, _));
+        decl.add_method(
+            sel!(stream:didStopWithError:),
+            did_stop_with_error as extern "C" fn(&_, _, _, _),
+        );
+        decl.add_method(
+            sel!(dealloc),
+            dealloc_stream_delegate as extern "C" fn(&_, _),
+        );
+    }
+    decl.register()
+});
+
+extern "C" fn set_inner(this: &mut Object, _: Sel, inner_ptr: *mut c_void) {
+    unsafe {
+        this.set_ivar("_inner", inner_ptr);
+    }
+}
+
+extern "C" fn did_output_sample_buffer_of_type(
+    this: &Object,
+    _: Sel,
+    stream: id,
+    sample_buffer: id,
+    type_: NSInteger,
+) {
+    let sample_buffer = sample_buffer as fw_sys::CMSampleBufferRef;
+    unsafe {
+        let inner_ptr = *this.get_ivar::<*mut c_void>("_inner") as *mut OutputHandler;
+        let boxed_inner = Box::from_raw(inner_ptr);
+        let stream = Stream {
+            inner: StrongPtr::retain(stream),
+            span: boxed_inner.stream_span.clone(),
+            _delegate: None,
+        };
+        boxed_inner.span.in_scope(|| {
+            boxed_inner
+                .output
+                .did_output_sample_buffer_of_type(stream, sample_buffer, type_)
+        });
+        // forget
+        let _ = Box::into_raw(boxed_inner);
+    }
+}
+
+/// The stream holds on to its outputs until it is deallocated itself, so this
+/// runs once the last handle to the stream is gone.
+extern "C" fn dealloc_stream_output_delegate(this: &Object, _: Sel) {
+    unsafe {
+        let inner_ptr = *this.get_ivar::<*mut c_void>("_inner") as *mut OutputHandler;
+        if !inner_ptr.is_null() {
+            drop(Box::from_raw(inner_ptr));
+        }
+        let _: () = msg_send![super(this, class!(NSObject)), dealloc];
+    }
+}
+
+extern "C" fn did_stop_with_error(this: &Object, _: Sel, stream: id, error: id) {
+    unsafe {
+        let handler = &*(*this.get_ivar::<*mut c_void>("_inner") as *const DelegateHandler);
+        let _entered = handler.span.enter();
+        let stream = Stream {
+            inner: StrongPtr::retain(stream),
+            span: handler.span.clone(),
+            _delegate: None,
+        };
+        handler
+            .delegate
+            .did_stop_with_error(stream, ns_error(error, "Stream stopped"));
+    }
+}
+
+extern "C" fn dealloc_stream_delegate(this: &Object, _: Sel) {
+    unsafe {
+        let inner_ptr = *this.get_ivar::<*mut c_void>("_inner") as *mut DelegateHandler;
+        if !inner_ptr.is_null() {
+            drop(Box::from_raw(inner_ptr));
+        }
+        let _: () = msg_send![super(this, class!(NSObject)), dealloc];
+    }
+}
+
+struct DelegateHandler {
+    delegate: Arc<dyn StreamDelegate>,
+    span: Span,
+}
+
+struct OutputHandler {
+    output: Arc<dyn StreamOutput>,
+    span: Span,
+    stream_span: Span,
+}
+
+/// Turns an `NSError` into an [`Error`].
+///
+/// # Safety
+///
+/// `err` must be an `NSError`.
+unsafe fn ns_error(err: id, context: &'static str) -> anyhow::Error {
+    let domain: id = msg_send![err, domain];
+    let code: NSInteger = msg_send![err, code];
+    let description: id = msg_send![err, localizedDescription];
+    Error {
+        context,
+        domain: if domain.is_null() {
+            String::new()
+        } else {
+            to_rust_string(domain)
+        },
+        code,
+        description: (!description.is_null()).then(|| to_rust_string(description)),
+    }
+    .into()
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// Whether the app has Screen Recording permission, without prompting.
+pub fn preflight_screen_capture_access() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Prompts for Screen Recording permission unless the user has already
+/// decided, and returns whether it is granted. The system only prompts once
+/// per app; later calls just report the current decision.
+pub fn request_screen_capture_access() -> bool {
+    unsafe { CGRequestScreenCaptureAccess() }
+}
+
+/// Calls `callback` with the outcome of a completion handler that only takes
+/// an `NSError`.
+fn completion(
+    context: &'static str,
+    span: Span,
+    callback: impl Fn(Result<()>) + 'static,
+) -> impl Fn(id) {
+    move |err: id| {
+        let _entered = span.enter();
+        if err.is_null() {
+            debug!("{}: done", context);
+            callback(Ok(()));
+        } else {
+            callback(Err(unsafe { ns_error(err, context) }));
+        }
+    }
+}
+
+/// `SCStreamDelegate`.
+pub trait StreamDelegate {
+    /// The stream stopped on its own, e.g. because the captured window closed
+    /// or the system interrupted it.
+    fn did_stop_with_error(&self, stream: Stream, error: anyhow::Error);
+}
+
+pub trait StreamOutput {
+    fn did_output_sample_buffer_of_type(
+        &self,
+        stream: Stream,
+        sample_buffer: fw_sys::CMSampleBufferRef,
+        type_: NSInteger,
+    );
+}
+
+#[link(name = "ScreenCaptureKit", kind = "framework")]
+extern "C" {
+    static SCStreamFrameInfoStatus: id;
+}
+
+impl FrameStatus {
+    /// Reads the status from a sample buffer's `SCStreamFrameInfo` attachment.
+    ///
+    /// # Safety
+    ///
+    /// `sample_buffer` must be a valid sample buffer delivered by a stream.
+    pub unsafe fn of(sample_buffer: fw_sys::CMSampleBufferRef) -> Option<Self> {
+        let attachments = fw_sys::CMSampleBufferGetSampleAttachmentsArray(sample_buffer, 0) as id;
+        if attachments.is_null() || attachments.count() == 0 {
+            return None;
+        }
+        let info = attachments.objectAtIndex(0);
+        let status: id = msg_send![info, objectForKey: SCStreamFrameInfoStatus];
+        if status.is_null() {
+            return None;
+        }
+        let raw: NSInteger = msg_send![status, integerValue];
+        Self::from_raw(raw)
+    }
+}
+
+pub struct StreamConfig(StrongPtr);
+
+impl StreamConfig {
+    pub fn width(&self) -> usize {
+        unsafe { msg_send![*self.0, width] }
+    }
+    pub fn set_width(&mut self, width: usize) {
+        unsafe { msg_send![*self.0, setWidth: width] }
+    }
+
+    pub fn height(&self) -> usize {
+        unsafe { msg_send![*self.0, height] }
+    }
+    pub fn set_height(&mut self, height: usize) {
+        unsafe { msg_send![*self.0, setHeight: height] }
+    }
+
+    pub fn source_rect(&self) -> CGRect {
+        unsafe { msg_send![*self.0, sourceRect] }
+    }
+    pub fn set_source_rect(&mut self, source_rect: CGRect) {
+        unsafe { msg_send![*self.0, setSourceRect: source_rect] }
+    }
+
+    pub fn destination_rect(&self) -> CGRect {
+        unsafe { msg_send![*self.0, destinationRect] }
+    }
+    pub fn set_destination_rect(&mut self, destination_rect: CGRect) {
+        unsafe { msg_send![*self.0, setDestinationRect: destination_rect] }
+    }
+
+    pub fn shows_cursor(&self) -> bool {
+        let shows_cursor: BOOL = unsafe { msg_send![*self.0, showsCursor] };
+        shows_cursor != NO
+    }
+    pub fn set_shows_cursor(&mut self, shows_cursor: bool) {
+        unsafe { msg_send![*self.0, setShowsCursor: shows_cursor as BOOL] }
+    }
+
+    pub fn queue_depth(&self) -> NSInteger {
+        unsafe { msg_send![*self.0, queueDepth] }
+    }
+    pub fn set_queue_depth(&self, queue_depth: NSInteger) {
+        unsafe { msg_send![*self.0, setQueueDepth: queue_depth] }
+    }
+
+    pub fn minimum_frame_interval(&self) -> CMTime {
+        let minimum_frame_interval: fw_sys::CMTime =
+            unsafe { msg_send![*self.0, minimumFrameInterval] };
+        minimum_frame_interval.into()
+    }
+    pub fn set_minimum_frame_interval(&mut self, minimum_frame_interval: CMTime) {
+        let minimum_frame_interval: fw_sys::CMTime = minimum_frame_interval.into();
+        unsafe { msg_send![*self.0, setMinimumFrameInterval: minimum_frame_interval] }
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        let stream_config = unsafe {
+            let stream_config: id = msg_send![class!(SCStreamConfiguration), alloc];
+            let stream_config = StrongPtr::new(msg_send![stream_config, init]);
+            let _: () = msg_send![
+                *stream_config,
+                setPixelFormat: fw_sys::kCVPixelFormatType_32BGRA
+            ];
+            let _: () = msg_send![*stream_config, setColorSpaceName: fw_sys::kCGColorSpaceSRGB];
+            stream_config
+        };
+        Self(stream_config)
+    }
+}
+
+pub struct NSArrayIter {
+    ns_array: id,
+    pos: u64,
+}
+impl NSArrayIter {
+    fn new(ns_array: id) -> Self {
+        Self { ns_array, pos: 0 }
+    }
+}
+impl Iterator for NSArrayIter {
+    type Item = id;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos < unsafe { self.ns_array.count() } {
+            let pos = self.pos;
+            self.pos += 1;
+            Some(unsafe { self.ns_array.objectAtIndex(pos) })
+        } else {
+            None
+        }
+    }
+}
+
+fn to_rust_string(ns_string: id) -> String {
+    let s = unsafe {
+        let len = ns_string.len();
+        let bytes = std::slice::from_raw_parts(ns_string.UTF8String() as *const u8, len);
+        std::str::from_utf8_unchecked(bytes)
+    };
+    s.to_string()
+}
+
+pub struct ShareableContent(StrongPtr);
+unsafe impl Send for ShareableContent {}
+unsafe impl Sync for ShareableContent {}
+impl ShareableContent {
+    pub fn get(callback: impl Fn(Result<ShareableContent>) + 'static) {
+        let callback = std::sync::Mutex::new(Some(callback));
+        let block = ConcreteBlock::new(move |shareable_content: id, err: id| {
+            if let Some(callback) = callback.lock().unwrap().take() {
+                if err.is_null() {
+                    callback(Ok(unsafe { Self::retain(shareable_content) }));
+                } else {
+                    callback(Err(unsafe {
+                        ns_error(err, "Failed to get shareable content")
+                    }));
+                }
+            }
+        });
+        let block = block.copy();
+        unsafe {
+            let _: () = msg_send![
+                class!(SCShareableContent),
+                getShareableContentExcludingDesktopWindows:false onScreenWindowsOnly:true completionHandler:block
+            ];
+        }
+    }
+
+    pub unsafe fn retain(shareable_content: id) -> Self {
+        Self(StrongPtr::retain(shareable_content))
+    }
+
+    pub fn displays(&self) -> Vec<Display> {
+        let displays: id = unsafe { msg_send![*self.0, displays] };
+        NSArrayIter::new(displays)
+            .map(|d| Display(unsafe { StrongPtr::retain(d) }))
+            .collect()
+    }
+
+    pub fn windows(&self) -> Vec<Window> {
+        let windows: id = unsafe { msg_send![*self.0, windows] };
+        NSArrayIter::new(windows)
+            .map(|w| Window(unsafe { StrongPtr::retain(w) }))
+            .collect()
+    }
+
+    pub fn applications(&self) -> Vec<RunningApplication> {
+        let applications: id = unsafe { msg_send![*self.0, applications] };
+        NSArrayIter::new(applications)
+            .map(|a| RunningApplication(unsafe { StrongPtr::retain(a) }))
+            .collect()
+    }
+}
+impl Debug for ShareableContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ShareableContent").field(&*self.0).finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct Display(StrongPtr);
+
+impl Display {
+    pub fn display_id(&self) -> u32 {
+        unsafe { msg_send![*self.0, displayID] }
+    }
+
+    pub fn width(&self) -> isize {
+        unsafe { msg_send![*self.0, width] }
+    }
+
+    pub fn height(&self) -> isize {
+        unsafe { msg_send![*self.0, height] }
+    }
+
+    pub fn frame(&self) -> CGRect {
+        unsafe { msg_send![*self.0, frame] }
+    }
+}
+
+#[derive(Clone)]
+pub struct Window(StrongPtr);
+
+impl Window {
+    pub fn window_id(&self) -> u32 {
+        unsafe { msg_send![*self.0, windowID] }
+    }
+
+    pub fn title(&self) -> String {
+        let title: id = unsafe { msg_send![*self.0, title] };
+        to_rust_string(title)
+    }
+
+    pub fn frame(&self) -> CGRect {
+        unsafe { msg_send![*self.0, frame] }
+    }
+
+    pub fn owning_application(&self) -> RunningApplication {
+        let application = unsafe { msg_send![*self.0, owningApplication] };
+        RunningApplication(unsafe { StrongPtr::retain(application) })
+    }
+}
+
+#[derive(Clone)]
+pub struct RunningApplication(StrongPtr);
+
+impl RunningApplication {
+    pub fn process_id(&self) -> u32 {
+        unsafe { msg_send![*self.0, processID] }
+    }
+
+    pub fn bundle_identifier(&self) -> Option<String> {
+        let bundle_identifier: id = unsafe { msg_send![*self.0, bundleIdentifier] };
+        NonNull::new(bundle_identifier).map(|non_null| to_rust_string(non_null.as_ptr()))
+    }
+
+    pub fn application_name(&self) -> Option<String> {
+        let application_name = unsafe { msg_send![*self.0, applicationName] };
+        NonNull::new(application_name).map(|non_null| to_rust_string(non_null.as_ptr()))
+    }
+}
+
+fn windows_to_nsarray<'a>(into_iter: impl IntoIterator<Item = &'a Window>) -> id {
+    let windows: Vec<id> = into_iter.into_iter().map(|w| *w.0).collect();
+    unsafe { NSArray::arrayWithObjects(nil, &windows) }
+}
+
+fn apps_to_nsarray<'a>(into_iter: impl IntoIterator<Item = &'a RunningApplication>) -> id {
+    let apps: Vec<id> = into_iter.into_iter().map(|a| *a.0).collect();
+    unsafe { NSArray::arrayWithObjects(nil, &apps) }
+}
+
+pub struct ContentFilter(StrongPtr);
+impl ContentFilter {
+    pub fn with_desktop_independent_window(window: &Window) -> Self {
+        let filter = unsafe {
+            let filter: id = msg_send![class!(SCContentFilter), alloc];
+            StrongPtr::new(msg_send![filter, initWithDesktopIndependentWindow:*window.0])
+        };
+        Self(filter)
+    }
+
+    pub fn init_with_display_including_windows<'a>(
+        display: &'a Display,
+        excluding_windows: impl IntoIterator<Item = &'a Window>,
+    ) -> Self {
+        let filter = unsafe {
+            let filter: id = msg_send![class!(SCContentFilter), alloc];
+            StrongPtr::new(msg_send![
+                filter,
+                initWithDisplay:*display.0
+                includingWindows:windows_to_nsarray(excluding_windows)
+            ])
+        };
+        Self(filter)
+    }
+
+    pub fn init_with_display_excluding_windows<'a>(
+        display: &'a Display,
+        excluding_windows: impl IntoIterator<Item = &'a Window>,
+    ) -> Self {
+        let filter = unsafe {
+            let filter: id = msg_send![class!(SCContentFilter), alloc];
+            StrongPtr::new(msg_send![
+                filter,
+                initWithDisplay:*display.0
+                excludingWindows:windows_to_nsarray(excluding_windows)
+            ])
+        };
+        Self(filter)
+    }
+
+    pub fn init_with_display_including_applications_excepting_windows<'a>(
+        display: &'a Display,
+        including_applications: impl IntoIterator<Item = &'a RunningApplication>,
+        excepting_windows: impl IntoIterator<Item = &'a Window>,
+    ) -> Self {
+        let filter = unsafe {
+            let filter: id = msg_send![class!(SCContentFilter), alloc];
+            StrongPtr::new(msg_send![
+                filter,
+                initWithDisplay:*display.0
+                includingApplications:apps_to_nsarray(including_applications)
+                exceptingWindows:windows_to_nsarray(excepting_windows)
+            ])
+        };
+        Self(filter)
+    }
+
+    pub fn init_with_display_excluding_applications_excepting_windows<'a>(
+        display: &'a Display,
+        excluding_applications: impl IntoIterator<Item = &'a RunningApplication>,
+        excepting_windows: impl IntoIterator<Item = &'a Window>,
+    ) -> Self {
+        let filter = unsafe {
+            let filter: id = msg_send![class!(SCContentFilter), alloc];
+            StrongPtr::new(msg_send![
+                filter,
+                initWithDisplay:*display.0
+                excludingApplications:apps_to_nsarray(excluding_applications)
+                exceptingWindows:windows_to_nsarray(excepting_windows)
+            ])
+        };
+        Self(filter)
+    }
+}
+
+static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
+pub struct Stream {
+    inner: StrongPtr,
+    span: Span,
+    /// `SCStream` only holds its delegate weakly. Handles passed to callbacks
+    /// don't keep it alive; the one returned by `with_delegate` does.
+    _delegate: Option<StrongPtr>,
+}
+unsafe impl Send for Stream {}
+unsafe impl Sync for Stream {}
+
+impl Stream {
+    pub fn new(filter: ContentFilter, config: StreamConfig) -> Self {
+        Self::init(filter, config, None)
+    }
+
+    pub fn with_delegate(
+        filter: ContentFilter,
+        config: StreamConfig,
+        delegate: Arc<dyn StreamDelegate>,
+    ) -> Self {
+        Self::init(filter, config, Some(delegate))
+    }
+
+    fn init(
+        filter: ContentFilter,
+        config: StreamConfig,
+        delegate: Option<Arc<dyn StreamDelegate>>,
+    ) -> Self {
+        let id = NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed);
+        let span = info_span!("stream", id);
+        let delegate = delegate.map(|delegate| unsafe {
+            let handler = Box::new(DelegateHandler {
+                delegate,
+                span: span.clone(),
+            });
+            let objc_delegate: id = msg_send![*STREAM_DELEGATE, alloc];
+            let objc_delegate: id = msg_send![objc_delegate, init];
+            let inner_ptr = Box::into_raw(handler) as *const c_void;
+            let _: () = msg_send![objc_delegate, setInner: inner_ptr];
+            StrongPtr::new(objc_delegate)
+        });
+        let objc_delegate = delegate.as_ref().map_or(null_mut(), |delegate| **delegate);
+        let inner = unsafe {
+            let stream: id = msg_send![class!(SCStream), alloc];
+            let stream = StrongPtr::new(msg_send![stream, init]);
+            let _: () = msg_send![*stream, initWithFilter:filter.0 configuration:config.0 delegate:objc_delegate];
+            stream
+        };
+        Self {
+            inner,
+            span,
+            _delegate: delegate,
+        }
+    }
+
+    /// The span that callbacks for this stream run in.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn start_capture(&self, callback: impl Fn(Result<()>) + 'static) {
+        let block = ConcreteBlock::new(completion(
+            "Failed to start capture",
+            self.span.clone(),
+            callback,
+        ));
+        let _: () = unsafe { msg_send![*self.inner, startCaptureWithCompletionHandler: block] };
+    }
+
+    pub fn stop_capture(&self, callback: impl Fn(Result<()>) + 'static) {
+        let block = ConcreteBlock::new(completion(
+            "Failed to stop capture",
+            self.span.clone(),
+            callback,
+        ));
+        let _: () = unsafe { msg_send![*self.inner, stopCaptureWithCompletionHandler: block] };
+    }
+
+    pub fn update_content_filter(
+        &self,
+        filter: ContentFilter,
+        callback: impl Fn(Result<()>) + 'static,
+    ) {
+        let block = ConcreteBlock::new(completion(
+            "Failed to update content filter",
+            self.span.clone(),
+            callback,
+        ));
+        let _: () = unsafe {
+            msg_send![*self.inner, updateContentFilter:*filter.0 completionHandler:block]
+        };
+    }
+
+    pub fn update_configuration(
+        &self,
+        config: StreamConfig,
+        callback: impl Fn(Result<()>) + 'static,
+    ) {
+        let block = ConcreteBlock::new(completion(
+            "Failed to update configuration",
+            self.span.clone(),
+            callback,
+        ));
+        let _: () = unsafe {
+            msg_send![*self.inner, updateConfiguration:*config.0 completionHandler:block]
+        };
+    }
+
+    pub fn add_stream_output(
+        &self,
+        stream_output: Arc<dyn StreamOutput>,
+        type_: NSInteger,
+    ) -> Result<()> {
+        let handler = Box::new(OutputHandler {
+            output: stream_output,
+            span: info_span!(parent: &self.span, "output", type = type_),
+            stream_span: self.span.clone(),
+        });
+        let delegate = unsafe {
+            let delegate: id = msg_send![*STREAM_OUTPUT_DELEGATE, alloc];
+            let delegate: id = msg_send![delegate, init];
+            let inner_ptr = Box::into_raw(handler) as *const c_void;
+            let _: () = msg_send![delegate, setInner: inner_ptr];
+            StrongPtr::new(delegate)
+        };
+        let mut error: id = null_mut();
+        let added: bool = unsafe {
+            msg_send![*self.inner, addStreamOutput:delegate type:type_ sampleHandlerQueue:null::<id>() error:&mut error]
+        };
+        if added {
+            Ok(())
+        } else if error.is_null() {
+            Err(anyhow!("Failed to add stream output"))
+        } else {
+            Err(unsafe { ns_error(error, "Failed to add stream output") })
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+
+pub const SC_STREAM_ERROR_DOMAIN: &str = "com.apple.ScreenCaptureKit.SCStreamErrorDomain";
+
+/// `SCStreamErrorUserDeclined`: Screen Recording permission was denied.
+pub const SC_STREAM_ERROR_USER_DECLINED: i64 = -3801;
+
+/// An `NSError` reported by ScreenCaptureKit. Failing calls return it inside
+/// an [`anyhow::Error`], so use `downcast_ref` to inspect it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// What was being attempted.
+    pub context: &'static str,
+    pub domain: String,
+    /// The `NSInteger` error code.
+    pub code: i64,
+    pub description: Option<String>,
+}
+
+impl Error {
+    pub fn is_user_declined(&self) -> bool {
+        self.domain == SC_STREAM_ERROR_DOMAIN && self.code == SC_STREAM_ERROR_USER_DECLINED
+    }
+
+    /// Whether `err` is, or was caused by, a Screen Recording permission
+    /// denial.
+    pub fn is_permission_denied(err: &anyhow::Error) -> bool {
+        err.chain()
+            .filter_map(|cause| cause.downcast_ref::<Error>())
+            .any(Error::is_user_declined)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.description {
+            Some(description) => write!(
+                f,
+                "{}: {} ({} {})",
+                self.context, description, self.domain, self.code
+            ),
+            None => write!(f, "{} ({} {})", self.context, self.domain, self.code),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
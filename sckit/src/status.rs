@@ -0,0 +1,37 @@
+/// `SCFrameStatus`: why ScreenCaptureKit delivered a sample buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameStatus {
+    Complete,
+    Idle,
+    Blank,
+    Suspended,
+    Started,
+    Stopped,
+}
+
+impl FrameStatus {
+    pub const ALL: [FrameStatus; 6] = [
+        FrameStatus::Complete,
+        FrameStatus::Idle,
+        FrameStatus::Blank,
+        FrameStatus::Suspended,
+        FrameStatus::Started,
+        FrameStatus::Stopped,
+    ];
+
+    /// `raw` is the `NSInteger` value of the status.
+    pub fn from_raw(raw: i64) -> Option<Self> {
+        Self::ALL.get(usize::try_from(raw).ok()?).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameStatus::Complete => "complete",
+            FrameStatus::Idle => "idle",
+            FrameStatus::Blank => "blank",
+            FrameStatus::Suspended => "suspended",
+            FrameStatus::Started => "started",
+            FrameStatus::Stopped => "stopped",
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    cmp::Ordering,
+    ops::{Add, Mul, Neg, Sub},
+    time::Duration,
+};
+
+#[cfg(target_os = "macos")]
+use framework_sys as fw_sys;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CMTimeFlags(u32);
+
+impl CMTimeFlags {
+    pub const VALID: Self = Self(1 << 0);
+    pub const HAS_BEEN_ROUNDED: Self = Self(1 << 1);
+    pub const POSITIVE_INFINITY: Self = Self(1 << 2);
+    pub const NEGATIVE_INFINITY: Self = Self(1 << 3);
+    pub const INDEFINITE: Self = Self(1 << 4);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// A rational time value with the same layout rules as CoreMedia's `CMTime`,
+/// implemented in Rust so that arithmetic is exact where possible.
+#[derive(Debug, Clone, Copy)]
+pub struct CMTime {
+    value: i64,
+    timescale: i32,
+    flags: CMTimeFlags,
+    epoch: i64,
+}
+
+impl CMTime {
+    pub const INVALID: Self = Self::special(CMTimeFlags(0));
+    pub const INDEFINITE: Self = Self::special(CMTimeFlags::VALID.union(CMTimeFlags::INDEFINITE));
+    pub const POSITIVE_INFINITY: Self =
+        Self::special(CMTimeFlags::VALID.union(CMTimeFlags::POSITIVE_INFINITY));
+    pub const NEGATIVE_INFINITY: Self =
+        Self::special(CMTimeFlags::VALID.union(CMTimeFlags::NEGATIVE_INFINITY));
+    pub const ZERO: Self = Self::new(0, 1);
+
+    const fn special(flags: CMTimeFlags) -> Self {
+        Self {
+            value: 0,
+            timescale: 0,
+            flags,
+            epoch: 0,
+        }
+    }
+
+    /// `value / timescale` seconds. A non-positive timescale yields an invalid
+    /// time.
+    pub const fn new(value: i64, timescale: i32) -> Self {
+        if timescale <= 0 {
+            return Self::INVALID;
+        }
+        Self {
+            value,
+            timescale,
+            flags: CMTimeFlags::VALID,
+            epoch: 0,
+        }
+    }
+
+    /// The duration of one frame at `numerator / denominator` frames per
+    /// second.
+    pub fn from_fps(numerator: u32, denominator: u32) -> Self {
+        match i32::try_from(numerator) {
+            Ok(timescale) if denominator > 0 => Self::new(denominator as i64, timescale),
+            _ => Self::INVALID,
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    pub fn timescale(&self) -> i32 {
+        self.timescale
+    }
+
+    pub fn flags(&self) -> CMTimeFlags {
+        self.flags
+    }
+
+    pub fn epoch(&self) -> i64 {
+        self.epoch
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.flags.contains(CMTimeFlags::VALID)
+    }
+
+    pub fn is_indefinite(&self) -> bool {
+        self.is_valid() && self.flags.contains(CMTimeFlags::INDEFINITE)
+    }
+
+    pub fn is_positive_infinity(&self) -> bool {
+        self.is_valid() && self.flags.contains(CMTimeFlags::POSITIVE_INFINITY)
+    }
+
+    pub fn is_negative_infinity(&self) -> bool {
+        self.is_valid() && self.flags.contains(CMTimeFlags::NEGATIVE_INFINITY)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_valid()
+            && !self.is_indefinite()
+            && !self.is_positive_infinity()
+            && !self.is_negative_infinity()
+    }
+
+    pub fn has_been_rounded(&self) -> bool {
+        self.flags.contains(CMTimeFlags::HAS_BEEN_ROUNDED)
+    }
+
+    /// Re-expresses a numeric time in `timescale`, rounding half away from zero.
+    /// Non-numeric times are returned unchanged.
+    pub fn convert_scale(self, timescale: i32) -> Self {
+        if !self.is_numeric() {
+            return self;
+        }
+        if timescale <= 0 {
+            return Self::INVALID;
+        }
+        let (value, exact) = rescale(self.value as i128, self.timescale, timescale);
+        match i64::try_from(value) {
+            Ok(value) => {
+                let mut time = Self::new(value, timescale);
+                time.epoch = self.epoch;
+                time.flags = self.flags;
+                if !exact {
+                    time.flags = time.flags.union(CMTimeFlags::HAS_BEEN_ROUNDED);
+                }
+                time
+            }
+            Err(_) if value > 0 => Self::POSITIVE_INFINITY,
+            Err(_) => Self::NEGATIVE_INFINITY,
+        }
+    }
+
+    /// Numeric times are seconds as `f64`; infinities map to `f64` infinities
+    /// and everything else to NaN.
+    pub fn as_secs_f64(&self) -> f64 {
+        if self.is_numeric() {
+            self.value as f64 / self.timescale as f64
+        } else if self.is_positive_infinity() {
+            f64::INFINITY
+        } else if self.is_negative_infinity() {
+            f64::NEG_INFINITY
+        } else {
+            f64::NAN
+        }
+    }
+
+    /// Non-negative numeric times as a `Duration`, rounded to the nearest
+    /// nanosecond.
+    pub fn to_duration(&self) -> Option<Duration> {
+        if !self.is_numeric() || self.value < 0 {
+            return None;
+        }
+        let (nanos, _) = rescale_to(self.value as i128, self.timescale as i128, NANOS_PER_SECOND);
+        u64::try_from(nanos).ok().map(Duration::from_nanos)
+    }
+
+    fn non_numeric_sum(self, rhs: Self) -> Option<Self> {
+        if !self.is_valid() || !rhs.is_valid() {
+            return Some(Self::INVALID);
+        }
+        if self.is_indefinite() || rhs.is_indefinite() {
+            return Some(Self::INDEFINITE);
+        }
+        let positive = self.is_positive_infinity() || rhs.is_positive_infinity();
+        let negative = self.is_negative_infinity() || rhs.is_negative_infinity();
+        match (positive, negative) {
+            (true, true) => Some(Self::INVALID),
+            (true, false) => Some(Self::POSITIVE_INFINITY),
+            (false, true) => Some(Self::NEGATIVE_INFINITY),
+            (false, false) => None,
+        }
+    }
+
+    /// Total order following `CMTimeCompare`: negative infinity, numeric
+    /// times, indefinite, positive infinity, invalid.
+    fn rank(&self) -> u8 {
+        if !self.is_valid() {
+            4
+        } else if self.is_positive_infinity() {
+            3
+        } else if self.is_indefinite() {
+            2
+        } else if self.is_negative_infinity() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+/// `value * to / from` rounded half away from zero, and whether it was exact.
+fn rescale_to(value: i128, from: i128, to: i128) -> (i128, bool) {
+    let scaled = value * to;
+    let quotient = scaled / from;
+    let remainder = scaled % from;
+    if remainder == 0 {
+        return (quotient, true);
+    }
+    let rounded = if remainder.abs() * 2 >= from {
+        quotient + scaled.signum()
+    } else {
+        quotient
+    };
+    (rounded, false)
+}
+
+fn rescale(value: i128, from: i32, to: i32) -> (i128, bool) {
+    rescale_to(value, from as i128, to as i128)
+}
+
+impl Add for CMTime {
+    type Output = CMTime;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        if let Some(time) = self.non_numeric_sum(rhs) {
+            return time;
+        }
+        let (lhs_scale, rhs_scale) = (self.timescale as i128, rhs.timescale as i128);
+        let lcm = lhs_scale / gcd(lhs_scale, rhs_scale) * rhs_scale;
+        // Prefer the exact common timescale; CoreMedia falls back to the larger
+        // of the two and rounds when it doesn't fit.
+        let (timescale, rounded) = match i32::try_from(lcm) {
+            Ok(lcm) => (lcm, false),
+            Err(_) => (self.timescale.max(rhs.timescale), true),
+        };
+        let (lhs_value, lhs_exact) = rescale(self.value as i128, self.timescale, timescale);
+        let (rhs_value, rhs_exact) = rescale(rhs.value as i128, rhs.timescale, timescale);
+        let sum = lhs_value + rhs_value;
+        match i64::try_from(sum) {
+            Ok(value) => {
+                let mut time = Self::new(value, timescale);
+                if (rounded && !(lhs_exact && rhs_exact))
+                    || self.has_been_rounded()
+                    || rhs.has_been_rounded()
+                {
+                    time.flags = time.flags.union(CMTimeFlags::HAS_BEEN_ROUNDED);
+                }
+                time
+            }
+            Err(_) if sum > 0 => Self::POSITIVE_INFINITY,
+            Err(_) => Self::NEGATIVE_INFINITY,
+        }
+    }
+}
+
+impl Neg for CMTime {
+    type Output = CMTime;
+
+    fn neg(self) -> Self::Output {
+        if self.is_positive_infinity() {
+            Self::NEGATIVE_INFINITY
+        } else if self.is_negative_infinity() {
+            Self::POSITIVE_INFINITY
+        } else if self.is_numeric() {
+            match self.value.checked_neg() {
+                Some(value) => Self { value, ..self },
+                None => Self::POSITIVE_INFINITY,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+impl Sub for CMTime {
+    type Output = CMTime;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + -rhs
+    }
+}
+
+impl Mul<i32> for CMTime {
+    type Output = CMTime;
+
+    fn mul(self, rhs: i32) -> Self::Output {
+        if !self.is_numeric() {
+            return match (rhs.signum(), self.is_valid() && !self.is_indefinite()) {
+                (-1, true) => -self,
+                (0, true) => Self::INVALID,
+                _ => self,
+            };
+        }
+        match self.value.checked_mul(rhs as i64) {
+            Some(value) => Self { value, ..self },
+            None if (self.value > 0) == (rhs > 0) => Self::POSITIVE_INFINITY,
+            None => Self::NEGATIVE_INFINITY,
+        }
+    }
+}
+
+impl PartialEq for CMTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CMTime {}
+
+impl PartialOrd for CMTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Like `CMTimeCompare`, numeric times in a later epoch are greater
+/// regardless of their value.
+impl Ord for CMTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.rank().cmp(&other.rank()) {
+            Ordering::Equal if self.is_numeric() => {
+                let lhs = self.value as i128 * other.timescale as i128;
+                let rhs = other.value as i128 * self.timescale as i128;
+                self.epoch.cmp(&other.epoch).then(lhs.cmp(&rhs))
+            }
+            ordering => ordering,
+        }
+    }
+}
+
+impl From<Duration> for CMTime {
+    /// Nanosecond precision; durations too long for that fall back to
+    /// microseconds, then milliseconds, then positive infinity.
+    fn from(duration: Duration) -> Self {
+        let nanos = duration.as_nanos() as i128;
+        for timescale in [1_000_000_000, 1_000_000, 1_000] {
+            let (value, exact) = rescale_to(nanos, NANOS_PER_SECOND, timescale as i128);
+            if let Ok(value) = i64::try_from(value) {
+                let mut time = Self::new(value, timescale);
+                if !exact {
+                    time.flags = time.flags.union(CMTimeFlags::HAS_BEEN_ROUNDED);
+                }
+                return time;
+            }
+        }
+        Self::POSITIVE_INFINITY
+    }
+}
+
+impl TryFrom<CMTime> for Duration {
+    type Error = anyhow::Error;
+
+    fn try_from(time: CMTime) -> Result<Self, Self::Error> {
+        time.to_duration()
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not a non-negative finite time", time))
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<fw_sys::CMTime> for CMTime {
+    fn from(time: fw_sys::CMTime) -> Self {
+        Self {
+            value: time.value,
+            timescale: time.timescale,
+            flags: CMTimeFlags::from_bits(time.flags),
+            epoch: time.epoch,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<CMTime> for fw_sys::CMTime {
+    fn from(time: CMTime) -> Self {
+        fw_sys::CMTime {
+            value: time.value,
+            timescale: time.timescale,
+            flags: time.flags.bits(),
+            epoch: time.epoch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_epoch(time: CMTime, epoch: i64) -> CMTime {
+        CMTime { epoch, ..time }
+    }
+
+    #[test]
+    fn adds_in_the_common_timescale() {
+        let sum = CMTime::new(1, 30) + CMTime::new(1, 25);
+        assert_eq!(sum.value(), 11);
+        assert_eq!(sum.timescale(), 150);
+        assert!(!sum.has_been_rounded());
+        assert_eq!(CMTime::new(1, 3) - CMTime::new(1, 2), CMTime::new(-1, 6));
+    }
+
+    #[test]
+    fn rounds_sums_without_a_common_timescale() {
+        let sum = CMTime::new(1, i32::MAX) + CMTime::new(1, i32::MAX - 1);
+        assert_eq!(sum.timescale(), i32::MAX);
+        assert_eq!(sum.value(), 2);
+        assert!(sum.has_been_rounded());
+    }
+
+    #[test]
+    fn overflowing_arithmetic_saturates_to_infinity() {
+        let max = CMTime::new(i64::MAX, 1);
+        assert_eq!(max + CMTime::new(1, 1), CMTime::POSITIVE_INFINITY);
+        assert_eq!(-max - CMTime::new(2, 1), CMTime::NEGATIVE_INFINITY);
+        assert_eq!(max * 2, CMTime::POSITIVE_INFINITY);
+        assert_eq!(max * -2, CMTime::NEGATIVE_INFINITY);
+        assert_eq!(-CMTime::new(i64::MIN, 1), CMTime::POSITIVE_INFINITY);
+    }
+
+    #[test]
+    #[allow(clippy::erasing_op)]
+    fn non_numeric_arithmetic() {
+        let one = CMTime::new(1, 1);
+        assert!(!(CMTime::INVALID + one).is_valid());
+        assert!((CMTime::INDEFINITE + one).is_indefinite());
+        assert!((CMTime::POSITIVE_INFINITY + one).is_positive_infinity());
+        assert!(!(CMTime::POSITIVE_INFINITY + CMTime::NEGATIVE_INFINITY).is_valid());
+        assert!((CMTime::POSITIVE_INFINITY * -1).is_negative_infinity());
+        assert!(!(CMTime::POSITIVE_INFINITY * 0).is_valid());
+        assert!((CMTime::INDEFINITE * -1).is_indefinite());
+    }
+
+    #[test]
+    fn converts_scale_rounding_half_away_from_zero() {
+        let time = CMTime::new(1, 3).convert_scale(1000);
+        assert_eq!((time.value(), time.timescale()), (333, 1000));
+        assert!(time.has_been_rounded());
+        assert_eq!(CMTime::new(-1, 2).convert_scale(1).value(), -1);
+        assert_eq!(CMTime::new(1, 2).convert_scale(1).value(), 1);
+        assert!(!CMTime::new(1, 2).convert_scale(10).has_been_rounded());
+        assert!(!CMTime::new(1, 1).convert_scale(0).is_valid());
+        assert!(CMTime::INDEFINITE.convert_scale(10).is_indefinite());
+        assert!(CMTime::new(i64::MAX, 1)
+            .convert_scale(10)
+            .is_positive_infinity());
+    }
+
+    #[test]
+    fn converts_durations() {
+        assert_eq!(CMTime::from(Duration::from_millis(1500)), CMTime::new(3, 2));
+        assert_eq!(
+            CMTime::new(1, 3).to_duration(),
+            Some(Duration::from_nanos(333_333_333))
+        );
+        assert_eq!(CMTime::new(-1, 3).to_duration(), None);
+        assert_eq!(CMTime::POSITIVE_INFINITY.to_duration(), None);
+        let long = CMTime::from(Duration::new(10_000_000_000, 1));
+        assert_eq!(long.timescale(), 1_000_000);
+        assert!(long.has_been_rounded());
+        assert!(CMTime::from(Duration::MAX).is_positive_infinity());
+        assert!(Duration::try_from(CMTime::INVALID).is_err());
+    }
+
+    #[test]
+    fn compares_across_timescales() {
+        assert_eq!(CMTime::new(1, 2), CMTime::new(500, 1000));
+        assert!(CMTime::new(1, 3) < CMTime::new(334, 1000));
+        assert!(CMTime::new(-1, 1) < CMTime::ZERO);
+    }
+
+    #[test]
+    fn orders_non_numeric_times_like_cmtimecompare() {
+        let mut times = [
+            CMTime::INVALID,
+            CMTime::POSITIVE_INFINITY,
+            CMTime::INDEFINITE,
+            CMTime::new(i64::MAX, 1),
+            CMTime::new(i64::MIN, 1),
+            CMTime::NEGATIVE_INFINITY,
+        ];
+        times.sort();
+        assert!(times[0].is_negative_infinity());
+        assert_eq!(times[1].value(), i64::MIN);
+        assert_eq!(times[2].value(), i64::MAX);
+        assert!(times[3].is_indefinite());
+        assert!(times[4].is_positive_infinity());
+        assert!(!times[5].is_valid());
+        assert_eq!(CMTime::INVALID, CMTime::INVALID);
+    }
+
+    #[test]
+    fn compares_epochs_before_values() {
+        let early = CMTime::new(10, 1);
+        let late = in_epoch(CMTime::new(1, 1), 1);
+        assert!(early < late);
+        assert_ne!(CMTime::new(1, 1), in_epoch(CMTime::new(1, 1), 1));
+        assert_eq!(
+            in_epoch(CMTime::new(1, 2), 3),
+            in_epoch(CMTime::new(2, 4), 3)
+        );
+        // Epochs don't apply to non-numeric times.
+        assert_eq!(
+            CMTime::POSITIVE_INFINITY,
+            in_epoch(CMTime::POSITIVE_INFINITY, 1)
+        );
+    }
+}
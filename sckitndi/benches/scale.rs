@@ -0,0 +1,93 @@
+//! Times the scaler on common capture and output sizes.
+//!
+//! Run with `cargo bench --bench scale`, on any platform. The app is a binary,
+//! so the modules the scaler needs are compiled in directly, along with code
+//! and unit tests the bench doesn't use.
+
+#![allow(dead_code, unused_imports)]
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[path = "../src/frame.rs"]
+mod frame;
+#[path = "../src/geometry.rs"]
+mod geometry;
+#[path = "../src/pool.rs"]
+mod pool;
+#[path = "../src/scale.rs"]
+mod scale;
+
+/// The pool counts the buffers in use on a gauge; the bench doesn't need the
+/// metrics registry behind it.
+mod metrics {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    #[derive(Default)]
+    pub struct Gauge(AtomicI64);
+
+    impl Gauge {
+        pub fn add(&self, delta: i64) {
+            self.0.fetch_add(delta, Ordering::Relaxed);
+        }
+    }
+}
+
+use frame::{Frame, PixelFormat};
+use geometry::Size;
+use metrics::Gauge;
+use pool::FramePool;
+use scale::{Filter, Fit, Scaler};
+
+/// How long each case is timed for, after one untimed run.
+const DURATION: Duration = Duration::from_secs(1);
+
+const CASES: [(Size, Size); 4] = [
+    (Size::new(3840, 2160), Size::new(1920, 1080)),
+    (Size::new(2880, 1800), Size::new(1920, 1080)),
+    (Size::new(1920, 1080), Size::new(1280, 720)),
+    (Size::new(1280, 720), Size::new(1920, 1080)),
+];
+
+/// A frame with detail in every channel, so that no filter gets an easy
+/// flat picture.
+fn source(format: PixelFormat, size: Size) -> Frame {
+    let mut frame = Frame::black(format, size.width, size.height, None);
+    for (i, sample) in frame.data_mut().iter_mut().enumerate() {
+        *sample = ((i * 31) ^ (i >> 7)) as u8;
+    }
+    frame
+}
+
+fn main() {
+    let pool = FramePool::new(2, Arc::new(Gauge::default()));
+    for format in [PixelFormat::Bgra, PixelFormat::Uyvy] {
+        for (from, to) in CASES {
+            let frame = source(format, from);
+            for filter in [Filter::Bilinear, Filter::Bicubic, Filter::Area] {
+                let scaler = Scaler::new(to, filter, Fit::Letterbox);
+                black_box(scaler.scale(&frame, Some(&pool)));
+                let (started, mut frames) = (Instant::now(), 0u32);
+                while started.elapsed() < DURATION {
+                    black_box(scaler.scale(black_box(&frame), Some(&pool)));
+                    frames += 1;
+                }
+                let per_frame = started.elapsed() / frames;
+                println!(
+                    "{:?} {}x{} to {}x{} {:?}: {:.2} ms ({:.0} fps)",
+                    format,
+                    from.width,
+                    from.height,
+                    to.width,
+                    to.height,
+                    filter,
+                    per_frame.as_secs_f64() * 1e3,
+                    1. / per_frame.as_secs_f64(),
+                );
+            }
+        }
+    }
+}
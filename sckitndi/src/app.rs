@@ -0,0 +1,489 @@
+use std::{sync::Arc, time::Duration};
+
+use cacao::{
+    appkit::{
+        window::{Window, WindowConfig},
+        App, AppDelegate,
+    },
+    button::Button,
+    color::Color,
+    control::Control,
+    layout::{Layout, LayoutConstraint},
+    notification_center::Dispatcher,
+    select::Select,
+    text::Label,
+    view::{View, ViewDelegate},
+};
+use cocoa_foundation::foundation::NSInteger;
+use tracing::{error, info};
+
+use crate::{
+    command::Command,
+    config::Config,
+    content::{self, ContentListing},
+    control::Controller,
+    grabber::Grabber,
+    hotplug::DisplayWatcher,
+    http::HttpServer,
+    logging,
+    osc::OscServer,
+    pacer::PacerStats,
+    permission::{self, Permission},
+    remote::RemoteControl,
+    status::Status,
+    status_item::StatusItem,
+    tally::Tally,
+    watchdog::Restart,
+    ws::EventServer,
+};
+
+struct SCKitNDI {
+    window: Window,
+    content: View<GrabberView>,
+    grabber: Arc<Grabber>,
+    status_item: StatusItem,
+    _remote_control: RemoteControl,
+    _display_watcher: Option<DisplayWatcher>,
+}
+
+impl SCKitNDI {
+    fn refresh_start_button(&self) {
+        let running = self.grabber.status().running;
+        self.content
+            .delegate
+            .as_ref()
+            .unwrap()
+            .start
+            .set_enabled(!running);
+    }
+
+    fn refresh_scene_select(&self) {
+        let view = self.content.delegate.as_ref().unwrap();
+        let scene = self.grabber.status().scene;
+        let index = scene
+            .and_then(|scene| view.scenes.iter().position(|name| *name == scene))
+            .map_or(-1, |index| index as NSInteger);
+        view.scene.set_selected_index(index);
+    }
+}
+
+/// Serves the control interfaces; commands go through the main thread just
+/// like the UI's.
+struct AppController {
+    config: Config,
+    grabber: Arc<Grabber>,
+}
+
+impl Controller for AppController {
+    fn dispatch(&self, command: Command) {
+        Action::Command(command).dispatch_main();
+    }
+
+    fn config(&self) -> Config {
+        self.config.clone()
+    }
+
+    fn status(&self) -> Status {
+        self.grabber.status()
+    }
+
+    fn stats(&self) -> PacerStats {
+        self.grabber.stats()
+    }
+
+    fn tally(&self) -> Tally {
+        self.grabber.tally().get()
+    }
+
+    fn content(&self) -> anyhow::Result<ContentListing> {
+        content::fetch(Duration::from_secs(5))
+    }
+
+    fn metrics(&self) -> String {
+        self.grabber.metrics().registry.render()
+    }
+}
+
+impl AppDelegate for SCKitNDI {
+    fn did_finish_launching(&self) {
+        self.window.show();
+        self.grabber.tally().subscribe(|tally| {
+            Action::TallyChanged(*tally).dispatch_main();
+        });
+        Action::TallyChanged(self.grabber.tally().get()).dispatch_main();
+        self.grabber.permission().subscribe(|permission| {
+            Action::PermissionChanged(*permission).dispatch_main();
+        });
+        Action::PermissionChanged(self.grabber.permission().get()).dispatch_main();
+        std::thread::spawn(|| loop {
+            std::thread::sleep(Duration::from_secs(1));
+            Action::RefreshStats.dispatch_main();
+        });
+    }
+}
+
+#[derive(Debug)]
+enum Action {
+    GetShareableContent,
+    RefreshStats,
+    TallyChanged(Tally),
+    PermissionChanged(Permission),
+    OpenPermissionSettings,
+    DisplaysChanged,
+    SceneSelected,
+    Restart(Restart),
+    Command(Command),
+}
+
+impl Action {
+    pub fn dispatch_main(self) {
+        App::<SCKitNDI, Self>::dispatch_main(self);
+    }
+}
+
+impl Dispatcher for SCKitNDI {
+    type Message = Action;
+
+    fn on_ui_message(&self, message: Self::Message) {
+        match message {
+            Action::GetShareableContent => {
+                let events = self.grabber.events();
+                sckit::ShareableContent::get(move |ret| {
+                    let shareable_content = match ret {
+                        Ok(shareable_content) => shareable_content,
+                        Err(err) => {
+                            events.error(format!("{:#}", err));
+                            return;
+                        }
+                    };
+                    let windows = shareable_content.windows();
+                    for w in &windows {
+                        let app = w.owning_application();
+                        let bundle_id = app
+                            .bundle_identifier()
+                            .unwrap_or_else(|| "UNKNOWN".to_string());
+                        let app_name = app
+                            .application_name()
+                            .unwrap_or_else(|| "UNKNOWN".to_string());
+                        info!(
+                            "[{}]{} *{}: #{} {}",
+                            bundle_id,
+                            app_name,
+                            app.process_id(),
+                            w.window_id(),
+                            w.title()
+                        );
+                    }
+                    let apps = shareable_content.applications();
+                    for app in &apps {
+                        let bundle_id = app
+                            .bundle_identifier()
+                            .unwrap_or_else(|| "UNKNOWN".to_string());
+                        let app_name = app
+                            .application_name()
+                            .unwrap_or_else(|| "UNKNOWN".to_string());
+                        info!("[{}]{} *{}", bundle_id, app_name, app.process_id());
+                    }
+                });
+            }
+            Action::RefreshStats => {
+                self.refresh_start_button();
+                self.refresh_scene_select();
+                self.grabber.refresh_stats();
+                let stats = self.grabber.stats();
+                let metrics = self.grabber.metrics();
+                let millis = |mean: Option<f64>| mean.map_or(0., |mean| mean * 1000.);
+                self.content
+                    .delegate
+                    .as_ref()
+                    .unwrap()
+                    .stats
+                    .set_text(format!(
+                        "fresh: {} / repeated: {} / dropped: {}\nconvert: {:.2} ms / send: {:.2} ms\nbuffers: {}/{} / connections: {}",
+                        stats.fresh,
+                        stats.repeated,
+                        metrics.frames_dropped.get(),
+                        millis(metrics.conversion_time.mean()),
+                        millis(metrics.send_time.mean()),
+                        metrics.buffers_in_use.get(),
+                        metrics.buffers_capacity.get(),
+                        metrics.connections.get(),
+                    ));
+            }
+            Action::Command(command) => {
+                self.grabber.handle(command);
+                self.refresh_start_button();
+                self.refresh_scene_select();
+            }
+            Action::SceneSelected => {
+                let view = self.content.delegate.as_ref().unwrap();
+                if let Some(name) = view.scenes.get(view.scene.get_selected_index()) {
+                    self.grabber.handle(Command::SwitchScene(name.clone()));
+                }
+            }
+            Action::TallyChanged(tally) => {
+                self.window
+                    .set_title(&format!("ScreenCaptureKit2NDI [{}]", tally.label()));
+                let (color, light) = match (tally.on_program, tally.on_preview) {
+                    (true, _) => (Color::SystemRed, "🔴"),
+                    (false, true) => (Color::SystemGreen, "🟢"),
+                    (false, false) => (Color::Clear, "⚪️"),
+                };
+                self.content.set_background_color(color);
+                self.status_item
+                    .set_title(&format!("{} {}", light, tally.label()));
+            }
+            Action::PermissionChanged(permission) => {
+                let view = self.content.delegate.as_ref().unwrap();
+                let denied = permission == Permission::Denied;
+                view.permission
+                    .set_text(if denied { permission::GUIDANCE } else { "" });
+                view.permission.set_hidden(!denied);
+                view.open_settings.set_hidden(!denied);
+                if permission == Permission::Granted {
+                    self.grabber.resume_pending_start();
+                }
+                self.refresh_start_button();
+            }
+            Action::OpenPermissionSettings => {
+                if let Err(err) = permission::open_settings() {
+                    error!("{:#}", err);
+                }
+            }
+            Action::DisplaysChanged => self.grabber.displays_changed(),
+            Action::Restart(restart) => self.grabber.restart(restart),
+        }
+    }
+}
+
+struct GrabberView {
+    start: Button,
+    get_shareable_contents: Button,
+    freeze: Button,
+    slate: Button,
+    resume: Button,
+    scene: Select,
+    /// The scene names, in the order of the select's items.
+    scenes: Vec<String>,
+    stats: Label,
+    permission: Label,
+    open_settings: Button,
+}
+
+impl GrabberView {
+    fn new(scenes: Vec<String>) -> Self {
+        let mut start = Button::new("Start");
+        start.set_action(|| {
+            Action::Command(Command::Start).dispatch_main();
+        });
+        let mut get_shareable_contents = Button::new("Get Shareable Contents");
+        get_shareable_contents.set_action(|| {
+            Action::GetShareableContent.dispatch_main();
+        });
+
+        let mut freeze = Button::new("Freeze");
+        freeze.set_action(|| {
+            Action::Command(Command::Pause).dispatch_main();
+        });
+        let mut slate = Button::new("Slate");
+        slate.set_action(|| {
+            Action::Command(Command::ShowSlate).dispatch_main();
+        });
+        let mut resume = Button::new("Resume");
+        resume.set_action(|| {
+            Action::Command(Command::Resume).dispatch_main();
+        });
+
+        let mut scene = Select::new();
+        for name in &scenes {
+            scene.add_item(name);
+        }
+        scene.set_action(|| {
+            Action::SceneSelected.dispatch_main();
+        });
+        scene.set_hidden(scenes.is_empty());
+
+        let stats = Label::new();
+
+        let permission = Label::new();
+        permission.set_max_number_of_lines(0);
+        permission.set_hidden(true);
+        let mut open_settings = Button::new("Open System Settings");
+        open_settings.set_action(|| {
+            Action::OpenPermissionSettings.dispatch_main();
+        });
+        open_settings.set_hidden(true);
+
+        Self {
+            start,
+            get_shareable_contents,
+            freeze,
+            slate,
+            resume,
+            scene,
+            scenes,
+            stats,
+            permission,
+            open_settings,
+        }
+    }
+}
+
+impl ViewDelegate for GrabberView {
+    const NAME: &'static str = stringify!(GrabberView);
+
+    fn did_load(&mut self, view: View) {
+        view.add_subview(&self.start);
+        view.add_subview(&self.get_shareable_contents);
+        view.add_subview(&self.freeze);
+        view.add_subview(&self.slate);
+        view.add_subview(&self.resume);
+        view.add_subview(&self.scene);
+        view.add_subview(&self.stats);
+        view.add_subview(&self.permission);
+        view.add_subview(&self.open_settings);
+
+        LayoutConstraint::activate(&[
+            self.start.top.constraint_equal_to(&view.top).offset(36.),
+            self.get_shareable_contents
+                .top
+                .constraint_equal_to(&view.top)
+                .offset(72.),
+            self.freeze.top.constraint_equal_to(&view.top).offset(36.),
+            self.freeze
+                .leading
+                .constraint_equal_to(&self.start.trailing)
+                .offset(8.),
+            self.slate.top.constraint_equal_to(&view.top).offset(36.),
+            self.slate
+                .leading
+                .constraint_equal_to(&self.freeze.trailing)
+                .offset(8.),
+            self.resume.top.constraint_equal_to(&view.top).offset(36.),
+            self.resume
+                .leading
+                .constraint_equal_to(&self.slate.trailing)
+                .offset(8.),
+            self.scene.top.constraint_equal_to(&view.top).offset(72.),
+            self.scene
+                .trailing
+                .constraint_equal_to(&view.trailing)
+                .offset(-16.),
+            self.stats.top.constraint_equal_to(&view.top).offset(108.),
+            self.stats
+                .leading
+                .constraint_equal_to(&view.leading)
+                .offset(16.),
+            self.permission
+                .top
+                .constraint_equal_to(&view.top)
+                .offset(180.),
+            self.permission
+                .leading
+                .constraint_equal_to(&view.leading)
+                .offset(16.),
+            self.permission
+                .trailing
+                .constraint_equal_to(&view.trailing)
+                .offset(-16.),
+            self.open_settings
+                .top
+                .constraint_equal_to(&self.permission.bottom)
+                .offset(12.),
+        ]);
+    }
+}
+
+/// Runs the app until it quits.
+pub fn run() {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("invalid configuration: {:#}", err);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = logging::init(&config) {
+        eprintln!("failed to set up logging: {:#}", err);
+        std::process::exit(2);
+    }
+
+    let scenes = config
+        .scenes
+        .iter()
+        .map(|scene| scene.name.clone())
+        .collect();
+    let content = View::with(GrabberView::new(scenes));
+
+    let mut window_config = WindowConfig::default();
+    window_config.set_initial_dimensions(100., 100., 440., 400.);
+    let window = Window::new(window_config);
+    window.set_minimum_content_size(400., 400.);
+    window.set_title("ScreenCaptureKit2NDI");
+    window.set_content_view(&content);
+    let status_item = StatusItem::new();
+
+    let grabber = match Grabber::new(&config) {
+        Ok(grabber) => Arc::new(grabber),
+        Err(err) => {
+            error!("{:#}", err);
+            std::process::exit(1);
+        }
+    };
+    grabber.spawn_watchdog(|restart| Action::Restart(restart).dispatch_main());
+    grabber.spawn_redaction();
+    grabber.spawn_layers();
+    if let Some(scene) = &config.scene {
+        grabber.handle(Command::SwitchScene(scene.clone()));
+    }
+    let controller = Arc::new(AppController {
+        config: config.clone(),
+        grabber: grabber.clone(),
+    });
+    if let Some(addr) = config.http_addr {
+        match HttpServer::bind(addr, config.allowed_origins.clone(), controller.clone()) {
+            Ok(server) => info!("http: listening on {}", server.local_addr()),
+            Err(err) => error!("http: {:#}", err),
+        }
+    }
+    if let Some(addr) = config.osc_addr {
+        match OscServer::bind(addr, controller.clone()) {
+            Ok(server) => info!("osc: listening on {}", server.local_addr()),
+            Err(err) => error!("osc: {:#}", err),
+        }
+    }
+    if let Some(addr) = config.events_addr {
+        match EventServer::bind(
+            addr,
+            config.allowed_origins.clone(),
+            grabber.events(),
+            controller,
+        ) {
+            Ok(server) => info!("ws: listening on {}", server.local_addr()),
+            Err(err) => error!("ws: {:#}", err),
+        }
+    }
+    let remote_control = RemoteControl::new(grabber.sender(), |command| {
+        Action::Command(command).dispatch_main();
+    });
+    let display_watcher = match DisplayWatcher::new(|| Action::DisplaysChanged.dispatch_main()) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            error!("display watcher: {:#}", err);
+            None
+        }
+    };
+
+    App::new(
+        "com.koba789.sckitndi",
+        SCKitNDI {
+            window,
+            content,
+            grabber,
+            status_item,
+            _remote_control: remote_control,
+            _display_watcher: display_watcher,
+        },
+    )
+    .run();
+}
@@ -0,0 +1,167 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    geometry::Rect,
+    mask::Mask,
+    metadata::{self, Tag},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum Target {
+    Display(u32),
+    Window(u32),
+}
+
+impl Target {
+    pub fn id(&self) -> u32 {
+        match *self {
+            Target::Display(id) | Target::Window(id) => id,
+        }
+    }
+}
+
+/// Changes to the running capture, whether requested from the UI or remotely.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Start,
+    Stop,
+    SwitchDisplay(u32),
+    SwitchWindow(u32),
+    SetCrop(Option<Rect>),
+    SetMasks(Vec<Mask>),
+    /// Applies a configured scene by name.
+    SwitchScene(String),
+    /// Freezes the output on the last frame.
+    Pause,
+    /// Sends the slate instead of the capture.
+    ShowSlate,
+    /// Leaves both the freeze and the slate.
+    Resume,
+}
+
+impl Command {
+    /// Reads a command from a metadata tag, or `None` if the tag isn't part of
+    /// the command vocabulary.
+    ///
+    /// ```xml
+    /// <switch_display id="1"/>
+    /// <switch_window id="1234"/>
+    /// <set_crop x="0" y="0" width="1920" height="1080"/>
+    /// <clear_crop/>
+    /// <switch_scene name="Keynote"/>
+    /// <pause/>
+    /// <slate/>
+    /// <resume/>
+    /// ```
+    pub fn from_tag(tag: &Tag) -> Result<Option<Self>> {
+        let command = match tag.name.as_str() {
+            "switch_display" => Self::SwitchDisplay(tag.parse_attr("id")?),
+            "switch_window" => Self::SwitchWindow(tag.parse_attr("id")?),
+            "set_crop" => Self::SetCrop(Some(Rect::new(
+                tag.parse_attr("x")?,
+                tag.parse_attr("y")?,
+                tag.parse_attr("width")?,
+                tag.parse_attr("height")?,
+            ))),
+            "clear_crop" => Self::SetCrop(None),
+            "switch_scene" => Self::SwitchScene(tag.parse_attr("name")?),
+            "pause" => Self::Pause,
+            "slate" => Self::ShowSlate,
+            "resume" => Self::Resume,
+            _ => return Ok(None),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Parses every command in a metadata frame. Tags outside the vocabulary,
+/// such as the `ndi_capabilities` receivers send, are skipped.
+pub fn parse_commands(xml: &str) -> Result<Vec<Command>> {
+    let mut commands = Vec::new();
+    for tag in metadata::parse_tags(xml)? {
+        if let Some(command) = Command::from_tag(&tag)? {
+            commands.push(command);
+        }
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> Command {
+        let mut commands = parse_commands(xml).unwrap();
+        assert_eq!(commands.len(), 1, "{xml}");
+        commands.pop().unwrap()
+    }
+
+    #[test]
+    fn parses_each_command() {
+        assert_eq!(
+            parse(r#"<switch_display id="2"/>"#),
+            Command::SwitchDisplay(2)
+        );
+        assert_eq!(
+            parse(r#"<switch_window id="1234"/>"#),
+            Command::SwitchWindow(1234)
+        );
+        assert_eq!(
+            parse(r#"<set_crop x="10" y="20.5" width="1920" height="1080"/>"#),
+            Command::SetCrop(Some(Rect::new(10., 20.5, 1920., 1080.)))
+        );
+        assert_eq!(parse("<clear_crop/>"), Command::SetCrop(None));
+        assert_eq!(
+            parse(r#"<switch_scene name="Q&amp;A"/>"#),
+            Command::SwitchScene("Q&A".to_string())
+        );
+        assert_eq!(parse("<pause/>"), Command::Pause);
+        assert_eq!(parse("<slate/>"), Command::ShowSlate);
+        assert_eq!(parse("<resume/>"), Command::Resume);
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_attributes() {
+        for xml in [
+            "<switch_display/>",
+            r#"<switch_display id="main"/>"#,
+            r#"<switch_window id="-1"/>"#,
+            r#"<set_crop x="0" y="0" width="1920"/>"#,
+            r#"<set_crop x="0" y="0" width="wide" height="1080"/>"#,
+            "<switch_scene/>",
+        ] {
+            assert!(parse_commands(xml).is_err(), "{xml}");
+        }
+    }
+
+    #[test]
+    fn skips_tags_outside_the_vocabulary() {
+        let xml = r#"<ndi_capabilities ntk_ptz="true"/><ndi_tally_echo on_program="true"/>"#;
+        assert_eq!(parse_commands(xml).unwrap(), []);
+        assert_eq!(
+            parse_commands(r#"<ndi_capabilities web_control="x"/><pause/>"#).unwrap(),
+            [Command::Pause]
+        );
+    }
+
+    #[test]
+    fn parses_every_command_in_a_frame_in_order() {
+        let xml = r#"<commands><switch_display id="1"/><clear_crop/><slate/></commands>"#;
+        assert_eq!(
+            parse_commands(xml).unwrap(),
+            [
+                Command::SwitchDisplay(1),
+                Command::SetCrop(None),
+                Command::ShowSlate
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_the_whole_frame_if_a_command_is_invalid() {
+        assert!(parse_commands(r#"<pause/><switch_display id="x"/>"#).is_err());
+        assert!(parse_commands("<pause/><resume").is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::{
+    cursor::HighlightStyle,
+    frame::{FrameRate, PixelFormat},
+    geometry::{Rect, Size},
+    mask::Mask,
+    overlay::{self, OverlaySpec},
+    pip::{self, LayerSpec},
+    redact::TitlePattern,
+    rendition::RenditionSpec,
+    scale::{Filter, Fit},
+    scene::{self, SceneSpec},
+    slate::SlateSpec,
+    timing::TimecodeMode,
+    transition::Transition,
+};
+
+/// The region captured unless `SCKITNDI_CROP` says otherwise, which is what
+/// versions before source switching always captured.
+const DEFAULT_CROP: Rect = Rect::new(960., 540., 1920., 1080.);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub ndi_name: String,
+    pub frame_rate: FrameRate,
+    pub timecode_mode: TimecodeMode,
+    pub crop: Option<Rect>,
+    /// What frames are scaled to before sending, or `None` to send them at
+    /// the captured size.
+    pub output_size: Option<Size>,
+    pub scale_filter: Filter,
+    pub scale_fit: Fit,
+    pub pixel_format: PixelFormat,
+    /// Extra NDI outputs at other sizes or formats, scaled from the main one.
+    pub renditions: Vec<RenditionSpec>,
+    /// Regions of the output to hide, applied in order.
+    pub masks: Vec<Mask>,
+    /// Whether ScreenCaptureKit draws the cursor.
+    pub show_cursor: bool,
+    /// Drawn around the cursor, or `None` to leave it alone.
+    pub cursor_highlight: Option<HighlightStyle>,
+    /// Images, text and clocks burned into the output.
+    pub overlays: Vec<OverlaySpec>,
+    /// Captures drawn over the main one, picture-in-picture.
+    pub layers: Vec<LayerSpec>,
+    /// Windows to hide from display captures by title.
+    pub redact_titles: Vec<TitlePattern>,
+    /// Presets that can be switched between while capturing.
+    pub scenes: Vec<SceneSpec>,
+    /// The scene to start in, or `None` to start with the settings above.
+    pub scene: Option<String>,
+    /// How the output changes over when the capture target or crop changes.
+    pub transition: Transition,
+    /// Sent instead of the capture on request.
+    pub slate: SlateSpec,
+    /// What to capture when the selected display disappears.
+    pub fallback_display: Option<FallbackDisplay>,
+    /// How long the capture may go without frames before it is restarted, or
+    /// `None` to only restart after errors.
+    pub stall_timeout: Option<Duration>,
+    /// Address of the HTTP control API, or `None` to disable it.
+    pub http_addr: Option<SocketAddr>,
+    /// Web page origins, such as `http://localhost:3000`, that may use the
+    /// HTTP API and the event stream.
+    pub allowed_origins: Vec<String>,
+    /// Address of the OSC listener, or `None` to disable it.
+    pub osc_addr: Option<SocketAddr>,
+    /// Address of the WebSocket event stream, or `None` to disable it.
+    pub events_addr: Option<SocketAddr>,
+    /// `tracing` filter directives, e.g. `info,sckit=debug`.
+    pub log_filter: String,
+    pub log_outputs: Vec<LogOutput>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackDisplay {
+    First,
+    Id(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogOutput {
+    Stderr,
+    OsLog,
+    File(PathBuf),
+}
+
+impl FromStr for LogOutput {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stderr" => Ok(LogOutput::Stderr),
+            "oslog" => Ok(LogOutput::OsLog),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LogOutput::File(path.into())),
+                _ => Err(anyhow!(
+                    "Unknown log output {:?}; expected stderr, oslog or file:<path>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ndi_name: "sckitndi".to_string(),
+            frame_rate: FrameRate::default(),
+            timecode_mode: TimecodeMode::default(),
+            crop: Some(DEFAULT_CROP),
+            output_size: None,
+            scale_filter: Filter::default(),
+            scale_fit: Fit::default(),
+            pixel_format: PixelFormat::default(),
+            renditions: Vec::new(),
+            masks: Vec::new(),
+            show_cursor: true,
+            cursor_highlight: None,
+            overlays: Vec::new(),
+            layers: Vec::new(),
+            redact_titles: Vec::new(),
+            scenes: Vec::new(),
+            scene: None,
+            transition: Transition::default(),
+            slate: SlateSpec::default(),
+            fallback_display: Some(FallbackDisplay::First),
+            stall_timeout: None,
+            http_addr: Some(([127, 0, 0, 1], 8090).into()),
+            allowed_origins: Vec::new(),
+            osc_addr: Some(([127, 0, 0, 1], 9000).into()),
+            events_addr: Some(([127, 0, 0, 1], 8091).into()),
+            log_filter: "info".to_string(),
+            log_outputs: vec![LogOutput::Stderr],
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(name) = std::env::var("SCKITNDI_NAME") {
+            config.ndi_name = name;
+        }
+        if let Ok(fps) = std::env::var("SCKITNDI_FPS") {
+            let fps = fps.parse().context("Invalid SCKITNDI_FPS")?;
+            config.frame_rate = FrameRate::new(fps, 1).context("Invalid SCKITNDI_FPS")?;
+        }
+        if let Ok(mode) = std::env::var("SCKITNDI_TIMECODE") {
+            config.timecode_mode = mode.parse()?;
+        }
+        match std::env::var("SCKITNDI_CROP").as_deref() {
+            Ok("off") => config.crop = None,
+            Ok(crop) => config.crop = Some(parse_rect(crop).context("Invalid SCKITNDI_CROP")?),
+            Err(_) => {}
+        }
+        if let Ok(size) = std::env::var("SCKITNDI_OUTPUT_SIZE") {
+            let size: Size = size.parse().context("Invalid SCKITNDI_OUTPUT_SIZE")?;
+            if !size.width.is_multiple_of(2) {
+                return Err(anyhow!("Invalid SCKITNDI_OUTPUT_SIZE: width must be even"));
+            }
+            config.output_size = Some(size);
+        }
+        if let Ok(filter) = std::env::var("SCKITNDI_SCALE_FILTER") {
+            config.scale_filter = filter.parse().context("Invalid SCKITNDI_SCALE_FILTER")?;
+        }
+        if let Ok(fit) = std::env::var("SCKITNDI_SCALE_FIT") {
+            config.scale_fit = fit.parse().context("Invalid SCKITNDI_SCALE_FIT")?;
+        }
+        match std::env::var("SCKITNDI_PIXEL_FORMAT").as_deref() {
+            Ok("bgra") => config.pixel_format = PixelFormat::Bgra,
+            Ok("uyvy") => config.pixel_format = PixelFormat::Uyvy,
+            Ok(format) => {
+                return Err(anyhow!(
+                    "Unknown pixel format {:?}; expected bgra or uyvy",
+                    format
+                ))
+            }
+            Err(_) => {}
+        }
+        if let Ok(renditions) = std::env::var("SCKITNDI_RENDITIONS") {
+            config.renditions = renditions
+                .split(';')
+                .filter(|rendition| !rendition.trim().is_empty())
+                .map(|rendition| rendition.trim().parse())
+                .collect::<Result<_>>()
+                .context("Invalid SCKITNDI_RENDITIONS")?;
+            let mut names: Vec<&str> = config.renditions.iter().map(|r| r.name.as_str()).collect();
+            names.sort_unstable();
+            if let Some(name) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+                return Err(anyhow!(
+                    "Invalid SCKITNDI_RENDITIONS: {:?} is used twice",
+                    name[0]
+                ));
+            }
+        }
+        if let Ok(masks) = std::env::var("SCKITNDI_MASKS") {
+            config.masks = masks
+                .split(';')
+                .filter(|mask| !mask.trim().is_empty())
+                .map(|mask| mask.trim().parse())
+                .collect::<Result<_>>()
+                .context("Invalid SCKITNDI_MASKS")?;
+        }
+        match std::env::var("SCKITNDI_SHOW_CURSOR").as_deref() {
+            Ok("on") => config.show_cursor = true,
+            Ok("off") => config.show_cursor = false,
+            Ok(_) => return Err(anyhow!("Invalid SCKITNDI_SHOW_CURSOR; expected on or off")),
+            Err(_) => {}
+        }
+        match std::env::var("SCKITNDI_CURSOR_HIGHLIGHT").as_deref() {
+            Ok("off") => config.cursor_highlight = None,
+            Ok(style) => {
+                config.cursor_highlight =
+                    Some(style.parse().context("Invalid SCKITNDI_CURSOR_HIGHLIGHT")?)
+            }
+            Err(_) => {}
+        }
+        if let Ok(path) = std::env::var("SCKITNDI_OVERLAYS") {
+            config.overlays =
+                overlay::load_specs(path.as_ref()).context("Invalid SCKITNDI_OVERLAYS")?;
+        }
+        if let Ok(path) = std::env::var("SCKITNDI_PIP_LAYOUT") {
+            config.layers =
+                pip::load_layout(path.as_ref()).context("Invalid SCKITNDI_PIP_LAYOUT")?;
+        }
+        if let Ok(patterns) = std::env::var("SCKITNDI_REDACT_TITLES") {
+            config.redact_titles = patterns
+                .split(';')
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::parse)
+                .collect::<Result<_>>()
+                .context("Invalid SCKITNDI_REDACT_TITLES")?;
+        }
+        if let Ok(path) = std::env::var("SCKITNDI_SCENES") {
+            config.scenes = scene::load_scenes(path.as_ref()).context("Invalid SCKITNDI_SCENES")?;
+        }
+        if let Ok(name) = std::env::var("SCKITNDI_SCENE") {
+            if !config.scenes.iter().any(|scene| scene.name == name) {
+                return Err(anyhow!("Invalid SCKITNDI_SCENE: no scene named {:?}", name));
+            }
+            config.scene = Some(name);
+        }
+        if let Ok(kind) = std::env::var("SCKITNDI_TRANSITION") {
+            config.transition.kind = kind.parse()?;
+        }
+        if let Ok(secs) = std::env::var("SCKITNDI_TRANSITION_DURATION") {
+            let secs: f64 = secs
+                .parse()
+                .context("Invalid SCKITNDI_TRANSITION_DURATION")?;
+            config.transition.duration = Duration::try_from_secs_f64(secs)
+                .context("Invalid SCKITNDI_TRANSITION_DURATION")?;
+        }
+        if let Ok(color) = std::env::var("SCKITNDI_SLATE_COLOR") {
+            config.slate.color = color.parse().context("Invalid SCKITNDI_SLATE_COLOR")?;
+        }
+        if let Ok(text) = std::env::var("SCKITNDI_SLATE_TEXT") {
+            config.slate.text = text;
+        }
+        if let Ok(path) = std::env::var("SCKITNDI_SLATE_IMAGE") {
+            config.slate.image = Some(path.into());
+        }
+        match std::env::var("SCKITNDI_FALLBACK_DISPLAY").as_deref() {
+            Ok("off") => config.fallback_display = None,
+            Ok("first") => config.fallback_display = Some(FallbackDisplay::First),
+            Ok(id) => {
+                config.fallback_display = Some(FallbackDisplay::Id(
+                    id.parse().context("Invalid SCKITNDI_FALLBACK_DISPLAY")?,
+                ))
+            }
+            Err(_) => {}
+        }
+        match std::env::var("SCKITNDI_STALL_TIMEOUT").as_deref() {
+            Ok("off") => config.stall_timeout = None,
+            Ok(secs) => {
+                let secs: f64 = secs.parse().context("Invalid SCKITNDI_STALL_TIMEOUT")?;
+                config.stall_timeout = Some(
+                    Duration::try_from_secs_f64(secs).context("Invalid SCKITNDI_STALL_TIMEOUT")?,
+                );
+            }
+            Err(_) => {}
+        }
+        match std::env::var("SCKITNDI_HTTP").as_deref() {
+            Ok("off") => config.http_addr = None,
+            Ok(addr) => config.http_addr = Some(addr.parse().context("Invalid SCKITNDI_HTTP")?),
+            Err(_) => {}
+        }
+        if let Ok(origins) = std::env::var("SCKITNDI_ALLOWED_ORIGINS") {
+            config.allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        match std::env::var("SCKITNDI_OSC").as_deref() {
+            Ok("off") => config.osc_addr = None,
+            Ok(addr) => config.osc_addr = Some(addr.parse().context("Invalid SCKITNDI_OSC")?),
+            Err(_) => {}
+        }
+        match std::env::var("SCKITNDI_EVENTS").as_deref() {
+            Ok("off") => config.events_addr = None,
+            Ok(addr) => config.events_addr = Some(addr.parse().context("Invalid SCKITNDI_EVENTS")?),
+            Err(_) => {}
+        }
+        if let Ok(filter) = std::env::var("SCKITNDI_LOG") {
+            config.log_filter = filter;
+        }
+        if let Ok(outputs) = std::env::var("SCKITNDI_LOG_OUTPUT") {
+            config.log_outputs = outputs
+                .split(',')
+                .map(|output| output.trim().parse())
+                .collect::<Result<_>>()
+                .context("Invalid SCKITNDI_LOG_OUTPUT")?;
+        }
+        Ok(config)
+    }
+}
+
+/// Parses `x,y,width,height`.
+pub fn parse_rect(s: &str) -> Result<Rect> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [x, y, width, height] => Ok(Rect::new(x, y, width, height)),
+        _ => Err(anyhow::anyhow!("Expected x,y,width,height")),
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(target_os = "macos")]
+use std::{sync::mpsc, time::Duration};
+
+#[cfg(target_os = "macos")]
+use anyhow::{anyhow, Result};
+#[cfg(target_os = "macos")]
+use sckit::{RunningApplication, ShareableContent};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DisplayInfo {
+    pub id: u32,
+    pub width: isize,
+    pub height: isize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplicationInfo {
+    pub process_id: u32,
+    pub bundle_id: Option<String>,
+    pub name: Option<String>,
+}
+
+#[cfg(target_os = "macos")]
+impl From<&RunningApplication> for ApplicationInfo {
+    fn from(app: &RunningApplication) -> Self {
+        Self {
+            process_id: app.process_id(),
+            bundle_id: app.bundle_identifier(),
+            name: app.application_name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub application: ApplicationInfo,
+}
+
+/// A serializable snapshot of `ShareableContent`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentListing {
+    pub displays: Vec<DisplayInfo>,
+    pub windows: Vec<WindowInfo>,
+    pub applications: Vec<ApplicationInfo>,
+}
+
+#[cfg(target_os = "macos")]
+impl From<&ShareableContent> for ContentListing {
+    fn from(shareable_content: &ShareableContent) -> Self {
+        let displays = shareable_content
+            .displays()
+            .iter()
+            .map(|d| DisplayInfo {
+                id: d.display_id(),
+                width: d.width(),
+                height: d.height(),
+            })
+            .collect();
+        let windows = shareable_content
+            .windows()
+            .iter()
+            .map(|w| WindowInfo {
+                id: w.window_id(),
+                title: w.title(),
+                application: (&w.owning_application()).into(),
+            })
+            .collect();
+        let applications = shareable_content
+            .applications()
+            .iter()
+            .map(ApplicationInfo::from)
+            .collect();
+        Self {
+            displays,
+            windows,
+            applications,
+        }
+    }
+}
+
+/// Fetches the shareable content, blocking the calling thread.
+#[cfg(target_os = "macos")]
+pub fn fetch(timeout: Duration) -> Result<ContentListing> {
+    let (tx, rx) = mpsc::channel();
+    ShareableContent::get(move |ret| {
+        let _ = tx.send(ret.map(|content| ContentListing::from(&content)));
+    });
+    rx.recv_timeout(timeout)
+        .map_err(|_| anyhow!("Timed out fetching shareable content"))?
+}
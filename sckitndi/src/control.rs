@@ -0,0 +1,108 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    command::Command, config::Config, content::ContentListing, pacer::PacerStats, status::Status,
+    tally::Tally,
+};
+
+/// Everything the control interfaces (HTTP, OSC, ...) need from the app.
+pub trait Controller: Send + Sync {
+    /// Queues a command for the grabber; it is applied asynchronously, exactly
+    /// as if it had come from the UI.
+    fn dispatch(&self, command: Command);
+    fn config(&self) -> Config;
+    fn status(&self) -> Status;
+    fn stats(&self) -> PacerStats;
+    fn tally(&self) -> Tally;
+    fn content(&self) -> Result<ContentListing>;
+    /// All metrics in the Prometheus text format.
+    fn metrics(&self) -> String;
+}
+
+#[derive(Debug, Serialize)]
+pub struct Accepted {
+    pub accepted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// A controller for testing the control interfaces: it records the commands
+/// it is sent and reports a running capture on program, with one display and
+/// one window to share.
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        content::{ApplicationInfo, DisplayInfo, WindowInfo},
+        permission::Permission,
+        status::Source,
+    };
+
+    #[derive(Default)]
+    pub struct FakeController {
+        pub dispatched: Mutex<Vec<Command>>,
+    }
+
+    impl Controller for FakeController {
+        fn dispatch(&self, command: Command) {
+            self.dispatched.lock().unwrap().push(command);
+        }
+
+        fn config(&self) -> Config {
+            Config::default()
+        }
+
+        fn status(&self) -> Status {
+            Status {
+                running: true,
+                paused: false,
+                slate: false,
+                source: Source::default(),
+                scene: None,
+                permission: Permission::Granted,
+            }
+        }
+
+        fn stats(&self) -> PacerStats {
+            PacerStats::default()
+        }
+
+        fn tally(&self) -> Tally {
+            Tally {
+                on_program: true,
+                on_preview: false,
+            }
+        }
+
+        fn content(&self) -> Result<ContentListing> {
+            let application = ApplicationInfo {
+                process_id: 42,
+                bundle_id: Some("com.apple.Keynote".to_string()),
+                name: Some("Keynote".to_string()),
+            };
+            Ok(ContentListing {
+                displays: vec![DisplayInfo {
+                    id: 1,
+                    width: 1920,
+                    height: 1080,
+                }],
+                windows: vec![WindowInfo {
+                    id: 1234,
+                    title: "Slides".to_string(),
+                    application: application.clone(),
+                }],
+                applications: vec![application],
+            })
+        }
+
+        fn metrics(&self) -> String {
+            String::new()
+        }
+    }
+}
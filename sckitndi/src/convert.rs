@@ -0,0 +1,152 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{
+    frame::{Frame, PixelFormat},
+    pacer::VideoSink,
+    pool::FramePool,
+};
+
+/// BT.709 video range coefficients in 1/65536ths: luma scaled to 16-235 and
+/// chroma to 16-240.
+const Y_R: i32 = 11966;
+const Y_G: i32 = 40254;
+const Y_B: i32 = 4064;
+const CB_R: i32 = -6596;
+const CB_G: i32 = -22189;
+const CB_B: i32 = 28784;
+const CR_R: i32 = 28784;
+const CR_G: i32 = -26145;
+const CR_B: i32 = -2639;
+
+fn luma(b: i32, g: i32, r: i32) -> u8 {
+    (16 + ((Y_R * r + Y_G * g + Y_B * b + 0x8000) >> 16)) as u8
+}
+
+/// Converts a BGRA frame to UYVY, averaging the chroma of each pixel pair.
+/// An odd last column is dropped.
+pub fn bgra_to_uyvy(frame: &Frame, pool: Option<&FramePool>) -> Frame {
+    debug_assert_eq!(frame.format(), PixelFormat::Bgra);
+    let (width, height) = (frame.width() & !1, frame.height());
+    let mut out = Frame::black(PixelFormat::Uyvy, width.max(2), height, pool);
+    out.set_timestamp(frame.timestamp());
+    let (src_stride, dst_stride) = (frame.stride(), out.stride());
+    let src = frame.data();
+    let dst = out.data_mut();
+    for y in 0..height {
+        let src_row = &src[y * src_stride..][..width * 4];
+        let dst_row = &mut dst[y * dst_stride..][..width * 2];
+        for (bgra, uyvy) in src_row.chunks_exact(8).zip(dst_row.chunks_exact_mut(4)) {
+            let [b0, g0, r0, _, b1, g1, r1, _] = <[u8; 8]>::try_from(bgra).unwrap().map(i32::from);
+            let (b, g, r) = (b0 + b1, g0 + g1, r0 + r1);
+            // The sums are of two pixels, hence the extra bit of shift.
+            let cb = 128 + ((CB_R * r + CB_G * g + CB_B * b + 0x10000) >> 17);
+            let cr = 128 + ((CR_R * r + CR_G * g + CR_B * b + 0x10000) >> 17);
+            uyvy.copy_from_slice(&[cb as u8, luma(b0, g0, r0), cr as u8, luma(b1, g1, r1)]);
+        }
+    }
+    out
+}
+
+/// Converts outgoing BGRA frames to UYVY, which halves the bandwidth NDI
+/// needs to compress.
+pub struct UyvySink {
+    pool: FramePool,
+    /// While unset, frames pass through in whatever format they are in.
+    enabled: AtomicBool,
+    inner: Arc<dyn VideoSink>,
+}
+
+impl UyvySink {
+    pub fn new(pool: FramePool, enabled: bool, inner: Arc<dyn VideoSink>) -> Self {
+        Self {
+            pool,
+            enabled: AtomicBool::new(enabled),
+            inner,
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+impl VideoSink for UyvySink {
+    fn send_video(&self, frame: Arc<Frame>) {
+        let frame = match frame.format() {
+            PixelFormat::Bgra if self.enabled.load(Ordering::Relaxed) => {
+                Arc::new(bgra_to_uyvy(&frame, Some(&self.pool)))
+            }
+            _ => frame,
+        };
+        self.inner.send_video(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{metrics::Gauge, pacer::testing::Recorder};
+
+    fn bgra(pixels: &[[u8; 4]]) -> Frame {
+        let data = pixels.concat();
+        Frame::with_buffer(pixels.len(), 1, pixels.len() * 4, data, None)
+    }
+
+    #[test]
+    fn converts_to_video_range() {
+        let white = [0xff; 4];
+        let black = [0, 0, 0, 0xff];
+        assert_eq!(
+            bgra_to_uyvy(&bgra(&[white, white]), None).data(),
+            [128, 235, 128, 235]
+        );
+        assert_eq!(
+            bgra_to_uyvy(&bgra(&[black, black]), None).data(),
+            [128, 16, 128, 16]
+        );
+    }
+
+    #[test]
+    fn converts_primaries_with_bt709_coefficients() {
+        let uyvy = |pixel: [u8; 4]| bgra_to_uyvy(&bgra(&[pixel, pixel]), None).data().to_vec();
+        assert_eq!(uyvy([0, 0, 0xff, 0xff]), [102, 63, 240, 63]);
+        assert_eq!(uyvy([0, 0xff, 0, 0xff]), [42, 173, 26, 173]);
+        assert_eq!(uyvy([0xff, 0, 0, 0xff]), [240, 32, 118, 32]);
+    }
+
+    #[test]
+    fn averages_chroma_per_pair() {
+        let red = [0, 0, 0xff, 0xff];
+        let blue = [0xff, 0, 0, 0xff];
+        let out = bgra_to_uyvy(&bgra(&[red, blue]), None);
+        // Luma stays per pixel; chroma is that of the pair's average.
+        assert_eq!(out.data(), [171, 63, 179, 32]);
+    }
+
+    #[test]
+    fn drops_an_odd_last_column() {
+        let gray = [0x80, 0x80, 0x80, 0xff];
+        let mut frame = bgra(&[gray, gray, [0xff; 4]]);
+        frame.set_timestamp(Some(7));
+        let out = bgra_to_uyvy(&frame, None);
+        assert_eq!((out.width(), out.height(), out.stride()), (2, 1, 4));
+        assert_eq!(out.data(), [128, 126, 128, 126]);
+        assert_eq!(out.timestamp(), Some(7));
+    }
+
+    #[test]
+    fn converts_only_while_enabled() {
+        let recorder = Arc::new(Recorder::default());
+        let in_use = Arc::new(Gauge::default());
+        let sink = UyvySink::new(FramePool::new(1, in_use), false, recorder.clone());
+        sink.send_video(Arc::new(bgra(&[[0; 4]; 2])));
+        sink.set_enabled(true);
+        sink.send_video(Arc::new(bgra(&[[0; 4]; 2])));
+        let formats: Vec<_> = recorder.frames().iter().map(|f| f.format()).collect();
+        assert_eq!(formats, [PixelFormat::Bgra, PixelFormat::Uyvy]);
+    }
+}
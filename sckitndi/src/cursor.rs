@@ -0,0 +1,418 @@
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+#[cfg(target_os = "macos")]
+use core_graphics_types::geometry::CGPoint;
+use serde::Serialize;
+
+use crate::{
+    frame::{CursorState, Frame},
+    geometry::OutputMapping,
+    mask::Color,
+};
+
+#[cfg(target_os = "macos")]
+type CGEventRef = *mut c_void;
+#[cfg(target_os = "macos")]
+type CGEventSourceStateID = i32;
+#[cfg(target_os = "macos")]
+type CGMouseButton = u32;
+
+#[cfg(target_os = "macos")]
+const K_CG_EVENT_SOURCE_STATE_COMBINED_SESSION_STATE: CGEventSourceStateID = 0;
+#[cfg(target_os = "macos")]
+const K_CG_MOUSE_BUTTON_LEFT: CGMouseButton = 0;
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventCreate(source: *const c_void) -> CGEventRef;
+    fn CGEventGetLocation(event: CGEventRef) -> CGPoint;
+    fn CGEventSourceButtonState(state: CGEventSourceStateID, button: CGMouseButton) -> bool;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+/// How long a click ripple takes to expand and fade out.
+const RIPPLE_DURATION: Duration = Duration::from_millis(500);
+/// How far a ripple grows, relative to the highlight radius.
+const RIPPLE_GROWTH: f64 = 1.5;
+
+pub trait CursorSource: Send + Sync {
+    fn cursor(&self) -> Option<CursorState>;
+}
+
+/// Reads the cursor from the window server.
+#[cfg(target_os = "macos")]
+pub struct SystemCursor;
+
+#[cfg(target_os = "macos")]
+impl CursorSource for SystemCursor {
+    fn cursor(&self) -> Option<CursorState> {
+        unsafe {
+            let event = CGEventCreate(std::ptr::null());
+            if event.is_null() {
+                return None;
+            }
+            let location = CGEventGetLocation(event);
+            CFRelease(event);
+            Some(CursorState {
+                position: (location.x, location.y),
+                pressed: CGEventSourceButtonState(
+                    K_CG_EVENT_SOURCE_STATE_COMBINED_SESSION_STATE,
+                    K_CG_MOUSE_BUTTON_LEFT,
+                ),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HighlightStyle {
+    /// In output pixels.
+    pub radius: f64,
+    pub color: Color,
+    pub opacity: f32,
+    /// Whether left clicks send out a ring.
+    pub ripple: bool,
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        Self {
+            radius: 24.,
+            color: Color {
+                r: 0xff,
+                g: 0xd4,
+                b: 0x00,
+            },
+            opacity: 0.35,
+            ripple: true,
+        }
+    }
+}
+
+impl FromStr for HighlightStyle {
+    type Err = anyhow::Error;
+
+    /// Parses `on` for the defaults, or comma-separated overrides such as
+    /// `radius=32,color=#ff0000,opacity=0.5,ripple=off`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut style = Self::default();
+        if s == "on" {
+            return Ok(style);
+        }
+        for option in s.split(',') {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected key=value, got {:?}", option))?;
+            let context = || format!("Invalid cursor highlight {}", key);
+            match key.trim() {
+                "radius" => style.radius = value.parse().with_context(context)?,
+                "color" => style.color = value.parse().with_context(context)?,
+                "opacity" => style.opacity = value.parse().with_context(context)?,
+                "ripple" => {
+                    style.ripple = match value {
+                        "on" => true,
+                        "off" => false,
+                        _ => bail!("Expected ripple=on or ripple=off"),
+                    }
+                }
+                _ => bail!("Unknown cursor highlight option {:?}", key),
+            }
+        }
+        if style.radius <= 0. {
+            bail!("Cursor highlight radius must be positive");
+        }
+        Ok(style)
+    }
+}
+
+/// Radius and opacity of a ripple `elapsed` after its click, or `None` once
+/// it has faded out.
+pub fn ripple(style: &HighlightStyle, elapsed: Duration) -> Option<(f64, f32)> {
+    let t = elapsed.as_secs_f64() / RIPPLE_DURATION.as_secs_f64();
+    (t < 1.).then_some((
+        style.radius * (1. + RIPPLE_GROWTH * t),
+        style.opacity * (1. - t as f32),
+    ))
+}
+
+/// Blends `color` over every pixel whose centre lies between `inner` and
+/// `outer` from `center`, antialiasing both edges. An `inner` of zero draws a
+/// disc.
+pub fn draw_ring(
+    frame: &mut Frame,
+    center: (f64, f64),
+    inner: f64,
+    outer: f64,
+    color: Color,
+    opacity: f32,
+) {
+    let (width, height, stride) = (frame.width(), frame.height(), frame.stride());
+    let left = (center.0 - outer - 1.).floor().max(0.) as usize;
+    let top = (center.1 - outer - 1.).floor().max(0.) as usize;
+    let right = ((center.0 + outer + 1.).ceil().max(0.) as usize).min(width);
+    let bottom = ((center.1 + outer + 1.).ceil().max(0.) as usize).min(height);
+    let data = frame.data_mut();
+    for y in top..bottom {
+        for x in left..right {
+            let distance = (x as f64 + 0.5 - center.0).hypot(y as f64 + 0.5 - center.1);
+            let coverage = (outer + 0.5 - distance).clamp(0., 1.)
+                * if inner > 0. {
+                    (distance - inner + 0.5).clamp(0., 1.)
+                } else {
+                    1.
+                };
+            let alpha = (coverage as f32 * opacity).clamp(0., 1.);
+            if alpha <= 0. {
+                continue;
+            }
+            let pixel = &mut data[y * stride + x * 4..][..4];
+            let mix = |s: u8, d: u8| (s as f32 * alpha + d as f32 * (1. - alpha)).round() as u8;
+            pixel[0] = mix(color.b, pixel[0]);
+            pixel[1] = mix(color.g, pixel[1]);
+            pixel[2] = mix(color.r, pixel[2]);
+        }
+    }
+}
+
+struct Clicks {
+    was_pressed: bool,
+    /// When and where each visible ripple started, in desktop coordinates.
+    ripples: Vec<(Instant, (f64, f64))>,
+}
+
+/// Highlights the cursor where it was when each outgoing frame was captured
+/// and draws a ripple for each left click.
+///
+/// The button is sampled once per frame captured, so clicks shorter than a
+/// frame may be missed.
+pub struct CursorOverlay {
+    style: HighlightStyle,
+    mapping: Arc<Mutex<Option<OutputMapping>>>,
+    clicks: Mutex<Clicks>,
+}
+
+impl CursorOverlay {
+    /// `mapping` follows the capture's region as it is reconfigured.
+    pub fn new(style: HighlightStyle, mapping: Arc<Mutex<Option<OutputMapping>>>) -> Self {
+        Self {
+            style,
+            mapping,
+            clicks: Mutex::new(Clicks {
+                was_pressed: false,
+                ripples: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, now: Instant) {
+        let Some(mapping) = *self.mapping.lock().unwrap() else {
+            return;
+        };
+        let Some(cursor) = frame.cursor() else {
+            return;
+        };
+        let (width, height) = (frame.width(), frame.height());
+        let mut clicks = self.clicks.lock().unwrap();
+        if self.style.ripple && cursor.pressed && !clicks.was_pressed {
+            clicks.ripples.push((now, cursor.position));
+        }
+        clicks.was_pressed = cursor.pressed;
+        let style = self.style;
+        clicks.ripples.retain(|&(started, position)| {
+            let Some((radius, opacity)) = ripple(&style, now.saturating_duration_since(started))
+            else {
+                return false;
+            };
+            let center = mapping.map_point(position, width, height);
+            let thickness = (style.radius / 8.).max(2.);
+            draw_ring(
+                frame,
+                center,
+                radius - thickness,
+                radius,
+                style.color,
+                opacity,
+            );
+            true
+        });
+        let center = mapping.map_point(cursor.position, width, height);
+        draw_ring(frame, center, 0., style.radius, style.color, style.opacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{frame::PixelFormat, geometry::Rect};
+
+    const WHITE: Color = Color {
+        r: 0xff,
+        g: 0xff,
+        b: 0xff,
+    };
+
+    fn gray(frame: &Frame, x: usize, y: usize) -> u8 {
+        let pixel = &frame.data()[y * frame.stride() + x * 4..][..4];
+        assert_eq!(pixel[0], pixel[1]);
+        assert_eq!(pixel[1], pixel[2]);
+        // Rings leave the alpha alone.
+        assert_eq!(pixel[3], 0xff);
+        pixel[0]
+    }
+
+    fn black(size: usize) -> Frame {
+        Frame::black(PixelFormat::Bgra, size, size, None)
+    }
+
+    #[test]
+    fn draws_an_antialiased_disc() {
+        let mut frame = black(9);
+        draw_ring(&mut frame, (4.5, 4.5), 0., 2., WHITE, 1.);
+        assert_eq!(gray(&frame, 4, 4), 0xff);
+        assert_eq!(gray(&frame, 5, 4), 0xff);
+        // Half covered, two pixels from the centre.
+        assert_eq!(gray(&frame, 6, 4), 128);
+        assert_eq!(gray(&frame, 4, 2), 128);
+        assert_eq!(gray(&frame, 7, 4), 0);
+        assert_eq!(gray(&frame, 0, 0), 0);
+    }
+
+    #[test]
+    fn draws_a_ring_around_its_inner_radius() {
+        let mut frame = black(9);
+        draw_ring(&mut frame, (4.5, 4.5), 2., 3., WHITE, 1.);
+        assert_eq!(gray(&frame, 4, 4), 0);
+        assert_eq!(gray(&frame, 5, 4), 0);
+        assert_eq!(gray(&frame, 6, 4), 128);
+        assert_eq!(gray(&frame, 7, 4), 128);
+        assert_eq!(gray(&frame, 8, 4), 0);
+    }
+
+    #[test]
+    fn blends_a_ring_by_its_opacity() {
+        let mut frame = black(9);
+        draw_ring(&mut frame, (4.5, 4.5), 0., 2., WHITE, 0.5);
+        assert_eq!(gray(&frame, 4, 4), 128);
+        assert_eq!(gray(&frame, 6, 4), 64);
+    }
+
+    #[test]
+    fn clips_rings_to_the_frame() {
+        let mut frame = black(4);
+        draw_ring(&mut frame, (0.5, 0.5), 0., 1., WHITE, 1.);
+        assert_eq!(gray(&frame, 0, 0), 0xff);
+        assert_eq!(gray(&frame, 2, 2), 0);
+        for center in [(-10., -10.), (20., 2.), (2., 20.)] {
+            let mut frame = black(4);
+            draw_ring(&mut frame, center, 0., 5., WHITE, 1.);
+            assert_eq!(frame.data(), black(4).data());
+        }
+    }
+
+    #[test]
+    fn ripples_grow_and_fade() {
+        let style = HighlightStyle::default();
+        assert_eq!(ripple(&style, Duration::ZERO), Some((24., 0.35)));
+        assert_eq!(
+            ripple(&style, Duration::from_millis(250)),
+            Some((42., 0.175))
+        );
+        assert_eq!(ripple(&style, RIPPLE_DURATION), None);
+    }
+
+    #[test]
+    fn parses_highlight_styles() {
+        assert_eq!(
+            "on".parse::<HighlightStyle>().unwrap(),
+            HighlightStyle::default()
+        );
+        let style: HighlightStyle = "radius=32,color=#ff0000,ripple=off".parse().unwrap();
+        assert_eq!(style.radius, 32.);
+        assert_eq!(
+            style.color,
+            Color {
+                r: 0xff,
+                g: 0,
+                b: 0
+            }
+        );
+        assert!(!style.ripple);
+        assert!("radius=0".parse::<HighlightStyle>().is_err());
+        assert!("size=3".parse::<HighlightStyle>().is_err());
+        assert!("ripple=yes".parse::<HighlightStyle>().is_err());
+    }
+
+    fn overlay(mapping: Option<OutputMapping>) -> CursorOverlay {
+        let style = HighlightStyle {
+            radius: 1.,
+            color: WHITE,
+            opacity: 1.,
+            ripple: true,
+        };
+        CursorOverlay::new(style, Arc::new(Mutex::new(mapping)))
+    }
+
+    fn with_cursor(position: (f64, f64), pressed: bool) -> Frame {
+        let mut frame = black(8);
+        frame.set_cursor(Some(CursorState { position, pressed }));
+        frame
+    }
+
+    #[test]
+    fn highlights_the_cursor_of_the_frame() {
+        // The region is 4 points square on a desktop offset by 100 points.
+        let mapping = OutputMapping::new((100., 0.), Rect::from_size(4., 4.), None);
+        let overlay = overlay(Some(mapping));
+        let mut frame = with_cursor((102.25, 2.25), false);
+        overlay.draw(&mut frame, Instant::now());
+        assert_eq!(gray(&frame, 4, 4), 0xff);
+        assert_eq!(gray(&frame, 0, 0), 0);
+
+        let mut frame = black(8);
+        overlay.draw(&mut frame, Instant::now());
+        assert_eq!(frame.data(), black(8).data());
+    }
+
+    #[test]
+    fn needs_a_mapping() {
+        let mut frame = with_cursor((2., 2.), false);
+        overlay(None).draw(&mut frame, Instant::now());
+        assert_eq!(frame.data(), black(8).data());
+    }
+
+    #[test]
+    fn starts_a_ripple_per_click() {
+        let mapping = OutputMapping::new((0., 0.), Rect::from_size(8., 8.), None);
+        let overlay = overlay(Some(mapping));
+        let ripples = || overlay.clicks.lock().unwrap().ripples.len();
+        let start = Instant::now();
+        let draw = |pressed, at: Duration| {
+            overlay.draw(&mut with_cursor((4., 4.), pressed), start + at);
+        };
+        draw(true, Duration::ZERO);
+        assert_eq!(ripples(), 1);
+        draw(true, Duration::from_millis(100));
+        assert_eq!(ripples(), 1);
+        draw(false, Duration::from_millis(200));
+        draw(true, Duration::from_millis(300));
+        assert_eq!(ripples(), 2);
+        draw(true, RIPPLE_DURATION + Duration::from_millis(100));
+        assert_eq!(ripples(), 1);
+        draw(false, RIPPLE_DURATION * 2);
+        assert_eq!(ripples(), 0);
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::{
+    mpsc::{self, Receiver, SyncSender, TrySendError},
+    Mutex,
+};
+
+use serde::Serialize;
+
+use crate::{pacer::PacerStats, permission::Permission, status::Source, tally::Tally};
+
+/// How many events a subscriber may fall behind before it starts missing
+/// them.
+const SUBSCRIBER_BACKLOG: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    State {
+        running: bool,
+        paused: bool,
+        slate: bool,
+    },
+    Source {
+        source: Source,
+    },
+    /// A scene was applied; later commands may have changed it since.
+    Scene {
+        name: String,
+    },
+    Tally {
+        on_program: bool,
+        on_preview: bool,
+    },
+    Stats {
+        stats: PacerStats,
+    },
+    Permission {
+        permission: Permission,
+    },
+    /// The capture stalled or failed and is being restarted.
+    Recovering {
+        attempt: u32,
+        reason: String,
+        retry_in_secs: f64,
+    },
+    /// Frames are arriving again after `attempts` restarts.
+    Recovered {
+        attempts: u32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl From<Tally> for Event {
+    fn from(tally: Tally) -> Self {
+        Event::Tally {
+            on_program: tally.on_program,
+            on_preview: tally.on_preview,
+        }
+    }
+}
+
+/// Fans events out to any number of subscribers. A subscriber that doesn't
+/// keep up misses events instead of blocking the publisher.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<SyncSender<Event>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: Event) {
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            match subscriber.try_send(event.clone()) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    pub fn error(&self, message: impl ToString) {
+        let message = message.to_string();
+        tracing::error!("{}", message);
+        self.publish(Event::Error { message });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn to_json(event: &Event) -> serde_json::Value {
+        serde_json::to_value(event).unwrap()
+    }
+
+    #[test]
+    fn serializes_events_with_a_type_tag() {
+        assert_eq!(
+            to_json(&Event::State {
+                running: true,
+                paused: false,
+                slate: true,
+            }),
+            json!({"type": "state", "running": true, "paused": false, "slate": true})
+        );
+        assert_eq!(
+            to_json(&Event::Permission {
+                permission: Permission::Denied,
+            }),
+            json!({"type": "permission", "permission": "denied"})
+        );
+        assert_eq!(
+            to_json(&Event::Recovering {
+                attempt: 2,
+                reason: "stalled".to_string(),
+                retry_in_secs: 1.5,
+            }),
+            json!({"type": "recovering", "attempt": 2, "reason": "stalled", "retry_in_secs": 1.5})
+        );
+        assert_eq!(
+            to_json(&Event::Recovered { attempts: 3 }),
+            json!({"type": "recovered", "attempts": 3})
+        );
+        assert_eq!(
+            to_json(&Event::Scene {
+                name: "wide".to_string(),
+            }),
+            json!({"type": "scene", "name": "wide"})
+        );
+        assert_eq!(
+            to_json(&Event::Source {
+                source: Source::default(),
+            }),
+            json!({"type": "source", "source": {"target": null, "crop": null, "masks": []}})
+        );
+    }
+
+    #[test]
+    fn converts_tally() {
+        let tally = Tally {
+            on_program: true,
+            on_preview: false,
+        };
+        assert_eq!(
+            to_json(&tally.into()),
+            json!({"type": "tally", "on_program": true, "on_preview": false})
+        );
+    }
+
+    #[test]
+    fn delivers_events_to_every_subscriber() {
+        let bus = EventBus::new();
+        let (a, b) = (bus.subscribe(), bus.subscribe());
+        bus.publish(Event::Recovered { attempts: 1 });
+        bus.error("failed");
+        for subscriber in [a, b] {
+            assert_eq!(
+                subscriber.try_iter().collect::<Vec<_>>(),
+                [
+                    Event::Recovered { attempts: 1 },
+                    Event::Error {
+                        message: "failed".to_string(),
+                    },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn drops_events_for_subscribers_that_fall_behind() {
+        let bus = EventBus::new();
+        let slow = bus.subscribe();
+        for attempts in 0..SUBSCRIBER_BACKLOG as u32 + 10 {
+            bus.publish(Event::Recovered { attempts });
+        }
+        let received: Vec<_> = slow.try_iter().collect();
+        assert_eq!(received.len(), SUBSCRIBER_BACKLOG);
+        assert_eq!(received[0], Event::Recovered { attempts: 0 });
+
+        bus.publish(Event::Recovered { attempts: 100 });
+        assert_eq!(slow.try_recv().unwrap(), Event::Recovered { attempts: 100 });
+    }
+
+    #[test]
+    fn forgets_subscribers_that_went_away() {
+        let bus = EventBus::new();
+        drop(bus.subscribe());
+        let kept = bus.subscribe();
+        bus.publish(Event::Recovered { attempts: 1 });
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert!(kept.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{num::NonZeroU32, time::Duration};
+
+use anyhow::{bail, Result};
+use sckit::CMTime;
+use serde::{Deserialize, Serialize};
+
+use crate::pool::FramePool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FrameRate {
+    numerator: NonZeroU32,
+    denominator: NonZeroU32,
+}
+
+impl FrameRate {
+    /// Fails for a zero rate, which would make the pacer spin, and for
+    /// numerators too large for a `CMTime` timescale.
+    pub fn new(numerator: u32, denominator: u32) -> Result<Self> {
+        match (NonZeroU32::new(numerator), NonZeroU32::new(denominator)) {
+            (Some(n), Some(d)) if i32::try_from(numerator).is_ok() => Ok(Self {
+                numerator: n,
+                denominator: d,
+            }),
+            _ => bail!("Invalid frame rate {}/{}", numerator, denominator),
+        }
+    }
+
+    pub fn numerator(&self) -> u32 {
+        self.numerator.get()
+    }
+
+    pub fn denominator(&self) -> u32 {
+        self.denominator.get()
+    }
+
+    pub fn frame_duration(&self) -> CMTime {
+        CMTime::from_fps(self.numerator(), self.denominator())
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.frame_duration().to_duration().unwrap_or_default()
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self::new(30, 1).unwrap()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelFormat {
+    /// 8-bit blue, green, red and alpha; what ScreenCaptureKit delivers.
+    #[default]
+    Bgra,
+    /// 8-bit 4:2:2 YCbCr packed as `U0 Y0 V0 Y1`, BT.709 video range. Widths
+    /// are even.
+    Uyvy,
+}
+
+impl PixelFormat {
+    /// Bytes per pixel, averaged over a chroma pair for 4:2:2 formats.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Bgra => 4,
+            PixelFormat::Uyvy => 2,
+        }
+    }
+
+    /// The bytes of two black pixels.
+    fn black(&self) -> [u8; 8] {
+        match self {
+            PixelFormat::Bgra => [0, 0, 0, 0xff, 0, 0, 0, 0xff],
+            PixelFormat::Uyvy => [0x80, 0x10, 0x80, 0x10, 0x80, 0x10, 0x80, 0x10],
+        }
+    }
+}
+
+/// Where the cursor was when a frame was captured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CursorState {
+    /// In global display coordinates, in points.
+    pub position: (f64, f64),
+    pub pressed: bool,
+}
+
+/// An owned frame, detached from the `CVPixelBuffer` it was captured into.
+///
+/// Frames taken from a [`FramePool`] hand their buffer back to it on drop.
+pub struct Frame {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    stride: usize,
+    data: Vec<u8>,
+    pool: Option<FramePool>,
+    timestamp: Option<i64>,
+    cursor: Option<CursorState>,
+}
+
+impl Frame {
+    pub(crate) fn with_buffer(
+        width: usize,
+        height: usize,
+        stride: usize,
+        data: Vec<u8>,
+        pool: Option<FramePool>,
+    ) -> Self {
+        Self::with_format(PixelFormat::Bgra, width, height, stride, data, pool)
+    }
+
+    pub(crate) fn with_format(
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        stride: usize,
+        data: Vec<u8>,
+        pool: Option<FramePool>,
+    ) -> Self {
+        debug_assert!(data.len() >= stride * height);
+        debug_assert!(stride >= width * format.bytes_per_pixel());
+        Self {
+            format,
+            width,
+            height,
+            stride,
+            data,
+            pool,
+            timestamp: None,
+            cursor: None,
+        }
+    }
+
+    /// A black frame with tightly packed rows, taking its buffer from `pool`
+    /// if given.
+    pub fn black(
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        pool: Option<&FramePool>,
+    ) -> Self {
+        let stride = width * format.bytes_per_pixel();
+        let mut data = match pool {
+            Some(pool) => pool.take(stride * height),
+            None => vec![0; stride * height],
+        };
+        for pixels in data.chunks_mut(8) {
+            pixels.copy_from_slice(&format.black()[..pixels.len()]);
+        }
+        Self::with_format(format, width, height, stride, data, pool.cloned())
+    }
+
+    /// A BGRA frame filled with `bgra`.
+    #[cfg(test)]
+    pub fn solid(width: usize, height: usize, bgra: [u8; 4]) -> Self {
+        Self::with_buffer(width, height, width * 4, bgra.repeat(width * height), None)
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Capture time in NDI ticks (100ns), if known.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Option<i64>) {
+        self.timestamp = timestamp;
+    }
+
+    /// The cursor as it was when the frame was captured, if sampled.
+    pub fn cursor(&self) -> Option<CursorState> {
+        self.cursor
+    }
+
+    pub fn set_cursor(&mut self, cursor: Option<CursorState>) {
+        self.cursor = cursor;
+    }
+
+    /// A copy of the frame in a buffer taken from `pool`, or from the heap
+    /// without one.
+    pub fn copy_into(&self, pool: Option<&FramePool>) -> Self {
+        let data = match pool {
+            Some(pool) => {
+                let mut data = pool.take(self.data.len());
+                data.copy_from_slice(&self.data);
+                data
+            }
+            None => self.data.clone(),
+        };
+        let mut frame = Self::with_format(
+            self.format,
+            self.width,
+            self.height,
+            self.stride,
+            data,
+            pool.cloned(),
+        );
+        frame.timestamp = self.timestamp;
+        frame.cursor = self.cursor;
+        frame
+    }
+}
+
+impl Clone for Frame {
+    fn clone(&self) -> Self {
+        self.copy_into(self.pool.as_ref())
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.recycle(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_zero_rate() {
+        assert!(FrameRate::new(0, 1).is_err());
+        assert!(FrameRate::new(30, 0).is_err());
+        assert!(FrameRate::new(u32::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn intervals_of_integer_rates() {
+        let rate = FrameRate::new(30, 1).unwrap();
+        assert_eq!(rate.frame_duration(), CMTime::new(1, 30));
+        assert_eq!(rate.interval(), Duration::from_nanos(33_333_333));
+        assert_eq!(
+            FrameRate::new(50, 1).unwrap().interval(),
+            Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn intervals_of_fractional_rates() {
+        let rate = FrameRate::new(30000, 1001).unwrap();
+        assert_eq!(rate.frame_duration(), CMTime::new(1001, 30000));
+        assert_eq!(rate.interval(), Duration::from_nanos(33_366_667));
+        let rate = FrameRate::new(60000, 1001).unwrap();
+        assert_eq!(rate.interval(), Duration::from_nanos(16_683_333));
+    }
+
+    #[test]
+    fn black_frames() {
+        let frame = Frame::black(PixelFormat::Bgra, 3, 1, None);
+        assert_eq!(frame.stride(), 12);
+        assert_eq!(frame.data(), &[0, 0, 0, 255].repeat(3)[..]);
+        let frame = Frame::black(PixelFormat::Uyvy, 2, 2, None);
+        assert_eq!(frame.data(), &[0x80, 0x10, 0x80, 0x10].repeat(2)[..]);
+    }
+}
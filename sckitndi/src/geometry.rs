@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+#[cfg(target_os = "macos")]
+use core_graphics_types::geometry::{CGPoint, CGRect, CGSize};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub const fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub const fn from_size(width: f64, height: f64) -> Self {
+        Self::new(0., 0., width, height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0. || self.height <= 0.
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        let rect = Rect::new(x, y, right - x, bottom - y);
+        (!rect.is_empty()).then_some(rect)
+    }
+}
+
+/// A size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Size {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Size {
+    pub const fn new(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+}
+
+impl FromStr for Size {
+    type Err = anyhow::Error;
+
+    /// Parses `<width>x<height>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| anyhow!("Expected <width>x<height>, got {:?}", s))?;
+        let size = Size::new(
+            width.trim().parse().context("Invalid width")?,
+            height.trim().parse().context("Invalid height")?,
+        );
+        if size.width == 0 || size.height == 0 {
+            return Err(anyhow!("Size must not be empty"));
+        }
+        Ok(size)
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    /// Reads the same `<width>x<height>` form as [`FromStr`].
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let size = String::deserialize(deserializer)?;
+        size.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<Rect> for CGRect {
+    fn from(rect: Rect) -> Self {
+        CGRect::new(
+            &CGPoint::new(rect.x, rect.y),
+            &CGSize::new(rect.width, rect.height),
+        )
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<CGRect> for Rect {
+    fn from(rect: CGRect) -> Self {
+        Rect::new(
+            rect.origin.x,
+            rect.origin.y,
+            rect.size.width,
+            rect.size.height,
+        )
+    }
+}
+
+/// Where to capture from and how large the captured frames are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    /// In points, relative to the captured display or window.
+    pub source_rect: Rect,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Where the captured region lies on the desktop, for mapping desktop
+/// positions such as the cursor's onto the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputMapping {
+    /// In global display coordinates, in points.
+    pub region: Rect,
+    /// Where the region lands in the output, in pixels, or `None` if it fills
+    /// the output.
+    pub output: Option<Rect>,
+}
+
+impl OutputMapping {
+    /// `source_rect` is relative to content whose top-left corner is at
+    /// `content_origin` on the desktop.
+    pub fn new(content_origin: (f64, f64), source_rect: Rect, output: Option<Rect>) -> Self {
+        Self {
+            output,
+            region: Rect::new(
+                content_origin.0 + source_rect.x,
+                content_origin.1 + source_rect.y,
+                source_rect.width,
+                source_rect.height,
+            ),
+        }
+    }
+
+    /// Maps a desktop point to pixels of a `width`x`height` output, which may
+    /// lie outside it.
+    pub fn map_point(&self, (x, y): (f64, f64), width: usize, height: usize) -> (f64, f64) {
+        let output = self
+            .output
+            .unwrap_or(Rect::from_size(width as f64, height as f64));
+        (
+            output.x + (x - self.region.x) * output.width / self.region.width,
+            output.y + (y - self.region.y) * output.height / self.region.height,
+        )
+    }
+}
+
+/// Crops `content_size` (in points) to `crop`, falling back to the whole
+/// content when the crop doesn't overlap it. Output dimensions are rounded
+/// down to even numbers as NDI's YUV formats require.
+pub fn capture_geometry(content_width: f64, content_height: f64, crop: Option<Rect>) -> Geometry {
+    let content = Rect::from_size(content_width, content_height);
+    let source_rect = crop
+        .and_then(|crop| crop.intersection(&content))
+        .unwrap_or(content);
+    let even = |v: f64| (v.round() as usize) & !1;
+    Geometry {
+        source_rect,
+        width: even(source_rect.width).max(2),
+        height: even(source_rect.height).max(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_the_region_on_the_desktop() {
+        let mapping = OutputMapping::new((1920., 0.), Rect::new(10., 20., 100., 50.), None);
+        assert_eq!(mapping.region, Rect::new(1930., 20., 100., 50.));
+    }
+
+    #[test]
+    fn maps_points_onto_a_filled_output() {
+        let mapping = OutputMapping {
+            region: Rect::new(100., 50., 200., 100.),
+            output: None,
+        };
+        assert_eq!(mapping.map_point((100., 50.), 400, 200), (0., 0.));
+        assert_eq!(mapping.map_point((200., 100.), 400, 200), (200., 100.));
+        assert_eq!(mapping.map_point((300., 150.), 400, 200), (400., 200.));
+        assert_eq!(mapping.map_point((0., 0.), 400, 200), (-200., -100.));
+    }
+
+    #[test]
+    fn maps_points_into_a_letterboxed_output() {
+        let mapping = OutputMapping {
+            region: Rect::new(100., 50., 200., 100.),
+            output: Some(Rect::new(40., 10., 320., 160.)),
+        };
+        assert_eq!(mapping.map_point((100., 50.), 400, 200), (40., 10.));
+        assert_eq!(mapping.map_point((200., 100.), 400, 200), (200., 90.));
+        assert_eq!(mapping.map_point((300., 150.), 400, 200), (360., 170.));
+    }
+
+    #[test]
+    fn crops_to_even_dimensions() {
+        let geometry = capture_geometry(1920., 1080., Some(Rect::new(10., 10., 101., 51.)));
+        assert_eq!(geometry.source_rect, Rect::new(10., 10., 101., 51.));
+        assert_eq!((geometry.width, geometry.height), (100, 50));
+        let geometry = capture_geometry(1920., 1080., Some(Rect::new(3000., 0., 10., 10.)));
+        assert_eq!(geometry.source_rect, Rect::from_size(1920., 1080.));
+    }
+}
@@ -1,87 +1,1035 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
 
+use anyhow::{anyhow, Result};
 use cocoa_foundation::foundation::NSInteger;
-use core_graphics_types::geometry::{CGPoint, CGRect, CGSize};
+use tracing::{debug, info, instrument, trace, warn};
 
 use framework_sys as fw_sys;
-use sckit::{ContentFilter, ShareableContent, Stream, StreamConfig, StreamOutput};
+use sckit::{
+    ContentFilter, Display, FrameStatus, RunningApplication, ShareableContent, Stream,
+    StreamConfig, StreamDelegate, StreamOutput, Window,
+};
 
-use crate::ndi;
+use crate::{
+    command::{Command, Target},
+    config::{Config, FallbackDisplay},
+    convert::UyvySink,
+    cursor::{CursorOverlay, CursorSource, SystemCursor},
+    events::{Event, EventBus},
+    frame::{Frame, FrameRate, PixelFormat},
+    geometry::{self, OutputMapping, Rect, Size},
+    identity::{self, DisplayGone, Identity, Selection},
+    layers::{find_window, LayerCaptures},
+    mask::{Mask, MaskSink},
+    metadata::{self, CaptureInfo},
+    metrics::PipelineMetrics,
+    ndi,
+    observable::Observable,
+    overlay::{Compositor, OverlaySink},
+    pacer::{Pacer, PacerStats, VideoSink},
+    permission::{Permission, PermissionGate, PermissionMonitor, SystemAccess},
+    pip::{LayerSource, PipCompositor},
+    pool::FramePool,
+    redact::{Redactor, WindowTitle},
+    rendition::{FanOut, Rendition},
+    scale::Scaler,
+    scene::Scene,
+    slate::SlateSink,
+    status::{Source, Status},
+    tally::{Tally, TallyMonitor},
+    timing,
+    transition::TransitionSink,
+    watchdog::{Restart, Watchdog},
+};
+
+const EXCLUDED_BUNDLE_IDS: &[&str] = &[
+    "com.koba789.sckitndi",
+    "com.apple.controlcenter",
+    "com.apple.dock",
+    "com.apple.TextInputMenuAgent",
+    "com.1password.1password",
+    "com.getdropbox.dropbox",
+    "com.getdropbox.dropbox",
+    "com.apple.notificationcenterui",
+    "com.justsystems.inputmethod.atok32",
+    "com.apple.systemuiserver",
+    "com.newtek.Application-Mac-NDI-StudioMonitor",
+    "com.hnc.Discord",
+];
 
 pub struct Grabber {
-    sender: ndi::Sender,
+    frame_rate: FrameRate,
+    sender: Arc<ndi::Sender>,
+    pacer: Pacer,
+    tally: TallyMonitor,
+    pool: FramePool,
+    source: Mutex<Source>,
+    /// The masks of sources captured before, restored when switching back.
+    saved_masks: Mutex<HashMap<Option<Target>, Vec<Mask>>>,
+    /// The current source's masks, shared with the mask sink.
+    masks: Arc<Mutex<Vec<Mask>>>,
+    running: AtomicBool,
+    paused: AtomicBool,
     stream: Mutex<Option<Stream>>,
+    /// Bumped by every start and stop, so a start that completes after it
+    /// was superseded doesn't bring the stream back.
+    generation: AtomicU64,
+    events: Arc<EventBus>,
+    metrics: Arc<PipelineMetrics>,
+    permission: Arc<PermissionGate>,
+    _permission_monitor: PermissionMonitor,
+    /// Set while a start waits for Screen Recording permission.
+    pending_start: AtomicBool,
+    /// What the current target looked like when it was last resolved.
+    identity: Mutex<Option<Identity>>,
+    /// The display the capture fell back from, to return to once it is back.
+    displaced: Mutex<Option<Identity>>,
+    fallback_display: Option<FallbackDisplay>,
+    /// Shared with the layer captures.
+    redactor: Arc<Redactor>,
+    show_cursor: bool,
+    /// Sampled with each captured frame while the cursor is highlighted.
+    cursor_source: Option<Arc<dyn CursorSource>>,
+    scaler: Mutex<Option<Arc<Scaler>>>,
+    /// Shared with the overlay sink.
+    compositor: Arc<Mutex<Arc<Compositor>>>,
+    /// Present if the output or any scene is UYVY.
+    uyvy: Option<Arc<UyvySink>>,
+    scenes: Vec<Scene>,
+    scene: Mutex<Option<String>>,
+    /// Covers the switch when the target or crop changes.
+    transition: Arc<TransitionSink>,
+    slate: Arc<SlateSink>,
+    /// Captures the picture-in-picture layers while the main capture runs.
+    layers: Option<Arc<LayerCaptures>>,
+    /// Shared with the cursor highlight.
+    output_mapping: Arc<Mutex<Option<OutputMapping>>>,
+    watchdog: Watchdog,
+}
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+/// How often window titles are checked against the redaction rules.
+const REDACT_INTERVAL: Duration = Duration::from_secs(2);
+
+fn display_identity(display: &Display) -> Identity {
+    Identity::Display {
+        id: display.display_id(),
+        frame: display.frame().into(),
+    }
+}
+
+pub fn is_excluded_application(app: &RunningApplication) -> bool {
+    app.bundle_identifier()
+        .map(|id| EXCLUDED_BUNDLE_IDS.iter().any(|&block| block == id))
+        .unwrap_or(false)
+}
+
+fn window_title(window: &Window) -> WindowTitle {
+    WindowTitle {
+        id: window.window_id(),
+        title: window.title(),
+    }
+}
+
+/// Windows a display capture has to exclude one by one: those matching the
+/// redaction rules and, if there are any, the excluded applications' windows
+/// too, since no content filter excludes both applications and individual
+/// windows.
+pub fn redacted_windows(redactor: &Redactor, windows: &[Window]) -> BTreeSet<u32> {
+    let mut redacted = redactor.matching(windows.iter().map(window_title));
+    if !redacted.is_empty() {
+        redacted.extend(
+            windows
+                .iter()
+                .filter(|window| is_excluded_application(&window.owning_application()))
+                .map(|window| window.window_id()),
+        );
+    }
+    redacted
+}
+
+/// Captures `display` without the excluded applications, or without the
+/// `redacted` windows if there are any.
+pub fn display_filter(
+    display: &Display,
+    applications: &[RunningApplication],
+    windows: &[Window],
+    redacted: &BTreeSet<u32>,
+) -> ContentFilter {
+    if redacted.is_empty() {
+        let excluding_applications: Vec<_> = applications
+            .iter()
+            .filter(|a| is_excluded_application(a))
+            .collect();
+        ContentFilter::init_with_display_excluding_applications_excepting_windows(
+            display,
+            excluding_applications.iter().copied(),
+            [],
+        )
+    } else {
+        ContentFilter::init_with_display_excluding_windows(
+            display,
+            windows
+                .iter()
+                .filter(|window| redacted.contains(&window.window_id())),
+        )
+    }
+}
+
+fn window_identity(window: &Window) -> Identity {
+    Identity::Window {
+        id: window.window_id(),
+        bundle_id: window.owning_application().bundle_identifier(),
+        title: window.title(),
+    }
 }
 
 impl Grabber {
-    pub fn new() -> Grabber {
-        let sender = ndi::Sender::new("sckitndi");
+    pub fn new(config: &Config) -> Result<Grabber> {
+        let sender = Arc::new(ndi::Sender::new(
+            &config.ndi_name,
+            config.frame_rate,
+            config.timecode_mode,
+        )?);
+        let metrics = Arc::new(PipelineMetrics::new());
+        // One buffer being filled, one held by the pacer and one in flight in
+        // NDI, plus slack for the queue and the copies made when scaling,
+        // drawing overlays or converting.
+        let pool = FramePool::new(6, metrics.buffers_in_use.clone());
+        metrics.buffers_capacity.set(pool.capacity() as i64);
+        let output_mapping = Arc::new(Mutex::new(None));
+        let cursor = config
+            .cursor_highlight
+            .map(|style| CursorOverlay::new(style, output_mapping.clone()));
+        let cursor_source = cursor
+            .is_some()
+            .then(|| Arc::new(SystemCursor) as Arc<dyn CursorSource>);
+        let scenes = config
+            .scenes
+            .iter()
+            .map(|spec| Scene::new(spec, config))
+            .collect::<Result<Vec<_>>>()?;
+        let compositor = Arc::new(Compositor::new(&config.overlays)?);
+        let overlays = !compositor.is_empty() || scenes.iter().any(|s| !s.compositor.is_empty());
+        let compositor = Arc::new(Mutex::new(compositor));
+        let mut sink: Arc<dyn VideoSink> = sender.clone();
+        let uyvy = (config.pixel_format == PixelFormat::Uyvy
+            || scenes.iter().any(|s| s.pixel_format == PixelFormat::Uyvy))
+        .then(|| {
+            Arc::new(UyvySink::new(
+                pool.clone(),
+                config.pixel_format == PixelFormat::Uyvy,
+                sink.clone(),
+            ))
+        });
+        if let Some(uyvy) = &uyvy {
+            sink = uyvy.clone();
+        }
+        if !config.renditions.is_empty() {
+            let renditions = config
+                .renditions
+                .iter()
+                .map(|spec| {
+                    let sender = ndi::Sender::new(
+                        &format!("{} {}", config.ndi_name, spec.name),
+                        config.frame_rate,
+                        config.timecode_mode,
+                    )?;
+                    Ok(Rendition::new(
+                        spec,
+                        config.scale_filter,
+                        config.scale_fit,
+                        Arc::new(sender),
+                        &metrics,
+                    ))
+                })
+                .collect::<Result<_>>()?;
+            sink = Arc::new(FanOut::new(sink, renditions));
+        }
+        let slate = Arc::new(SlateSink::new(&config.slate, config.output_size, sink)?);
+        sink = slate.clone();
+        if cursor.is_some() || overlays {
+            // The frame being drawn on and the one in flight.
+            let overlay_pool = FramePool::new(2, metrics.buffers_in_use.clone());
+            metrics.buffers_capacity.add(overlay_pool.capacity() as i64);
+            sink = Arc::new(OverlaySink::new(
+                cursor,
+                compositor.clone(),
+                overlay_pool,
+                sink,
+            ));
+        }
+        // The picture a transition starts from, one being mixed and one in
+        // flight.
+        let transition_pool = FramePool::new(3, metrics.buffers_in_use.clone());
+        metrics
+            .buffers_capacity
+            .add(transition_pool.capacity() as i64);
+        let transition = Arc::new(TransitionSink::new(
+            config.transition,
+            transition_pool,
+            sink,
+        ));
+        let redactor = Arc::new(Redactor::new(config.redact_titles.clone()));
+        let layers = (!config.layers.is_empty()).then(|| {
+            Arc::new(LayerCaptures::new(
+                &config.layers,
+                config.frame_rate,
+                redactor.clone(),
+                &metrics,
+            ))
+        });
+        let pip = layers.as_ref().map(|layers| {
+            // One composited picture being scaled per layer at a time.
+            let pool = FramePool::new(config.layers.len(), metrics.buffers_in_use.clone());
+            metrics.buffers_capacity.add(pool.capacity() as i64);
+            PipCompositor::new(config.layers.iter().cloned().zip(layers.feeds()), pool)
+        });
+        // Masks go on before a transition, so the picture it starts from is
+        // masked too.
+        let masks = Arc::new(Mutex::new(config.masks.clone()));
+        // The frame being drawn on, the one in flight and the one a
+        // transition starts from.
+        let mask_pool = FramePool::new(3, metrics.buffers_in_use.clone());
+        metrics.buffers_capacity.add(mask_pool.capacity() as i64);
+        let mask_sink = Arc::new(MaskSink::new(
+            pip,
+            masks.clone(),
+            mask_pool,
+            transition.clone(),
+        ));
+        let pacer = Pacer::new(config.frame_rate, mask_sink, metrics.clone());
+        let tally = TallyMonitor::new(sender.clone());
+        let events = Arc::new(EventBus::new());
+        {
+            let events = events.clone();
+            tally
+                .tally()
+                .subscribe(move |tally| events.publish((*tally).into()));
+        }
+        let permission = Arc::new(PermissionGate::new(Arc::new(SystemAccess)));
+        {
+            let events = events.clone();
+            permission
+                .permission()
+                .subscribe(move |&permission| events.publish(Event::Permission { permission }));
+        }
+        let permission_monitor = PermissionMonitor::new(permission.clone());
+        let source = Mutex::new(Source {
+            target: None,
+            crop: config.crop,
+            masks: config.masks.clone(),
+        });
         let stream = Mutex::new(None);
-        Self { sender, stream }
+        Ok(Self {
+            frame_rate: config.frame_rate,
+            sender,
+            pacer,
+            tally,
+            pool,
+            source,
+            saved_masks: Mutex::new(HashMap::new()),
+            masks,
+            running: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            stream,
+            generation: AtomicU64::new(0),
+            events,
+            metrics,
+            permission,
+            _permission_monitor: permission_monitor,
+            pending_start: AtomicBool::new(false),
+            identity: Mutex::new(None),
+            displaced: Mutex::new(None),
+            fallback_display: config.fallback_display,
+            redactor,
+            show_cursor: config.show_cursor,
+            cursor_source,
+            scaler: Mutex::new(
+                config
+                    .output_size
+                    .map(|size| Arc::new(Scaler::new(size, config.scale_filter, config.scale_fit))),
+            ),
+            compositor,
+            uyvy,
+            scenes,
+            scene: Mutex::new(None),
+            transition,
+            slate,
+            layers,
+            output_mapping,
+            watchdog: Watchdog::new(config.stall_timeout),
+        })
+    }
+
+    fn advertise(&self, info: &CaptureInfo) {
+        let product = metadata::product().to_string();
+        let capture = metadata::capture(info).to_string();
+        self.sender
+            .set_connection_metadata([product.as_str(), capture.as_str()]);
+        self.sender.send_metadata(&capture);
+        self.sender.set_frame_metadata(Some(&capture));
+    }
+
+    pub fn tally(&self) -> &Observable<Tally> {
+        self.tally.tally()
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            running: self.running.load(Ordering::Relaxed),
+            paused: self.paused.load(Ordering::Relaxed),
+            slate: self.slate.is_active(),
+            source: self.source.lock().unwrap().clone(),
+            scene: self.scene.lock().unwrap().clone(),
+            permission: self.permission.permission().get(),
+        }
+    }
+
+    pub fn permission(&self) -> &Observable<Permission> {
+        self.permission.permission()
+    }
+
+    /// Starts the capture if a start was waiting for permission.
+    pub fn resume_pending_start(self: &Arc<Self>) {
+        if self.pending_start.swap(false, Ordering::SeqCst) {
+            self.start();
+        }
+    }
+
+    pub fn stats(&self) -> PacerStats {
+        self.pacer.stats()
+    }
+
+    pub fn sender(&self) -> Arc<ndi::Sender> {
+        self.sender.clone()
+    }
+
+    pub fn events(&self) -> Arc<EventBus> {
+        self.events.clone()
+    }
+
+    pub fn metrics(&self) -> &PipelineMetrics {
+        &self.metrics
+    }
+
+    /// Samples the NDI connection count and publishes the pacer stats; called
+    /// once a second.
+    pub fn refresh_stats(&self) {
+        self.metrics
+            .connections
+            .set(self.sender.connections() as i64);
+        self.events.publish(Event::Stats {
+            stats: self.stats(),
+        });
+    }
+
+    fn publish_state(&self) {
+        self.events.publish(Event::State {
+            running: self.running.load(Ordering::Relaxed),
+            paused: self.paused.load(Ordering::Relaxed),
+            slate: self.slate.is_active(),
+        });
+    }
+
+    fn update_source(&self, update: impl FnOnce(&mut Source)) {
+        let source = {
+            let mut source = self.source.lock().unwrap();
+            update(&mut source);
+            *self.masks.lock().unwrap() = source.masks.clone();
+            source.clone()
+        };
+        self.events.publish(Event::Source { source });
+    }
+
+    fn set_source(self: &Arc<Self>, update: impl FnOnce(&mut Source)) {
+        let picture = |source: &Source| (source.target, source.crop);
+        let before = picture(&self.source.lock().unwrap());
+        self.update_source(update);
+        let changed = picture(&self.source.lock().unwrap()) != before;
+        self.reconfigure(changed);
+    }
+
+    fn retarget(&self, source: &mut Source, target: Option<Target>) {
+        source.retarget(target, &mut self.saved_masks.lock().unwrap());
+    }
+
+    /// Picks the display to capture, remembering the one it fell back from
+    /// until that display comes back.
+    fn select_display<'a>(
+        &self,
+        requested: Option<Target>,
+        last_seen: Option<&Identity>,
+        displays: &'a [Display],
+    ) -> Result<(&'a Display, Identity)> {
+        let mut displaced = self.displaced.lock().unwrap();
+        let (display, identity, selection) = identity::select_display(
+            requested,
+            last_seen,
+            displaced.as_ref(),
+            self.fallback_display,
+            displays,
+            display_identity,
+        )?;
+        match selection {
+            Selection::Returned => {
+                info!(display_id = identity.target().id(), "display is back");
+                *displaced = None;
+            }
+            Selection::Requested => {}
+            Selection::Fallback { displaced: gone } => {
+                warn!(
+                    display_id = gone.target().id(),
+                    fallback_id = identity.target().id(),
+                    "display is gone; falling back"
+                );
+                displaced.get_or_insert(gone);
+            }
+        }
+        Ok((display, identity))
+    }
+
+    /// Re-resolves the captured display and its size after displays were
+    /// added, removed or reconfigured.
+    pub fn displays_changed(self: &Arc<Self>) {
+        info!("displays changed");
+        let target = self.source.lock().unwrap().target;
+        if matches!(target, None | Some(Target::Display(_))) {
+            self.reconfigure(false);
+        }
+    }
+
+    fn configure(
+        &self,
+        shareable_content: &ShareableContent,
+    ) -> Result<(ContentFilter, StreamConfig)> {
+        let source = self.source.lock().unwrap().clone();
+        let last_seen = self.identity.lock().unwrap().clone();
+        let (filter, identity, content_rect, excluded_applications, redacted_windows) =
+            match source.target {
+                None | Some(Target::Display(_)) => {
+                    let displays = shareable_content.displays();
+                    let (display, identity) =
+                        self.select_display(source.target, last_seen.as_ref(), &displays)?;
+
+                    let apps = shareable_content.applications();
+                    let excluded_applications =
+                        apps.iter().filter(|a| is_excluded_application(a)).count();
+                    let windows = shareable_content.windows();
+                    let redacted = redacted_windows(&self.redactor, &windows);
+                    let filter = display_filter(display, &apps, &windows, &redacted);
+                    let redacted_windows = self.redactor.matching(windows.iter().map(window_title));
+                    self.redactor.applied(redacted);
+                    let origin = display.frame().origin;
+                    let content_rect = Rect::new(
+                        origin.x,
+                        origin.y,
+                        display.width() as f64,
+                        display.height() as f64,
+                    );
+                    (
+                        filter,
+                        identity,
+                        content_rect,
+                        excluded_applications,
+                        redacted_windows.len(),
+                    )
+                }
+                Some(target @ Target::Window(id)) => {
+                    let windows = shareable_content.windows();
+                    let (window, identity) =
+                        identity::resolve(target, last_seen.as_ref(), &windows, window_identity)
+                            .ok_or_else(|| anyhow!("Window {} not found", id))?;
+                    let filter = ContentFilter::with_desktop_independent_window(window);
+                    let content_rect = Rect::from(window.frame());
+                    (filter, identity, content_rect, 0, 0)
+                }
+            };
+        let target = identity.target();
+        if source.target.is_some_and(|requested| requested != target) {
+            info!(?source.target, ?target, "re-resolved capture target");
+            let mut current = self.source.lock().unwrap();
+            if current.target == source.target {
+                current.target = Some(target);
+                self.events.publish(Event::Source {
+                    source: current.clone(),
+                });
+            }
+        }
+        *self.identity.lock().unwrap() = Some(identity);
+
+        let geometry =
+            geometry::capture_geometry(content_rect.width, content_rect.height, source.crop);
+        let mut stream_config = StreamConfig::default();
+        let destination_rect = Rect::from_size(geometry.width as f64, geometry.height as f64);
+        stream_config.set_width(geometry.width);
+        stream_config.set_height(geometry.height);
+        stream_config.set_source_rect(geometry.source_rect.into());
+        stream_config.set_destination_rect(destination_rect.into());
+        stream_config.set_shows_cursor(self.show_cursor);
+        stream_config.set_queue_depth(5);
+        stream_config.set_minimum_frame_interval(self.frame_rate.frame_duration());
+        let output = self
+            .scaler
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|scaler| scaler.output_rect(Size::new(geometry.width, geometry.height)));
+        *self.output_mapping.lock().unwrap() = Some(OutputMapping::new(
+            (content_rect.x, content_rect.y),
+            geometry.source_rect,
+            output,
+        ));
+        self.advertise(&CaptureInfo {
+            target,
+            crop: geometry.source_rect,
+            excluded_applications,
+            redacted_windows,
+        });
+        Ok((filter, stream_config))
     }
 
     pub fn start(self: &Arc<Self>) {
+        if self.running.load(Ordering::SeqCst) {
+            return;
+        }
+        if self.permission.check() != Permission::Granted {
+            warn!("waiting for Screen Recording permission");
+            self.pending_start.store(true, Ordering::SeqCst);
+            return;
+        }
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.watchdog.arm();
+        self.publish_state();
+        if let Some(layers) = &self.layers {
+            layers.set_active(true);
+        }
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
         let this = self.clone();
         ShareableContent::get(move |ret| {
             let this = this.clone();
-            let shareable_content = ret.unwrap();
-            let displays = shareable_content.displays();
-            let first_display = &displays[0];
-
-            let apps = shareable_content.applications();
-            let excluding_applications = apps.iter().filter(|a| {
-                a.bundle_identifier()
-                    .map(|id| {
-                        [
-                            "com.koba789.sckitndi",
-                            "com.apple.controlcenter",
-                            "com.apple.dock",
-                            "com.apple.TextInputMenuAgent",
-                            "com.1password.1password",
-                            "com.getdropbox.dropbox",
-                            "com.getdropbox.dropbox",
-                            "com.apple.notificationcenterui",
-                            "com.justsystems.inputmethod.atok32",
-                            "com.apple.systemuiserver",
-                            "com.newtek.Application-Mac-NDI-StudioMonitor",
-                            "com.hnc.Discord",
-                        ]
-                        .iter()
-                        .any(|&block| block == id)
-                    })
-                    .unwrap_or(false)
-            });
-            let filter = ContentFilter::init_with_display_excluding_applications_excepting_windows(
-                first_display,
-                excluding_applications,
-                [],
+            let configured = ret.and_then(|shareable_content| this.configure(&shareable_content));
+            let (filter, stream_config) = match configured {
+                Ok(configured) => configured,
+                Err(err) if err.is::<DisplayGone>() => {
+                    this.display_gone(&err);
+                    return;
+                }
+                Err(err) => {
+                    this.abort_start(generation, err.context("failed to configure capture"));
+                    return;
+                }
+            };
+            let stream = Stream::with_delegate(
+                filter,
+                stream_config,
+                this.clone() as Arc<dyn StreamDelegate>,
             );
-            let mut stream_config = StreamConfig::default();
-            let source_rect = CGRect::new(
-                &CGPoint::new(1920. / 2., 1080. / 2.),
-                &CGSize::new(1920., 1080.),
-            );
-            let destination_rect = CGRect::new(&CGPoint::new(0., 0.), &source_rect.size);
-            stream_config.set_width(source_rect.size.width as usize);
-            stream_config.set_height(source_rect.size.height as usize);
-            stream_config.set_source_rect(source_rect);
-            stream_config.set_destination_rect(destination_rect);
-            stream_config.set_queue_depth(5);
-            let stream = Stream::new(filter, stream_config);
+            let _entered = stream.span().enter();
             {
                 let mut this_stream = this.stream.lock().unwrap();
+                if this.generation.load(Ordering::SeqCst) != generation {
+                    debug!("capture stopped before it started");
+                    return;
+                }
                 *this_stream = Some(stream.clone());
             }
-            let did_add_output = stream.add_stream_output(this as Arc<dyn StreamOutput>, 0);
-            assert!(did_add_output);
-            stream.start_capture(|ret| {
-                ret.unwrap();
-                println!("started");
+            if let Err(err) = stream.add_stream_output(this.clone() as Arc<dyn StreamOutput>, 0) {
+                this.abort_start(generation, err);
+                return;
+            }
+            stream.start_capture(move |ret| match ret {
+                Ok(()) => info!("capture started"),
+                Err(err) => this.abort_start(generation, err),
+            });
+        });
+    }
+
+    fn abort_start(&self, generation: u64, err: anyhow::Error) {
+        {
+            let mut stream = self.stream.lock().unwrap();
+            if self.generation.load(Ordering::SeqCst) != generation {
+                debug!("superseded start failed: {:#}", err);
+                return;
+            }
+            stream.take();
+        }
+        if self.permission.observe_error(&err) {
+            // Retried once the permission monitor sees access granted.
+            self.pending_start.store(true, Ordering::SeqCst);
+            self.watchdog.disarm();
+        } else {
+            self.watchdog.failed(format!("{:#}", err));
+        }
+        self.events.error(format!("{:#}", err));
+        self.running.store(false, Ordering::SeqCst);
+        self.publish_state();
+    }
+
+    /// Stops instead of capturing another display, as configured.
+    fn display_gone(&self, err: &anyhow::Error) {
+        self.events.error(format!("{:#}; stopping capture", err));
+        self.stop();
+    }
+
+    /// Supersedes any start in progress and takes the current stream.
+    fn take_stream(&self) -> Option<Stream> {
+        let mut stream = self.stream.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        stream.take()
+    }
+
+    pub fn stop(&self) {
+        self.pending_start.store(false, Ordering::SeqCst);
+        self.watchdog.disarm();
+        if let Some(layers) = &self.layers {
+            layers.set_active(false);
+        }
+        if let Some(stream) = self.take_stream() {
+            let events = self.events.clone();
+            stream.stop_capture(move |ret| match ret {
+                Ok(()) => info!("capture stopped"),
+                Err(err) => events.error(format!("{:#}", err)),
+            });
+        }
+        if self.running.swap(false, Ordering::SeqCst) {
+            self.publish_state();
+        }
+    }
+
+    /// Polls the watchdog on a background thread for as long as the grabber
+    /// is alive. `on_restart` must hand restarts to [`Grabber::restart`] on
+    /// the main thread.
+    pub fn spawn_watchdog(self: &Arc<Self>, on_restart: impl Fn(Restart) + Send + 'static) {
+        let this = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("watchdog".to_string())
+            .spawn(move || run_watchdog(this, on_restart))
+            .unwrap();
+    }
+
+    /// Keeps retrying picture-in-picture sources that aren't being captured.
+    pub fn spawn_layers(&self) {
+        if let Some(layers) = &self.layers {
+            layers.spawn_supervisor();
+        }
+    }
+
+    /// Re-evaluates the redaction rules periodically, so that windows are
+    /// hidden as soon as their titles match.
+    pub fn spawn_redaction(self: &Arc<Self>) {
+        if !self.redactor.is_enabled()
+            && self
+                .scenes
+                .iter()
+                .all(|scene| scene.redact_titles.is_empty())
+        {
+            return;
+        }
+        let this = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("redaction".to_string())
+            .spawn(move || run_redaction(this))
+            .unwrap();
+    }
+
+    fn check_redaction(self: &Arc<Self>) {
+        let capturing_display = matches!(
+            self.source.lock().unwrap().target,
+            None | Some(Target::Display(_))
+        );
+        if !capturing_display || self.stream.lock().unwrap().is_none() {
+            return;
+        }
+        let this = self.clone();
+        ShareableContent::get(move |ret| {
+            let shareable_content = match ret {
+                Ok(shareable_content) => shareable_content,
+                Err(err) => {
+                    debug!("{:#}", err);
+                    return;
+                }
+            };
+            let diff = this.redactor.diff(&redacted_windows(
+                &this.redactor,
+                &shareable_content.windows(),
+            ));
+            if !diff.is_empty() {
+                info!(added = ?diff.added, removed = ?diff.removed, "redacted windows changed");
+                this.reconfigure(false);
+            }
+        });
+    }
+
+    /// Restarts the capture as the watchdog asked, unless it was stopped in
+    /// the meantime. Must be called on the main thread.
+    pub fn restart(self: &Arc<Self>, restart: Restart) {
+        if !self.watchdog.is_armed() {
+            return;
+        }
+        warn!(
+            attempt = restart.attempt,
+            "restarting capture: {}", restart.reason
+        );
+        self.metrics.restarts.inc();
+        self.events.publish(Event::Recovering {
+            attempt: restart.attempt,
+            reason: restart.reason,
+            retry_in_secs: restart.backoff.as_secs_f64(),
+        });
+        if let Some(stream) = self.take_stream() {
+            stream.stop_capture(|ret| {
+                if let Err(err) = ret {
+                    debug!("{:#}", err);
+                }
+            });
+        }
+        self.running.store(false, Ordering::SeqCst);
+        self.start();
+    }
+
+    #[instrument(skip(self))]
+    pub fn handle(self: &Arc<Self>, command: Command) {
+        match command {
+            Command::Start => {
+                self.permission.retry();
+                self.start()
+            }
+            Command::Stop => self.stop(),
+            Command::SwitchDisplay(id) => {
+                self.displaced.lock().unwrap().take();
+                self.set_source(|source| self.retarget(source, Some(Target::Display(id))))
+            }
+            Command::SwitchWindow(id) => {
+                self.displaced.lock().unwrap().take();
+                self.set_source(|source| self.retarget(source, Some(Target::Window(id))))
+            }
+            Command::SetCrop(crop) => self.set_source(|source| source.crop = crop),
+            // Masks are applied to each frame; the stream needn't change.
+            Command::SetMasks(masks) => self.update_source(|source| source.masks = masks),
+            Command::SwitchScene(name) => self.switch_scene(&name),
+            Command::Pause => {
+                if !self.paused.swap(true, Ordering::Relaxed) {
+                    self.publish_state();
+                }
+            }
+            // Captured frames keep flowing underneath, so that resuming shows
+            // the current picture straight away.
+            Command::ShowSlate => {
+                if !self.slate.set_active(true) {
+                    self.publish_state();
+                }
+            }
+            Command::Resume => {
+                let paused = self.paused.swap(false, Ordering::Relaxed);
+                let slate = self.slate.set_active(false);
+                if paused || slate {
+                    self.publish_state();
+                }
+            }
+        }
+    }
+
+    /// Switches to a configured scene. A window source is looked up by title
+    /// first, and the scene is applied once it is found.
+    fn switch_scene(self: &Arc<Self>, name: &str) {
+        let Some(index) = self.scenes.iter().position(|scene| scene.name == name) else {
+            self.events.error(format!("No scene named {:?}", name));
+            return;
+        };
+        match &self.scenes[index].source {
+            Some(LayerSource::Window(pattern)) => {
+                let pattern = pattern.clone();
+                let this = self.clone();
+                ShareableContent::get(move |ret| {
+                    let found = ret.and_then(|shareable_content| {
+                        find_window(&pattern, &shareable_content.windows())
+                            .map(Window::window_id)
+                            .ok_or_else(|| anyhow!("No window matches {:?}", pattern.to_string()))
+                    });
+                    match found {
+                        Ok(id) => this.apply_scene(index, Some(Target::Window(id))),
+                        Err(err) => this.events.error(format!(
+                            "failed to switch to scene {:?}: {:#}",
+                            this.scenes[index].name, err
+                        )),
+                    }
+                });
+            }
+            &Some(LayerSource::Display(id)) => self.apply_scene(index, Some(Target::Display(id))),
+            None => self.apply_scene(index, None),
+        }
+    }
+
+    /// Swaps in the scene's per-frame settings, then reconfigures the stream
+    /// in place, so the NDI sender and its receivers stay connected.
+    fn apply_scene(self: &Arc<Self>, index: usize, target: Option<Target>) {
+        let scene = &self.scenes[index];
+        info!(scene = %scene.name, ?target, "switching scene");
+        *self.scaler.lock().unwrap() = scene.scaler.clone();
+        *self.compositor.lock().unwrap() = scene.compositor.clone();
+        if let Some(uyvy) = &self.uyvy {
+            uyvy.set_enabled(scene.pixel_format == PixelFormat::Uyvy);
+        }
+        self.redactor.set_patterns(scene.redact_titles.clone());
+        *self.scene.lock().unwrap() = Some(scene.name.clone());
+        self.events.publish(Event::Scene {
+            name: scene.name.clone(),
+        });
+        self.displaced.lock().unwrap().take();
+        self.set_source(|source| {
+            self.retarget(source, target);
+            source.crop = scene.crop;
+            source.masks = scene.masks.clone();
+        });
+    }
+
+    /// Applies the current source to a running stream without restarting it.
+    /// Before the capture has started there is nothing to do; the source is
+    /// picked up by `start`.
+    ///
+    /// With `transition`, the output holds its last frame until the stream is
+    /// updated and then transitions into the new source.
+    fn reconfigure(self: &Arc<Self>, transition: bool) {
+        if self.stream.lock().unwrap().is_none() {
+            return;
+        }
+        if transition {
+            self.transition.hold();
+        }
+        let this = self.clone();
+        ShareableContent::get(move |ret| {
+            let release = || {
+                if transition {
+                    this.transition.release();
+                }
+            };
+            let Some(stream) = this.stream.lock().unwrap().clone() else {
+                release();
+                return;
+            };
+            let _entered = stream.span().enter();
+            let configured = ret.and_then(|shareable_content| this.configure(&shareable_content));
+            let (filter, stream_config) = match configured {
+                Ok(configured) => configured,
+                Err(err) => {
+                    release();
+                    if err.is::<DisplayGone>() {
+                        this.display_gone(&err);
+                        return;
+                    }
+                    this.permission.observe_error(&err);
+                    this.events
+                        .error(format!("failed to reconfigure capture: {:#}", err));
+                    return;
+                }
+            };
+            // The transition is released once both updates are done, whether
+            // or not they succeed.
+            let done = {
+                let pending = Arc::new(AtomicUsize::new(2));
+                let transition = transition.then(|| this.transition.clone());
+                move || {
+                    if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        if let Some(transition) = &transition {
+                            transition.release();
+                        }
+                    }
+                }
+            };
+            let events = this.events.clone();
+            let filter_done = done.clone();
+            stream.update_content_filter(filter, move |ret| {
+                filter_done();
+                if let Err(err) = ret {
+                    events.error(format!("{:#}", err));
+                }
+            });
+            let events = this.events.clone();
+            stream.update_configuration(stream_config, move |ret| {
+                done();
+                if let Err(err) = ret {
+                    events.error(format!("{:#}", err));
+                }
             });
         });
     }
 }
 
+fn run_watchdog(grabber: Weak<Grabber>, on_restart: impl Fn(Restart)) {
+    loop {
+        std::thread::sleep(WATCHDOG_INTERVAL);
+        let Some(grabber) = grabber.upgrade() else {
+            return;
+        };
+        if let Some(restart) = grabber.watchdog.poll() {
+            on_restart(restart);
+        }
+    }
+}
+
+fn run_redaction(grabber: Weak<Grabber>) {
+    loop {
+        std::thread::sleep(REDACT_INTERVAL);
+        let Some(grabber) = grabber.upgrade() else {
+            return;
+        };
+        grabber.check_redaction();
+    }
+}
+
+/// Copies the image of a sample buffer into a frame, or returns `None` for
+/// idle and blank sample buffers, which carry no image.
+///
+/// # Safety
+///
+/// `sample_buffer` must be a valid `CMSampleBuffer`.
+pub unsafe fn copy_sample_buffer(
+    pool: &FramePool,
+    sample_buffer: fw_sys::CMSampleBufferRef,
+    status: Option<FrameStatus>,
+) -> Option<Frame> {
+    let pixel_buffer = fw_sys::CMSampleBufferGetImageBuffer(sample_buffer);
+    if status.is_some_and(|status| status != FrameStatus::Complete) || pixel_buffer.is_null() {
+        return None;
+    }
+    let width = fw_sys::CVPixelBufferGetWidth(pixel_buffer);
+    let height = fw_sys::CVPixelBufferGetHeight(pixel_buffer);
+    let stride = fw_sys::CVPixelBufferGetBytesPerRow(pixel_buffer);
+    fw_sys::CVPixelBufferLockBaseAddress(pixel_buffer, 1);
+    let data = fw_sys::CVPixelBufferGetBaseAddress(pixel_buffer) as *const u8;
+    let mut frame = pool.copy_from_raw(width, height, stride, data);
+    fw_sys::CVPixelBufferUnlockBaseAddress(pixel_buffer, 1);
+    let pts = fw_sys::CMSampleBufferGetPresentationTimeStamp(sample_buffer);
+    frame.set_timestamp(timing::cmtime_to_ticks(pts.into()));
+    Some(frame)
+}
+
+impl StreamDelegate for Grabber {
+    fn did_stop_with_error(&self, _stream: Stream, error: anyhow::Error) {
+        let message = format!("{:#}", error);
+        if self.permission.observe_error(&error) {
+            self.pending_start.store(true, Ordering::SeqCst);
+            self.watchdog.disarm();
+            self.stream.lock().unwrap().take();
+            self.running.store(false, Ordering::SeqCst);
+            self.publish_state();
+        } else {
+            self.watchdog.failed(message.clone());
+        }
+        self.events.error(message);
+    }
+}
+
 impl StreamOutput for Grabber {
     fn did_output_sample_buffer_of_type(
         &self,
@@ -89,14 +1037,36 @@ impl StreamOutput for Grabber {
         sample_buffer: fw_sys::CMSampleBufferRef,
         _type: NSInteger,
     ) {
-        unsafe {
-            let pixel_buffer = fw_sys::CMSampleBufferGetImageBuffer(sample_buffer);
-            let width = fw_sys::CVPixelBufferGetWidth(pixel_buffer);
-            let height = fw_sys::CVPixelBufferGetHeight(pixel_buffer);
-            fw_sys::CVPixelBufferLockBaseAddress(pixel_buffer, 1);
-            let data = fw_sys::CVPixelBufferGetBaseAddress(pixel_buffer) as *mut u8;
-            self.sender.send_video(width as i32, height as i32, data);
-            fw_sys::CVPixelBufferUnlockBaseAddress(pixel_buffer, 1);
+        let status = unsafe { FrameStatus::of(sample_buffer) };
+        trace!(?status, "sample buffer");
+        if let Some(attempts) = self.watchdog.activity() {
+            info!(attempts, "capture recovered");
+            self.events.publish(Event::Recovered { attempts });
+        }
+        if let Some(status) = status {
+            self.metrics.frame_captured(status);
+        }
+        // While paused the pacer keeps repeating the last frame.
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        let started = Instant::now();
+        let Some(mut frame) = (unsafe { copy_sample_buffer(&self.pool, sample_buffer, status) })
+        else {
+            return;
+        };
+        frame.set_cursor(
+            self.cursor_source
+                .as_ref()
+                .and_then(|source| source.cursor()),
+        );
+        let scaler = self.scaler.lock().unwrap().clone();
+        if let Some(scaler) = scaler {
+            frame = scaler.scale(&frame, Some(&self.pool));
         }
+        self.metrics
+            .conversion_time
+            .observe_duration(started.elapsed());
+        self.pacer.push(frame);
     }
 }
@@ -0,0 +1,158 @@
+#[cfg(target_os = "macos")]
+use std::{
+    ffi::c_void,
+    sync::mpsc::{self, Sender},
+};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+#[cfg(target_os = "macos")]
+use anyhow::{bail, Result};
+#[cfg(target_os = "macos")]
+use tracing::debug;
+
+#[cfg(target_os = "macos")]
+type CGDirectDisplayID = u32;
+#[cfg(target_os = "macos")]
+type CGDisplayChangeSummaryFlags = u32;
+#[cfg(target_os = "macos")]
+type CGError = i32;
+#[cfg(target_os = "macos")]
+type ReconfigurationCallback =
+    extern "C" fn(CGDirectDisplayID, CGDisplayChangeSummaryFlags, *mut c_void);
+
+#[cfg(target_os = "macos")]
+const K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG: CGDisplayChangeSummaryFlags = 1 << 0;
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: ReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> CGError;
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: ReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> CGError;
+}
+
+/// Reconfiguration callbacks arrive in bursts, one or two per display; wait
+/// for this much quiet before reacting.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+#[cfg(target_os = "macos")]
+extern "C" fn reconfigured(
+    display_id: CGDirectDisplayID,
+    flags: CGDisplayChangeSummaryFlags,
+    user_info: *mut c_void,
+) {
+    if flags & K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG != 0 {
+        return;
+    }
+    debug!(display_id, flags, "display reconfigured");
+    let changes = unsafe { &*(user_info as *const Sender<()>) };
+    let _ = changes.send(());
+}
+
+/// Calls `on_change` once the displays have been added, removed, moved or
+/// changed mode and things have settled.
+///
+/// The callbacks are delivered on the main run loop.
+#[cfg(target_os = "macos")]
+pub struct DisplayWatcher {
+    changes: *mut Sender<()>,
+}
+
+#[cfg(target_os = "macos")]
+impl DisplayWatcher {
+    pub fn new(on_change: impl Fn() + Send + 'static) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("display-watcher".to_string())
+            .spawn(move || settle(rx, on_change))?;
+        let changes = Box::into_raw(Box::new(tx));
+        let err =
+            unsafe { CGDisplayRegisterReconfigurationCallback(reconfigured, changes as *mut _) };
+        if err != 0 {
+            drop(unsafe { Box::from_raw(changes) });
+            bail!("CGDisplayRegisterReconfigurationCallback failed ({})", err);
+        }
+        Ok(Self { changes })
+    }
+}
+
+fn settle(changes: Receiver<()>, on_change: impl Fn()) {
+    while changes.recv().is_ok() {
+        loop {
+            match changes.recv_timeout(SETTLE_TIME) {
+                Ok(()) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        on_change();
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for DisplayWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            CGDisplayRemoveReconfigurationCallback(reconfigured, self.changes as *mut _);
+            // Disconnects the channel, which ends the settle thread.
+            drop(Box::from_raw(self.changes));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn reacts_once_per_burst_of_changes() {
+        let (tx, rx) = mpsc::channel();
+        let calls = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let settling = scope.spawn(|| {
+                settle(rx, || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                })
+            });
+            for _ in 0..3 {
+                tx.send(()).unwrap();
+            }
+            thread::sleep(SETTLE_TIME * 2);
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            tx.send(()).unwrap();
+            tx.send(()).unwrap();
+            thread::sleep(SETTLE_TIME * 2);
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            drop(tx);
+            settling.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn stops_without_reacting_when_disconnected_mid_burst() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(()).unwrap();
+        drop(tx);
+        let calls = AtomicUsize::new(0);
+        settle(rx, || {
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    command::Command,
+    control::{Accepted, Controller, ErrorBody},
+};
+
+const MAX_BODY_LEN: usize = 64 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// With lowercase names.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).unwrap(),
+        }
+    }
+
+    pub fn error(status: u16, error: impl ToString) -> Self {
+        Self::json(
+            status,
+            &ErrorBody {
+                error: error.to_string(),
+            },
+        )
+    }
+
+    pub fn text(status: u16, content_type: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            415 => "Unsupported Media Type",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// A small blocking HTTP/1.1 server exposing the [`Controller`] as JSON.
+///
+/// Every connection is served on its own thread and closed after one
+/// response. Browsers may only call it from `allowed_origins`.
+pub struct HttpServer {
+    local_addr: SocketAddr,
+}
+
+impl HttpServer {
+    pub fn bind(
+        addr: SocketAddr,
+        allowed_origins: Vec<String>,
+        controller: Arc<dyn Controller>,
+    ) -> Result<Self> {
+        let allowed_origins = Arc::new(allowed_origins);
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+        let local_addr = listener.local_addr()?;
+        std::thread::Builder::new()
+            .name("http".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let allowed_origins = allowed_origins.clone();
+                    let controller = controller.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = serve(stream, &allowed_origins, &*controller) {
+                            tracing::warn!("http: {:#}", err);
+                        }
+                    });
+                }
+            })?;
+        Ok(Self { local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+fn serve(stream: TcpStream, allowed_origins: &[String], controller: &dyn Controller) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let local_addr = stream.local_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => match check_origin(&request, local_addr, allowed_origins) {
+            Ok(()) => route(&request, controller),
+            Err(response) => response,
+        },
+        Err(err) => Response::error(400, err),
+    };
+    write_response(stream, &response)
+}
+
+/// Whether `host`, a `Host` header, names `local_addr`, the address the
+/// connection came in on. `localhost` stands for loopback addresses.
+pub fn is_local_host(host: &str, local_addr: SocketAddr) -> bool {
+    host.eq_ignore_ascii_case(&local_addr.to_string())
+        || (local_addr.ip().is_loopback()
+            && host.eq_ignore_ascii_case(&format!("localhost:{}", local_addr.port())))
+}
+
+/// Whether a request with an `Origin` header of `origin` may be served.
+/// Browsers send one with cross-origin requests; other clients usually don't.
+pub fn is_allowed_origin(origin: Option<&str>, allowed_origins: &[String]) -> bool {
+    match origin {
+        None | Some("") => true,
+        Some(origin) => allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin)),
+    }
+}
+
+/// Turns away requests that web pages could have made: ones for another host
+/// name, as a DNS rebinding attack would send, and ones from origins that
+/// aren't allowed.
+fn check_origin(
+    request: &Request,
+    local_addr: SocketAddr,
+    allowed_origins: &[String],
+) -> Result<(), Response> {
+    let host = request.header("host").unwrap_or_default();
+    if !is_local_host(host, local_addr) {
+        return Err(Response::error(403, format!("Unexpected Host {:?}", host)));
+    }
+    let origin = request.header("origin");
+    if !is_allowed_origin(origin, allowed_origins) {
+        return Err(Response::error(
+            403,
+            format!("Origin {:?} is not allowed", origin.unwrap_or_default()),
+        ));
+    }
+    Ok(())
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("Malformed request line {:?}", request_line);
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let method = method.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let content_length = match headers.iter().find(|(name, _)| name == "content-length") {
+        Some((_, value)) => value.parse().context("Invalid Content-Length")?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_LEN {
+        bail!("Request body too large");
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn parse_id(id: &str) -> Result<u32> {
+    id.parse().map_err(|_| anyhow!("Invalid id {:?}", id))
+}
+
+/// Decodes `%XX` escapes in a path segment, so scene names may contain
+/// spaces and other reserved characters.
+fn percent_decode(segment: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid escape in {:?}", segment))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("{:?} is not UTF-8", segment))
+}
+
+/// Parses a JSON request body, which must be labelled as such.
+fn json_body<T: DeserializeOwned>(request: &Request, expected: &str) -> Result<T, Response> {
+    let content_type = request.header("content-type").unwrap_or_default();
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    if !mime.eq_ignore_ascii_case("application/json") {
+        return Err(Response::error(
+            415,
+            "Expected Content-Type: application/json",
+        ));
+    }
+    serde_json::from_slice(&request.body)
+        .map_err(|err| Response::error(400, format!("Expected {}: {}", expected, err)))
+}
+
+/// Maps a request to a [`Command`], or `None` if it isn't a command route.
+fn command(request: &Request) -> Option<Result<Command, Response>> {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let command = match segments[..] {
+        ["start"] => Ok(Command::Start),
+        ["stop"] => Ok(Command::Stop),
+        ["pause"] => Ok(Command::Pause),
+        ["slate"] => Ok(Command::ShowSlate),
+        ["resume"] => Ok(Command::Resume),
+        ["source", "display", id] => parse_id(id).map(Command::SwitchDisplay),
+        ["source", "window", id] => parse_id(id).map(Command::SwitchWindow),
+        ["crop"] => return Some(json_body(request, "a rect or null").map(Command::SetCrop)),
+        ["scene", name] => percent_decode(name).map(Command::SwitchScene),
+        ["masks"] => return Some(json_body(request, "an array of masks").map(Command::SetMasks)),
+        _ => return None,
+    };
+    Some(command.map_err(|err| Response::error(400, format!("{:#}", err))))
+}
+
+fn route(request: &Request, controller: &dyn Controller) -> Response {
+    if let Some(command) = command(request) {
+        if request.method != "POST" {
+            return Response::error(405, "Use POST");
+        }
+        return match command {
+            Ok(command) => {
+                controller.dispatch(command);
+                Response::json(202, &Accepted { accepted: true })
+            }
+            Err(response) => response,
+        };
+    }
+    if request.method != "GET" {
+        return Response::error(405, "Use GET");
+    }
+    match request.path.as_str() {
+        "/config" => Response::json(200, &controller.config()),
+        "/status" => Response::json(200, &controller.status()),
+        "/stats" => Response::json(200, &controller.stats()),
+        "/metrics" => Response::text(
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            controller.metrics(),
+        ),
+        "/content" => match controller.content() {
+            Ok(content) => Response::json(200, &content),
+            Err(err) => Response::error(500, err),
+        },
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{control::fake::FakeController, geometry::Rect};
+
+    fn server() -> (SocketAddr, Arc<FakeController>) {
+        let controller = Arc::new(FakeController::default());
+        let server = HttpServer::bind(
+            ([127, 0, 0, 1], 0).into(),
+            vec!["http://localhost:3000".to_string()],
+            controller.clone(),
+        )
+        .unwrap();
+        (server.local_addr(), controller)
+    }
+
+    /// Sends a raw request and returns the response's status and body.
+    fn send(addr: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    fn post(addr: SocketAddr, path: &str, headers: &str, body: &str) -> (u16, String) {
+        send(
+            addr,
+            &format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\n\r\n{}",
+                path,
+                addr,
+                headers,
+                body.len(),
+                body
+            ),
+        )
+    }
+
+    #[test]
+    fn serves_requests_for_its_address() {
+        let (addr, controller) = server();
+        let get = |host: &str| {
+            send(
+                addr,
+                &format!("GET /stats HTTP/1.1\r\nHost: {}\r\n\r\n", host),
+            )
+        };
+        assert_eq!(get(&addr.to_string()).0, 200);
+        assert_eq!(get(&format!("LOCALHOST:{}", addr.port())).0, 200);
+        assert_eq!(post(addr, "/start", "", "").0, 202);
+        assert_eq!(*controller.dispatched.lock().unwrap(), [Command::Start]);
+    }
+
+    #[test]
+    fn rejects_other_hosts() {
+        let (addr, controller) = server();
+        for host in [
+            format!("evil.example:{}", addr.port()),
+            "127.0.0.1".to_string(),
+            format!("127.0.0.1:{}", addr.port() + 1),
+        ] {
+            let request = format!("POST /stop HTTP/1.1\r\nHost: {}\r\n\r\n", host);
+            assert_eq!(send(addr, &request).0, 403, "{host}");
+        }
+        assert_eq!(send(addr, "POST /stop HTTP/1.1\r\n\r\n").0, 403);
+        assert!(controller.dispatched.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_origins_not_allowed() {
+        let (addr, controller) = server();
+        let (status, body) = post(addr, "/stop", "Origin: http://evil.example\r\n", "");
+        assert_eq!(status, 403);
+        assert!(body.contains("evil.example"), "{body}");
+        assert_eq!(post(addr, "/stop", "Origin: null\r\n", "").0, 403);
+        assert!(controller.dispatched.lock().unwrap().is_empty());
+
+        assert_eq!(
+            post(addr, "/stop", "Origin: http://localhost:3000\r\n", "").0,
+            202
+        );
+        assert_eq!(post(addr, "/stop", "Origin: \r\n", "").0, 202);
+        assert_eq!(controller.dispatched.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn requires_json_bodies() {
+        let (addr, controller) = server();
+        let crop = r#"{"x": 0, "y": 0, "width": 640, "height": 480}"#;
+        assert_eq!(post(addr, "/crop", "", crop).0, 415);
+        assert_eq!(
+            post(addr, "/crop", "Content-Type: text/plain\r\n", crop).0,
+            415
+        );
+        assert_eq!(
+            post(addr, "/masks", "Content-Type: text/plain\r\n", "[]").0,
+            415
+        );
+        assert!(controller.dispatched.lock().unwrap().is_empty());
+
+        let json = "Content-Type: application/json; charset=utf-8\r\n";
+        assert_eq!(post(addr, "/crop", json, crop).0, 202);
+        assert_eq!(post(addr, "/crop", json, "null").0, 202);
+        assert_eq!(post(addr, "/masks", json, "[]").0, 202);
+        let (status, body) = post(addr, "/crop", json, "[1, 2]");
+        assert_eq!(status, 400);
+        assert!(body.contains("Expected a rect or null"), "{body}");
+        assert_eq!(
+            *controller.dispatched.lock().unwrap(),
+            [
+                Command::SetCrop(Some(Rect::new(0., 0., 640., 480.))),
+                Command::SetCrop(None),
+                Command::SetMasks(Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_methods_and_routes() {
+        let (addr, _) = server();
+        let get = |path: &str| {
+            send(
+                addr,
+                &format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr),
+            )
+        };
+        assert_eq!(get("/start").0, 405);
+        assert_eq!(get("/nowhere").0, 404);
+        assert_eq!(post(addr, "/stats", "", "").0, 405);
+        assert_eq!(post(addr, "/source/window/x", "", "").0, 400);
+        assert_eq!(send(addr, "nonsense\r\n\r\n").0, 400);
+    }
+
+    #[test]
+    fn lists_shareable_content() {
+        let (addr, _) = server();
+        let (status, body) = send(
+            addr,
+            &format!("GET /content HTTP/1.1\r\nHost: {}\r\n\r\n", addr),
+        );
+        assert_eq!(status, 200);
+        let application = json!({
+            "process_id": 42,
+            "bundle_id": "com.apple.Keynote",
+            "name": "Keynote",
+        });
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "displays": [{"id": 1, "width": 1920, "height": 1080}],
+                "windows": [{"id": 1234, "title": "Slides", "application": application}],
+                "applications": [application],
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_scene_names() {
+        assert_eq!(percent_decode("Two%20Up%2f%C3%A9").unwrap(), "Two Up/é");
+        assert!(percent_decode("%zz").is_err());
+        assert!(percent_decode("%4").is_err());
+        assert!(percent_decode("%FF").is_err());
+    }
+}
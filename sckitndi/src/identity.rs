@@ -0,0 +1,342 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use crate::{command::Target, config::FallbackDisplay, geometry::Rect};
+
+/// The selected display is gone and there is no fallback to capture instead.
+#[derive(Debug)]
+pub struct DisplayGone(pub u32);
+
+impl fmt::Display for DisplayGone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Display {} not found", self.0)
+    }
+}
+
+impl std::error::Error for DisplayGone {}
+
+/// Enough about a captured display or window to find it again after its id
+/// changed, e.g. across sleep/wake or a WindowServer restart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Identity {
+    Display {
+        id: u32,
+        frame: Rect,
+    },
+    Window {
+        id: u32,
+        bundle_id: Option<String>,
+        title: String,
+    },
+}
+
+impl Identity {
+    pub fn target(&self) -> Target {
+        match *self {
+            Identity::Display { id, .. } => Target::Display(id),
+            Identity::Window { id, .. } => Target::Window(id),
+        }
+    }
+
+    /// Whether both describe the same display or window: by id, or else by
+    /// what it looked like.
+    fn is_same(&self, other: &Identity) -> bool {
+        if self.target() == other.target() {
+            return true;
+        }
+        match (self, other) {
+            (Identity::Display { frame: a, .. }, Identity::Display { frame: b, .. }) => a == b,
+            (
+                Identity::Window {
+                    bundle_id: a_bundle,
+                    title: a_title,
+                    ..
+                },
+                Identity::Window {
+                    bundle_id: b_bundle,
+                    title: b_title,
+                    ..
+                },
+            ) => a_bundle.is_some() && a_bundle == b_bundle && a_title == b_title,
+            _ => false,
+        }
+    }
+}
+
+/// Finds `target` among `candidates`: by id, or else by the identity it was
+/// last seen with if that identity belongs to `target`.
+pub fn resolve<'a, T>(
+    target: Target,
+    last_seen: Option<&Identity>,
+    candidates: &'a [T],
+    identify: impl Fn(&T) -> Identity,
+) -> Option<(&'a T, Identity)> {
+    let identified = || candidates.iter().map(|c| (c, identify(c)));
+    if let Some(found) = identified().find(|(_, identity)| identity.target() == target) {
+        return Some(found);
+    }
+    let last_seen = last_seen.filter(|identity| identity.target() == target)?;
+    identified().find(|(_, identity)| identity.is_same(last_seen))
+}
+
+/// How [`select_display`] picked a display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    /// The display the capture fell back from has come back.
+    Returned,
+    /// The requested display, or the first one if none was requested.
+    Requested,
+    /// The requested display is gone; `displaced` is what to look for to
+    /// switch back once it returns.
+    Fallback { displaced: Identity },
+}
+
+/// Picks the display to capture: the one the capture fell back from if it
+/// has come back, else the requested one, else the configured fallback.
+pub fn select_display<'a, T>(
+    requested: Option<Target>,
+    last_seen: Option<&Identity>,
+    displaced: Option<&Identity>,
+    fallback: Option<FallbackDisplay>,
+    displays: &'a [T],
+    identify: impl Fn(&T) -> Identity,
+) -> Result<(&'a T, Identity, Selection)> {
+    let returned = displaced
+        .and_then(|displaced| resolve(displaced.target(), Some(displaced), displays, &identify));
+    if let Some((display, identity)) = returned {
+        return Ok((display, identity, Selection::Returned));
+    }
+    let Some(target) = requested else {
+        let display = displays.first().ok_or_else(|| anyhow!("No displays"))?;
+        return Ok((display, identify(display), Selection::Requested));
+    };
+    if let Some((display, identity)) = resolve(target, last_seen, displays, &identify) {
+        return Ok((display, identity, Selection::Requested));
+    }
+    let fallback = match fallback {
+        Some(FallbackDisplay::First) => displays.first(),
+        Some(FallbackDisplay::Id(id)) => displays
+            .iter()
+            .find(|d| identify(d).target() == Target::Display(id)),
+        None => None,
+    };
+    let fallback = fallback.ok_or(DisplayGone(target.id()))?;
+    let displaced = last_seen
+        .filter(|identity| identity.target() == target)
+        .cloned()
+        .unwrap_or(Identity::Display {
+            id: target.id(),
+            frame: Rect::default(),
+        });
+    Ok((
+        fallback,
+        identify(fallback),
+        Selection::Fallback { displaced },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: u32, x: f64) -> Identity {
+        Identity::Display {
+            id,
+            frame: Rect::new(x, 0., 1920., 1080.),
+        }
+    }
+
+    fn window(id: u32, bundle_id: Option<&str>, title: &str) -> Identity {
+        Identity::Window {
+            id,
+            bundle_id: bundle_id.map(str::to_string),
+            title: title.to_string(),
+        }
+    }
+
+    fn resolve_id(
+        target: Target,
+        last_seen: Option<&Identity>,
+        candidates: &[Identity],
+    ) -> Option<u32> {
+        resolve(target, last_seen, candidates, Identity::clone)
+            .map(|(_, identity)| identity.target().id())
+    }
+
+    #[test]
+    fn finds_a_target_by_id_first() {
+        // Display 2 moved onto the frame display 1 was last seen with.
+        let last_seen = display(1, 0.);
+        let displays = [display(2, 0.), display(1, 1920.)];
+        assert_eq!(
+            resolve_id(Target::Display(1), Some(&last_seen), &displays),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn finds_a_display_by_its_frame_once_its_id_is_gone() {
+        let last_seen = display(1, 1920.);
+        let displays = [display(2, 0.), display(3, 1920.)];
+        assert_eq!(
+            resolve_id(Target::Display(1), Some(&last_seen), &displays),
+            Some(3)
+        );
+        assert_eq!(resolve_id(Target::Display(1), None, &displays), None);
+        assert_eq!(
+            resolve_id(Target::Display(1), Some(&display(1, 3840.)), &displays),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_an_identity_of_another_target() {
+        let last_seen = display(5, 1920.);
+        let displays = [display(3, 1920.)];
+        assert_eq!(
+            resolve_id(Target::Display(1), Some(&last_seen), &displays),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_a_window_by_application_and_title() {
+        let last_seen = window(10, Some("com.apple.Safari"), "Slides");
+        let windows = [
+            window(11, Some("com.apple.Safari"), "Other"),
+            window(12, Some("com.google.Chrome"), "Slides"),
+            window(13, Some("com.apple.Safari"), "Slides"),
+        ];
+        assert_eq!(
+            resolve_id(Target::Window(10), Some(&last_seen), &windows),
+            Some(13)
+        );
+    }
+
+    #[test]
+    fn needs_a_bundle_id_to_match_a_window() {
+        let last_seen = window(10, None, "Slides");
+        let windows = [window(11, None, "Slides")];
+        assert_eq!(
+            resolve_id(Target::Window(10), Some(&last_seen), &windows),
+            None
+        );
+    }
+
+    #[test]
+    fn never_matches_a_display_with_a_window() {
+        assert!(!display(1, 0.).is_same(&window(1, Some("a"), "b")));
+        assert!(display(1, 0.).is_same(&display(1, 1920.)));
+    }
+
+    fn select(
+        requested: Option<u32>,
+        last_seen: Option<&Identity>,
+        displaced: Option<&Identity>,
+        fallback: Option<FallbackDisplay>,
+        displays: &[Identity],
+    ) -> Result<(u32, Selection)> {
+        select_display(
+            requested.map(Target::Display),
+            last_seen,
+            displaced,
+            fallback,
+            displays,
+            Identity::clone,
+        )
+        .map(|(_, identity, selection)| (identity.target().id(), selection))
+    }
+
+    #[test]
+    fn selects_the_requested_display_or_the_first() {
+        let displays = [display(1, 0.), display(2, 1920.)];
+        assert_eq!(
+            select(Some(2), None, None, None, &displays).unwrap(),
+            (2, Selection::Requested)
+        );
+        assert_eq!(
+            select(None, None, None, None, &displays).unwrap(),
+            (1, Selection::Requested)
+        );
+        assert!(select(None, None, None, None, &[]).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_the_first_display() {
+        let last_seen = display(3, 3840.);
+        let displays = [display(1, 0.), display(2, 1920.)];
+        assert_eq!(
+            select(
+                Some(3),
+                Some(&last_seen),
+                None,
+                Some(FallbackDisplay::First),
+                &displays
+            )
+            .unwrap(),
+            (
+                1,
+                Selection::Fallback {
+                    displaced: last_seen
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn falls_back_to_a_display_by_id() {
+        let displays = [display(1, 0.), display(2, 1920.)];
+        assert_eq!(
+            select(Some(3), None, None, Some(FallbackDisplay::Id(2)), &displays).unwrap(),
+            (
+                2,
+                Selection::Fallback {
+                    displaced: Identity::Display {
+                        id: 3,
+                        frame: Rect::default()
+                    }
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn fails_with_display_gone_without_a_fallback() {
+        let displays = [display(1, 0.)];
+        for fallback in [None, Some(FallbackDisplay::Id(2))] {
+            let err = select(Some(3), None, None, fallback, &displays).unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(DisplayGone(3))));
+        }
+    }
+
+    #[test]
+    fn switches_back_once_the_displaced_display_returns() {
+        let displaced = display(3, 3840.);
+        // Back under a new id, on the frame it was last seen with.
+        let displays = [display(1, 0.), display(4, 3840.)];
+        assert_eq!(
+            select(
+                Some(1),
+                None,
+                Some(&displaced),
+                Some(FallbackDisplay::First),
+                &displays
+            )
+            .unwrap(),
+            (4, Selection::Returned)
+        );
+        let displays = [display(1, 0.)];
+        assert_eq!(
+            select(
+                Some(1),
+                None,
+                Some(&displaced),
+                Some(FallbackDisplay::First),
+                &displays
+            )
+            .unwrap(),
+            (1, Selection::Requested)
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use cocoa_foundation::foundation::NSInteger;
+use tracing::{debug, info, warn};
+
+use framework_sys as fw_sys;
+use sckit::{
+    ContentFilter, FrameStatus, ShareableContent, Stream, StreamConfig, StreamDelegate,
+    StreamOutput, Window,
+};
+
+use crate::{
+    frame::{Frame, FrameRate},
+    grabber::{copy_sample_buffer, display_filter, is_excluded_application, redacted_windows},
+    metrics::PipelineMetrics,
+    pip::{LayerFeed, LayerSource, LayerSpec},
+    pool::FramePool,
+    redact::{Diff, Redactor, TitlePattern},
+};
+
+/// How often layers whose source isn't being captured are retried.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The first window whose title matches, leaving out excluded applications.
+pub fn find_window<'a>(pattern: &TitlePattern, windows: &'a [Window]) -> Option<&'a Window> {
+    windows.iter().find(|window| {
+        pattern.matches(&window.title()) && !is_excluded_application(&window.owning_application())
+    })
+}
+
+/// Captures the source of one layer with its own stream.
+pub struct LayerCapture {
+    source: LayerSource,
+    frame_rate: FrameRate,
+    pool: FramePool,
+    /// Shared with the main capture, whose title rules display layers follow.
+    redactor: Arc<Redactor>,
+    /// The windows the current display filter hides.
+    redacted: Mutex<BTreeSet<u32>>,
+    latest: Mutex<Option<Arc<Frame>>>,
+    stream: Mutex<Option<Stream>>,
+    /// Whether the source should be captured.
+    active: AtomicBool,
+    /// Set from the start of a capture until it stops.
+    capturing: AtomicBool,
+}
+
+impl LayerCapture {
+    fn new(
+        source: LayerSource,
+        frame_rate: FrameRate,
+        pool: FramePool,
+        redactor: Arc<Redactor>,
+    ) -> Self {
+        Self {
+            source,
+            frame_rate,
+            pool,
+            redactor,
+            redacted: Mutex::new(BTreeSet::new()),
+            latest: Mutex::new(None),
+            stream: Mutex::new(None),
+            active: AtomicBool::new(false),
+            capturing: AtomicBool::new(false),
+        }
+    }
+
+    fn start(self: &Arc<Self>) {
+        if !self.active.load(Ordering::SeqCst) || self.capturing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let this = self.clone();
+        ShareableContent::get(move |ret| {
+            if !this.active.load(Ordering::SeqCst) {
+                this.capturing.store(false, Ordering::SeqCst);
+                return;
+            }
+            let started = ret
+                .and_then(|shareable_content| this.configure(&shareable_content))
+                .and_then(|(filter, stream_config)| {
+                    let stream = Stream::with_delegate(
+                        filter,
+                        stream_config,
+                        this.clone() as Arc<dyn StreamDelegate>,
+                    );
+                    stream.add_stream_output(this.clone() as Arc<dyn StreamOutput>, 0)?;
+                    Ok(stream)
+                });
+            let stream = match started {
+                Ok(stream) => stream,
+                Err(err) => {
+                    // Retried later; the window may not be open yet.
+                    debug!(source = %this.source, "layer source unavailable: {:#}", err);
+                    this.capturing.store(false, Ordering::SeqCst);
+                    return;
+                }
+            };
+            let capture = this.clone();
+            stream.start_capture(move |ret| match ret {
+                Ok(()) => info!(source = %capture.source, "layer capture started"),
+                Err(err) => {
+                    warn!(source = %capture.source, "failed to start layer capture: {:#}", err);
+                    capture.stopped();
+                }
+            });
+            *this.stream.lock().unwrap() = Some(stream);
+        });
+    }
+
+    fn configure(
+        &self,
+        shareable_content: &ShareableContent,
+    ) -> Result<(ContentFilter, StreamConfig)> {
+        let (filter, width, height) = match &self.source {
+            LayerSource::Window(pattern) => {
+                let windows = shareable_content.windows();
+                let window = find_window(pattern, &windows)
+                    .ok_or_else(|| anyhow!("No window matches {:?}", pattern.to_string()))?;
+                let size = window.frame().size;
+                (
+                    ContentFilter::with_desktop_independent_window(window),
+                    size.width,
+                    size.height,
+                )
+            }
+            &LayerSource::Display(id) => {
+                let displays = shareable_content.displays();
+                let display = displays
+                    .iter()
+                    .find(|display| display.display_id() == id)
+                    .ok_or_else(|| anyhow!("Display {} not found", id))?;
+                let apps = shareable_content.applications();
+                let windows = shareable_content.windows();
+                let redacted = redacted_windows(&self.redactor, &windows);
+                let filter = display_filter(display, &apps, &windows, &redacted);
+                *self.redacted.lock().unwrap() = redacted;
+                (filter, display.width() as f64, display.height() as f64)
+            }
+        };
+        let mut stream_config = StreamConfig::default();
+        stream_config.set_width(width.round().max(1.) as usize);
+        stream_config.set_height(height.round().max(1.) as usize);
+        stream_config.set_shows_cursor(false);
+        stream_config.set_queue_depth(3);
+        stream_config.set_minimum_frame_interval(self.frame_rate.frame_duration());
+        Ok((filter, stream_config))
+    }
+
+    /// Updates the filter of a display layer whose redacted windows changed,
+    /// so that windows are hidden as soon as their titles match.
+    fn check_redaction(self: &Arc<Self>) {
+        let LayerSource::Display(id) = self.source else {
+            return;
+        };
+        if !self.redactor.is_enabled() && self.redacted.lock().unwrap().is_empty() {
+            return;
+        }
+        let Some(stream) = self.stream.lock().unwrap().clone() else {
+            return;
+        };
+        let this = self.clone();
+        ShareableContent::get(move |ret| {
+            let shareable_content = match ret {
+                Ok(shareable_content) => shareable_content,
+                Err(err) => {
+                    debug!("{:#}", err);
+                    return;
+                }
+            };
+            let windows = shareable_content.windows();
+            let redacted = redacted_windows(&this.redactor, &windows);
+            if Diff::between(&this.redacted.lock().unwrap(), &redacted).is_empty() {
+                return;
+            }
+            let displays = shareable_content.displays();
+            let Some(display) = displays.iter().find(|display| display.display_id() == id) else {
+                return;
+            };
+            let apps = shareable_content.applications();
+            let filter = display_filter(display, &apps, &windows, &redacted);
+            *this.redacted.lock().unwrap() = redacted;
+            let source = this.source.clone();
+            stream.update_content_filter(filter, move |ret| {
+                if let Err(err) = ret {
+                    warn!(%source, "failed to update layer filter: {:#}", err);
+                }
+            });
+        });
+    }
+
+    fn stop(&self) {
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let source = self.source.clone();
+            stream.stop_capture(move |ret| {
+                if let Err(err) = ret {
+                    warn!(%source, "failed to stop layer capture: {:#}", err);
+                }
+            });
+        }
+        self.stopped();
+    }
+
+    fn stopped(&self) {
+        self.stream.lock().unwrap().take();
+        self.latest.lock().unwrap().take();
+        self.capturing.store(false, Ordering::SeqCst);
+    }
+}
+
+impl LayerFeed for LayerCapture {
+    fn latest(&self) -> Option<Arc<Frame>> {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+impl StreamDelegate for LayerCapture {
+    fn did_stop_with_error(&self, _stream: Stream, error: anyhow::Error) {
+        warn!(source = %self.source, "layer capture stopped: {:#}", error);
+        self.stopped();
+    }
+}
+
+impl StreamOutput for LayerCapture {
+    fn did_output_sample_buffer_of_type(
+        &self,
+        _stream: Stream,
+        sample_buffer: fw_sys::CMSampleBufferRef,
+        _type: NSInteger,
+    ) {
+        let frame = unsafe {
+            let status = FrameStatus::of(sample_buffer);
+            copy_sample_buffer(&self.pool, sample_buffer, status)
+        };
+        if let Some(frame) = frame {
+            *self.latest.lock().unwrap() = Some(Arc::new(frame));
+        }
+    }
+}
+
+/// The captures feeding a layout, run while the main capture is.
+pub struct LayerCaptures {
+    captures: Vec<Arc<LayerCapture>>,
+}
+
+impl LayerCaptures {
+    pub fn new(
+        specs: &[LayerSpec],
+        frame_rate: FrameRate,
+        redactor: Arc<Redactor>,
+        metrics: &PipelineMetrics,
+    ) -> Self {
+        let captures = specs
+            .iter()
+            .map(|spec| {
+                // One buffer being filled, one shown and one being replaced.
+                let pool = FramePool::new(3, metrics.buffers_in_use.clone());
+                metrics.buffers_capacity.add(pool.capacity() as i64);
+                Arc::new(LayerCapture::new(
+                    spec.source.clone(),
+                    frame_rate,
+                    pool,
+                    redactor.clone(),
+                ))
+            })
+            .collect();
+        Self { captures }
+    }
+
+    /// One feed per spec, in the order they were given.
+    pub fn feeds(&self) -> impl Iterator<Item = Arc<dyn LayerFeed>> + '_ {
+        self.captures
+            .iter()
+            .map(|capture| capture.clone() as Arc<dyn LayerFeed>)
+    }
+
+    pub fn set_active(&self, active: bool) {
+        for capture in &self.captures {
+            capture.active.store(active, Ordering::SeqCst);
+            if active {
+                capture.start();
+            } else {
+                capture.stop();
+            }
+        }
+    }
+
+    /// Retries sources that aren't being captured, such as windows that
+    /// weren't open yet or have closed, and re-evaluates the redaction rules
+    /// of display layers, for as long as the captures exist.
+    pub fn spawn_supervisor(self: &Arc<Self>) {
+        let this = Arc::downgrade(self);
+        std::thread::Builder::new()
+            .name("layers".to_string())
+            .spawn(move || run_supervisor(this))
+            .unwrap();
+    }
+}
+
+fn run_supervisor(captures: Weak<LayerCaptures>) {
+    loop {
+        std::thread::sleep(RETRY_INTERVAL);
+        let Some(captures) = captures.upgrade() else {
+            return;
+        };
+        for capture in &captures.captures {
+            capture.start();
+            capture.check_redaction();
+        }
+    }
+}
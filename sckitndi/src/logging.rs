@@ -0,0 +1,38 @@
+use std::{fs::OpenOptions, sync::Mutex};
+
+use anyhow::{Context, Result};
+use tracing_oslog::OsLogger;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer, Registry};
+
+use crate::config::{Config, LogOutput};
+
+const SUBSYSTEM: &str = "com.koba789.sckitndi";
+
+/// Installs the global subscriber described by `config`.
+pub fn init(config: &Config) -> Result<()> {
+    let filter = EnvFilter::try_new(&config.log_filter).context("Invalid SCKITNDI_LOG")?;
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    for output in &config.log_outputs {
+        let layer = match output {
+            LogOutput::Stderr => fmt::layer().with_writer(std::io::stderr).boxed(),
+            LogOutput::OsLog => OsLogger::new(SUBSYSTEM, "default").boxed(),
+            LogOutput::File(path) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .boxed()
+            }
+        };
+        layers.push(layer);
+    }
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()?;
+    Ok(())
+}
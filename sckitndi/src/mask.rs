@@ -0,0 +1,556 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{frame::Frame, geometry::Rect, pacer::VideoSink, pip::PipCompositor, pool::FramePool};
+
+const DEFAULT_BLOCK_SIZE: usize = 16;
+const DEFAULT_BLUR_RADIUS: usize = 8;
+
+/// An opaque sRGB color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+
+    fn bgra(&self) -> [u8; 4] {
+        [self.b, self.g, self.r, 0xff]
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// Parses `#rrggbb` or `rrggbb`.
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            bail!("Expected a color as #rrggbb, got {:?}", s);
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16).with_context(|| format!("Invalid color {:?}", s))
+        };
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "style", rename_all = "snake_case")]
+pub enum MaskStyle {
+    Fill {
+        color: Color,
+    },
+    /// Replaces each `block_size` square, aligned to the mask's corner, with
+    /// its average.
+    Pixelate {
+        block_size: usize,
+    },
+    /// Averages each pixel with its neighbours up to `radius` away, using only
+    /// pixels inside the mask.
+    Blur {
+        radius: usize,
+    },
+}
+
+/// A region of the output frame to hide.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Mask {
+    /// In output pixels.
+    pub rect: Rect,
+    #[serde(flatten)]
+    pub style: MaskStyle,
+}
+
+impl FromStr for Mask {
+    type Err = anyhow::Error;
+
+    /// Parses `<style>:x,y,width,height`, where the style is `fill`,
+    /// `fill=#rrggbb`, `pixelate`, `pixelate=<block size>`, `blur` or
+    /// `blur=<radius>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (style, rect) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected <style>:x,y,width,height, got {:?}", s))?;
+        let (name, param) = match style.split_once('=') {
+            Some((name, param)) => (name, Some(param)),
+            None => (style, None),
+        };
+        let size = |default: usize| -> Result<usize> {
+            let size = param.map_or(Ok(default), |param| {
+                param
+                    .parse()
+                    .with_context(|| format!("Invalid {} size {:?}", name, param))
+            })?;
+            if size == 0 {
+                bail!("{} size must be positive", name);
+            }
+            Ok(size)
+        };
+        let style = match name {
+            "fill" => MaskStyle::Fill {
+                color: param.map_or(Ok(Color::BLACK), str::parse)?,
+            },
+            "pixelate" => MaskStyle::Pixelate {
+                block_size: size(DEFAULT_BLOCK_SIZE)?,
+            },
+            "blur" => MaskStyle::Blur {
+                radius: size(DEFAULT_BLUR_RADIUS)?,
+            },
+            _ => bail!(
+                "Unknown mask style {:?}; expected fill, pixelate or blur",
+                name
+            ),
+        };
+        let rect = crate::config::parse_rect(rect)?;
+        Ok(Mask { rect, style })
+    }
+}
+
+/// A mask's rect clipped to the frame, as pixel bounds.
+struct Bounds {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Bounds {
+    fn clip(rect: &Rect, width: usize, height: usize) -> Option<Self> {
+        let rect = rect.intersection(&Rect::from_size(width as f64, height as f64))?;
+        let bounds = Bounds {
+            left: rect.x.round() as usize,
+            top: rect.y.round() as usize,
+            right: ((rect.x + rect.width).round() as usize).min(width),
+            bottom: ((rect.y + rect.height).round() as usize).min(height),
+        };
+        (bounds.left < bounds.right && bounds.top < bounds.bottom).then_some(bounds)
+    }
+
+    fn width(&self) -> usize {
+        self.right - self.left
+    }
+
+    fn height(&self) -> usize {
+        self.bottom - self.top
+    }
+}
+
+/// Applies `masks` to a BGRA frame in order.
+pub fn apply(frame: &mut Frame, masks: &[Mask]) {
+    let (width, height, stride) = (frame.width(), frame.height(), frame.stride());
+    for mask in masks {
+        let Some(bounds) = Bounds::clip(&mask.rect, width, height) else {
+            continue;
+        };
+        let data = frame.data_mut();
+        match mask.style {
+            MaskStyle::Fill { color } => fill(data, stride, &bounds, color.bgra()),
+            MaskStyle::Pixelate { block_size } => pixelate(data, stride, &bounds, block_size),
+            MaskStyle::Blur { radius } => blur(data, stride, &bounds, radius),
+        }
+    }
+}
+
+fn pixel_range(stride: usize, x: usize, y: usize, len: usize) -> std::ops::Range<usize> {
+    let start = y * stride + x * 4;
+    start..start + len * 4
+}
+
+fn fill(data: &mut [u8], stride: usize, bounds: &Bounds, bgra: [u8; 4]) {
+    for y in bounds.top..bounds.bottom {
+        for pixel in data[pixel_range(stride, bounds.left, y, bounds.width())].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&bgra);
+        }
+    }
+}
+
+fn pixelate(data: &mut [u8], stride: usize, bounds: &Bounds, block_size: usize) {
+    let block_size = block_size.max(1);
+    for top in (bounds.top..bounds.bottom).step_by(block_size) {
+        let bottom = (top + block_size).min(bounds.bottom);
+        for left in (bounds.left..bounds.right).step_by(block_size) {
+            let right = (left + block_size).min(bounds.right);
+            let block = Bounds {
+                left,
+                top,
+                right,
+                bottom,
+            };
+            let mut sum = [0u64; 4];
+            for y in top..bottom {
+                for pixel in data[pixel_range(stride, left, y, block.width())].chunks_exact(4) {
+                    for (sum, &value) in sum.iter_mut().zip(pixel) {
+                        *sum += value as u64;
+                    }
+                }
+            }
+            let count = (block.width() * block.height()) as u64;
+            let average = sum.map(|sum| ((sum + count / 2) / count) as u8);
+            fill(data, stride, &block, average);
+        }
+    }
+}
+
+/// A separable box blur: a horizontal pass, then a vertical one. Near the
+/// edges of the mask the window shrinks rather than reaching outside it.
+fn blur(data: &mut [u8], stride: usize, bounds: &Bounds, radius: usize) {
+    let (width, height) = (bounds.width(), bounds.height());
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in bounds.top..bounds.bottom {
+        pixels.extend_from_slice(&data[pixel_range(stride, bounds.left, y, width)]);
+    }
+    let mut line = Vec::new();
+    for y in 0..height {
+        line.clear();
+        line.extend_from_slice(&pixels[y * width * 4..(y + 1) * width * 4]);
+        box_average(&line, width, radius, |x, value| {
+            pixels[(y * width + x) * 4..][..4].copy_from_slice(&value)
+        });
+    }
+    for x in 0..width {
+        line.clear();
+        for y in 0..height {
+            line.extend_from_slice(&pixels[(y * width + x) * 4..][..4]);
+        }
+        box_average(&line, height, radius, |y, value| {
+            data[pixel_range(stride, bounds.left + x, bounds.top + y, 1)].copy_from_slice(&value)
+        });
+    }
+}
+
+/// Calls `out` with the average of each of the `len` pixels in `line` and its
+/// neighbours within `radius`, using a running sum.
+fn box_average(line: &[u8], len: usize, radius: usize, mut out: impl FnMut(usize, [u8; 4])) {
+    let pixel = |i: usize| &line[i * 4..(i + 1) * 4];
+    let mut sum = [0u64; 4];
+    let mut window = 0..0;
+    for i in 0..len {
+        let wanted = i.saturating_sub(radius)..(i + radius + 1).min(len);
+        while window.end < wanted.end {
+            for (sum, &value) in sum.iter_mut().zip(pixel(window.end)) {
+                *sum += value as u64;
+            }
+            window.end += 1;
+        }
+        while window.start < wanted.start {
+            for (sum, &value) in sum.iter_mut().zip(pixel(window.start)) {
+                *sum -= value as u64;
+            }
+            window.start += 1;
+        }
+        let count = window.len() as u64;
+        out(i, sum.map(|sum| ((sum + count / 2) / count) as u8));
+    }
+}
+
+/// Draws picture-in-picture layers and then the masks onto a copy of each
+/// outgoing frame, so that the masks hide the layers too and repeated frames
+/// still show the current layers.
+pub struct MaskSink {
+    pip: Option<PipCompositor>,
+    /// Those of the current source.
+    masks: Arc<Mutex<Vec<Mask>>>,
+    /// Holds the copies drawn on.
+    pool: FramePool,
+    inner: Arc<dyn VideoSink>,
+}
+
+impl MaskSink {
+    pub fn new(
+        pip: Option<PipCompositor>,
+        masks: Arc<Mutex<Vec<Mask>>>,
+        pool: FramePool,
+        inner: Arc<dyn VideoSink>,
+    ) -> Self {
+        Self {
+            pip,
+            masks,
+            pool,
+            inner,
+        }
+    }
+}
+
+impl VideoSink for MaskSink {
+    fn send_video(&self, frame: Arc<Frame>) {
+        let masks = self.masks.lock().unwrap().clone();
+        if self.pip.is_none() && masks.is_empty() {
+            self.inner.send_video(frame);
+            return;
+        }
+        let mut frame = frame.copy_into(Some(&self.pool));
+        if let Some(pip) = &self.pip {
+            pip.composite(&mut frame);
+        }
+        apply(&mut frame, &masks);
+        self.inner.send_video(Arc::new(frame));
+    }
+
+    fn send_idle(&self) {
+        self.inner.send_idle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        metrics::Gauge,
+        pacer::testing::Recorder,
+        pip::{LayerFeed, LayerSource, LayerSpec},
+        scale::Fit,
+    };
+
+    const PADDING: u8 = 0xaa;
+
+    /// A frame of gray pixels with `values` in row order and a padded stride.
+    fn gray_frame(width: usize, values: &[u8]) -> Frame {
+        let height = values.len() / width;
+        let stride = width * 4 + 8;
+        let mut data = vec![PADDING; stride * height];
+        for (i, &value) in values.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            data[pixel_range(stride, x, y, 1)].copy_from_slice(&[value, value, value, 0xff]);
+        }
+        Frame::with_buffer(width, height, stride, data, None)
+    }
+
+    fn pixels(frame: &Frame) -> Vec<[u8; 4]> {
+        let (width, stride) = (frame.width(), frame.stride());
+        (0..frame.height())
+            .flat_map(|y| {
+                let row = &frame.data()[y * stride..][..stride];
+                assert!(row[width * 4..].iter().all(|&b| b == PADDING));
+                row[..width * 4]
+                    .chunks_exact(4)
+                    .map(|pixel| pixel.try_into().unwrap())
+            })
+            .collect()
+    }
+
+    /// The gray levels of a frame, checking that it is still gray and opaque.
+    fn grays(frame: &Frame) -> Vec<u8> {
+        pixels(frame)
+            .into_iter()
+            .map(|[b, g, r, a]| {
+                assert!(b == g && g == r && a == 0xff, "{:?}", [b, g, r, a]);
+                b
+            })
+            .collect()
+    }
+
+    fn mask(x: f64, y: f64, width: f64, height: f64, style: MaskStyle) -> Mask {
+        Mask {
+            rect: Rect::new(x, y, width, height),
+            style,
+        }
+    }
+
+    const RED: MaskStyle = MaskStyle::Fill {
+        color: Color { r: 255, g: 0, b: 0 },
+    };
+
+    #[test]
+    fn fills_the_rect_with_the_color() {
+        let mut frame = gray_frame(4, &[1; 12]);
+        apply(&mut frame, &[mask(1., 1., 2., 1., RED)]);
+        let g = [1, 1, 1, 0xff];
+        let r = [0, 0, 255, 0xff];
+        assert_eq!(pixels(&frame), [g, g, g, g, g, r, r, g, g, g, g, g]);
+    }
+
+    #[test]
+    fn clips_masks_to_the_frame() {
+        let mut frame = gray_frame(3, &[1; 6]);
+        let black = MaskStyle::Fill {
+            color: Color::BLACK,
+        };
+        apply(
+            &mut frame,
+            &[
+                mask(-1., -1., 2., 2., black),
+                mask(2.6, 0., 10., 10., black),
+                mask(5., 5., 1., 1., black),
+            ],
+        );
+        assert_eq!(grays(&frame), [0, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn rounds_fractional_rects_to_pixels() {
+        let mut frame = gray_frame(4, &[1; 4]);
+        apply(
+            &mut frame,
+            &[mask(
+                0.4,
+                0.,
+                1.2,
+                1.,
+                MaskStyle::Fill {
+                    color: Color::BLACK,
+                },
+            )],
+        );
+        assert_eq!(grays(&frame), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn pixelates_blocks_aligned_to_the_mask() {
+        #[rustfmt::skip]
+        let mut frame = gray_frame(5, &[
+            9, 0, 10, 20, 9,
+            9, 40, 50, 60, 9,
+            9, 1, 2, 3, 9,
+        ]);
+        apply(
+            &mut frame,
+            &[mask(1., 0., 3., 3., MaskStyle::Pixelate { block_size: 2 })],
+        );
+        #[rustfmt::skip]
+        assert_eq!(grays(&frame), [
+            9, 25, 25, 40, 9,
+            9, 25, 25, 40, 9,
+            // Halves round up.
+            9, 2, 2, 3, 9,
+        ]);
+    }
+
+    #[test]
+    fn blurs_with_a_box_of_the_radius() {
+        #[rustfmt::skip]
+        let mut frame = gray_frame(3, &[
+            0, 0, 0,
+            0, 90, 0,
+            0, 0, 0,
+        ]);
+        apply(
+            &mut frame,
+            &[mask(0., 0., 3., 3., MaskStyle::Blur { radius: 1 })],
+        );
+        #[rustfmt::skip]
+        assert_eq!(grays(&frame), [
+            23, 15, 23,
+            15, 10, 15,
+            23, 15, 23,
+        ]);
+    }
+
+    #[test]
+    fn blurs_only_with_pixels_inside_the_mask() {
+        let mut frame = gray_frame(6, &[90, 0, 0, 60, 0, 90]);
+        apply(
+            &mut frame,
+            &[mask(1., 0., 4., 1., MaskStyle::Blur { radius: 1 })],
+        );
+        assert_eq!(grays(&frame), [90, 0, 20, 20, 30, 90]);
+    }
+
+    #[test]
+    fn applies_masks_in_order() {
+        let mut frame = gray_frame(2, &[0, 100]);
+        apply(
+            &mut frame,
+            &[
+                mask(0., 0., 1., 1., RED),
+                mask(0., 0., 2., 1., MaskStyle::Pixelate { block_size: 2 }),
+            ],
+        );
+        assert_eq!(pixels(&frame), [[50, 50, 178, 0xff]; 2]);
+    }
+
+    #[test]
+    fn parses_masks() {
+        assert_eq!(
+            "fill=#ff0000:1,1,2,1".parse::<Mask>().unwrap(),
+            mask(1., 1., 2., 1., RED)
+        );
+        assert_eq!(
+            "pixelate:0,0,10,10".parse::<Mask>().unwrap().style,
+            MaskStyle::Pixelate {
+                block_size: DEFAULT_BLOCK_SIZE
+            }
+        );
+        assert_eq!(
+            "blur=3:0,0,10,10".parse::<Mask>().unwrap().style,
+            MaskStyle::Blur { radius: 3 }
+        );
+        for invalid in [
+            "fill",
+            "blur=0:0,0,1,1",
+            "swirl:0,0,1,1",
+            "fill=#12:0,0,1,1",
+        ] {
+            assert!(invalid.parse::<Mask>().is_err(), "{}", invalid);
+        }
+    }
+
+    struct Still(Arc<Frame>);
+
+    impl LayerFeed for Still {
+        fn latest(&self) -> Option<Arc<Frame>> {
+            Some(self.0.clone())
+        }
+    }
+
+    fn mask_sink(pip: Option<PipCompositor>, masks: Vec<Mask>) -> (MaskSink, Arc<Recorder>) {
+        let recorder = Arc::new(Recorder::default());
+        let pool = FramePool::new(1, Arc::new(Gauge::default()));
+        let sink = MaskSink::new(pip, Arc::new(Mutex::new(masks)), pool, recorder.clone());
+        (sink, recorder)
+    }
+
+    #[test]
+    fn masks_picture_in_picture_layers() {
+        let layer = LayerSpec {
+            source: LayerSource::Display(1),
+            x: 0.5,
+            y: 0.,
+            width: 0.5,
+            height: 1.,
+            z: 0,
+            border: None,
+            fit: Fit::Stretch,
+        };
+        let feed: Arc<dyn LayerFeed> = Arc::new(Still(Arc::new(gray_frame(2, &[200; 4]))));
+        let pool = FramePool::new(1, Arc::new(Gauge::default()));
+        let pip = PipCompositor::new([(layer, feed)], pool);
+        let mask = Mask {
+            rect: Rect::new(1., 0., 2., 1.),
+            style: MaskStyle::Fill {
+                color: Color::BLACK,
+            },
+        };
+        let (sink, recorder) = mask_sink(Some(pip), vec![mask]);
+        let frame = Arc::new(gray_frame(4, &[10; 8]));
+        sink.send_video(frame.clone());
+        assert_eq!(grays(&frame), [10; 8]);
+        let sent = recorder.take().pop().unwrap();
+        assert_eq!(grays(&sent), [10, 0, 0, 200, 10, 10, 200, 200]);
+    }
+
+    #[test]
+    fn passes_frames_through_without_layers_or_masks() {
+        let (sink, recorder) = mask_sink(None, Vec::new());
+        let frame = Arc::new(gray_frame(2, &[10; 2]));
+        sink.send_video(frame.clone());
+        assert!(Arc::ptr_eq(&recorder.frames()[0], &frame));
+
+        *sink.masks.lock().unwrap() = vec!["fill:0,0,1,1".parse().unwrap()];
+        sink.send_video(frame.clone());
+        assert_eq!(grays(&recorder.frames()[1]), [0, 10]);
+    }
+}
@@ -0,0 +1,378 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{command::Target, geometry::Rect};
+
+pub const PRODUCT_NAME: &str = "ScreenCaptureKit2NDI";
+pub const PRODUCT_SHORT_NAME: &str = "SCKitNDI";
+pub const PRODUCT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Escapes `&`, `<`, `>`, `"` and `'` for use in XML text and attribute
+/// values.
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn unescape(s: &str) -> Result<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| anyhow!("Unterminated entity in {:?}", s))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| anyhow!("Unknown entity &{};", entity))?
+            }
+        };
+        unescaped.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+/// A start or empty-element tag read by [`parse_tags`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn parse_attr<T: FromStr>(&self, name: &str) -> Result<T> {
+        let value = self
+            .attr(name)
+            .ok_or_else(|| anyhow!("<{}> is missing {:?}", self.name, name))?;
+        value
+            .parse()
+            .map_err(|_| anyhow!("<{}> has invalid {}={:?}", self.name, name, value))
+    }
+}
+
+fn parse_tag(tag: &str) -> Result<Tag> {
+    let tag = tag.strip_suffix('/').unwrap_or(tag).trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = &tag[..name_end];
+    if name.is_empty() {
+        bail!("Empty tag name");
+    }
+    let mut attributes = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| anyhow!("Attribute without value in <{}>", name))?;
+        let attr_name = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| anyhow!("Unquoted attribute {:?} in <{}>", attr_name, name))?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| anyhow!("Unterminated attribute {:?} in <{}>", attr_name, name))?;
+        attributes.push((attr_name.to_string(), unescape(&value[1..end + 1])?));
+        rest = value[end + 2..].trim_start();
+    }
+    Ok(Tag {
+        name: name.to_string(),
+        attributes,
+    })
+}
+
+/// The index of the `>` that closes a tag, skipping any inside quoted
+/// attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    tag.char_indices().find_map(|(i, c)| {
+        match (quote, c) {
+            (None, '>') => return Some(i),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+        None
+    })
+}
+
+/// Reads the start and empty-element tags of an XML fragment in document
+/// order, ignoring text, end tags, comments and processing instructions.
+///
+/// NDI metadata frames are small flat fragments, so nesting isn't tracked.
+pub fn parse_tags(xml: &str) -> Result<Vec<Tag>> {
+    let mut tags = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            let end = comment
+                .find("-->")
+                .ok_or_else(|| anyhow!("Unterminated comment"))?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = tag_end(rest).ok_or_else(|| anyhow!("Unterminated tag"))?;
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        tags.push(parse_tag(tag)?);
+    }
+    Ok(tags)
+}
+
+/// A minimal XML element builder; `Display` renders it without whitespace.
+#[derive(Debug, Clone)]
+pub struct Element {
+    name: &'static str,
+    attributes: Vec<(&'static str, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            attributes: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn attr(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.attributes.push((name, value.to_string()));
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for (name, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+        if self.children.is_empty() {
+            return f.write_str("/>");
+        }
+        f.write_str(">")?;
+        for child in &self.children {
+            write!(f, "{}", child)?;
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
+
+/// What is being captured, as advertised to NDI receivers.
+#[derive(Debug, Clone)]
+pub struct CaptureInfo {
+    pub target: Target,
+    pub crop: Rect,
+    pub excluded_applications: usize,
+    pub redacted_windows: usize,
+}
+
+/// The standard `ndi_product` connection metadata.
+pub fn product() -> Element {
+    Element::new("ndi_product")
+        .attr("long_name", PRODUCT_NAME)
+        .attr("short_name", PRODUCT_SHORT_NAME)
+        .attr("manufacturer", "KOBA789")
+        .attr("version", PRODUCT_VERSION)
+        .attr("model_name", "sckitndi")
+}
+
+pub fn capture(info: &CaptureInfo) -> Element {
+    let target = match &info.target {
+        Target::Display(id) => Element::new("display").attr("id", id),
+        Target::Window(id) => Element::new("window").attr("id", id),
+    };
+    Element::new("sckitndi_capture")
+        .child(target)
+        .child(
+            Element::new("crop")
+                .attr("x", info.crop.x)
+                .attr("y", info.crop.y)
+                .attr("width", info.crop.width)
+                .attr("height", info.crop.height),
+        )
+        .child(Element::new("excluded_applications").attr("count", info.excluded_applications))
+        .child(Element::new("redacted_windows").attr("count", info.redacted_windows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert!(matches!(escape("plain text"), Cow::Borrowed("plain text")));
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn unescapes_entities() {
+        assert_eq!(
+            unescape("&lt;&gt;&amp;&quot;&apos;&#65;&#x42;").unwrap(),
+            "<>&\"'AB"
+        );
+        assert!(unescape("a & b").is_err());
+        assert!(unescape("&nbsp;").is_err());
+        assert!(unescape("&#xD800;").is_err());
+    }
+
+    #[test]
+    fn parses_tags_and_attributes() {
+        let tags =
+            parse_tags(r#"<?xml version="1.0"?><!-- <pause/> --><a x="1" y = '2'>text</a><b/>"#)
+                .unwrap();
+        assert_eq!(
+            tags,
+            [
+                Tag {
+                    name: "a".to_string(),
+                    attributes: vec![
+                        ("x".to_string(), "1".to_string()),
+                        ("y".to_string(), "2".to_string())
+                    ],
+                },
+                Tag {
+                    name: "b".to_string(),
+                    attributes: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_markup_inside_quoted_values() {
+        let tags = parse_tags(r#"<a title="x > y" other='"/>'/><b/>"#).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].attr("title"), Some("x > y"));
+        assert_eq!(tags[0].attr("other"), Some("\"/>"));
+        assert_eq!(tags[1].name, "b");
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        assert!(parse_tags("<a").is_err());
+        assert!(parse_tags(r#"<a x="1>"#).is_err());
+        assert!(parse_tags("<a x=1/>").is_err());
+        assert!(parse_tags("<a x/>").is_err());
+        assert!(parse_tags("</>< >").is_err());
+        assert!(parse_tags("<!-- open").is_err());
+        assert!(parse_tags(r#"<a x="&bogus;"/>"#).is_err());
+    }
+
+    #[test]
+    fn parses_typed_attributes() {
+        let tag = &parse_tags(r#"<switch_display id="3" name="x"/>"#).unwrap()[0];
+        assert_eq!(tag.parse_attr::<u32>("id").unwrap(), 3);
+        assert!(tag.parse_attr::<u32>("name").is_err());
+        assert!(tag.parse_attr::<u32>("missing").is_err());
+    }
+
+    #[test]
+    fn renders_elements() {
+        let element = Element::new("a")
+            .attr("x", 1)
+            .attr("name", "\"quoted\" & <tagged>")
+            .child(Element::new("b"))
+            .child(Element::new("c").attr("y", 2.5));
+        assert_eq!(
+            element.to_string(),
+            r#"<a x="1" name="&quot;quoted&quot; &amp; &lt;tagged&gt;"><b/><c y="2.5"/></a>"#
+        );
+        assert_eq!(Element::new("empty").to_string(), "<empty/>");
+    }
+
+    #[test]
+    fn round_trips_through_the_parser() {
+        let values = ["plain", "Tom & Jerry's <\"show\">", "", "&amp;", "日本語 ✓"];
+        for value in values {
+            let xml = Element::new("tag").attr("value", value).to_string();
+            let tags = parse_tags(&xml).unwrap();
+            assert_eq!(tags.len(), 1, "{xml}");
+            assert_eq!(tags[0].attr("value"), Some(value), "{xml}");
+        }
+    }
+
+    #[test]
+    fn round_trips_nested_elements() {
+        let info = CaptureInfo {
+            target: Target::Window(42),
+            crop: Rect::new(0., 10., 1280., 720.),
+            excluded_applications: 2,
+            redacted_windows: 1,
+        };
+        let tags = parse_tags(&capture(&info).to_string()).unwrap();
+        let names: Vec<_> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "sckitndi_capture",
+                "window",
+                "crop",
+                "excluded_applications",
+                "redacted_windows"
+            ]
+        );
+        assert_eq!(tags[1].parse_attr::<u32>("id").unwrap(), 42);
+        assert_eq!(tags[2].parse_attr::<f64>("height").unwrap(), 720.);
+        assert_eq!(tags[4].attr("count"), Some("1"));
+    }
+
+    #[test]
+    fn product_metadata_names_the_version() {
+        let tags = parse_tags(&product().to_string()).unwrap();
+        assert_eq!(tags[0].name, "ndi_product");
+        assert_eq!(tags[0].attr("version"), Some(PRODUCT_VERSION));
+    }
+}
@@ -0,0 +1,506 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use sckit::FrameStatus;
+
+/// Bucket upper bounds, in seconds, for per-frame timings.
+pub const FRAME_TIME_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.002, 0.004, 0.008, 0.016, 0.033, 0.066, 0.1, 0.25,
+];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.0.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A histogram with fixed buckets. Bucket counts are stored per bucket and
+/// only made cumulative when rendered.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    /// The bits of an `f64`.
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        debug_assert!(bounds.windows(2).all(|w| w[0] < w[1]));
+        Self {
+            bounds: bounds.to_vec(),
+            // The last bucket is +Inf.
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < value);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+
+    /// The mean of all observations, or `None` before the first one.
+    pub fn mean(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.sum() / count as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+impl Metric {
+    fn kind(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    series: Vec<(String, Metric)>,
+}
+
+/// A set of metrics rendered together in the Prometheus text format.
+///
+/// Registering a name again with different labels adds a series to the same
+/// metric.
+#[derive(Debug, Default)]
+pub struct Registry {
+    families: Mutex<Vec<Family>>,
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Joins two rendered label sets into `{...}`, or nothing if both are empty.
+fn braces(labels: &str, extra: &str) -> String {
+    match (labels.is_empty(), extra.is_empty()) {
+        (true, true) => String::new(),
+        (false, true) => format!("{{{}}}", labels),
+        (true, false) => format!("{{{}}}", extra),
+        (false, false) => format!("{{{},{}}}", labels, extra),
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        metric: Metric,
+    ) {
+        let mut families = self.families.lock().unwrap();
+        let labels = format_labels(labels);
+        match families.iter_mut().find(|family| family.name == name) {
+            Some(family) => {
+                assert_eq!(
+                    family.series[0].1.kind(),
+                    metric.kind(),
+                    "{} registered with different types",
+                    name
+                );
+                assert!(
+                    family.series.iter().all(|(l, _)| *l != labels),
+                    "{}{{{}}} registered twice",
+                    name,
+                    labels
+                );
+                family.series.push((labels, metric));
+            }
+            None => families.push(Family {
+                name,
+                help,
+                series: vec![(labels, metric)],
+            }),
+        }
+    }
+
+    pub fn counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+    ) -> Arc<Counter> {
+        let counter = Arc::new(Counter::default());
+        self.register(name, help, labels, Metric::Counter(counter.clone()));
+        counter
+    }
+
+    pub fn gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+    ) -> Arc<Gauge> {
+        let gauge = Arc::new(Gauge::default());
+        self.register(name, help, labels, Metric::Gauge(gauge.clone()));
+        gauge
+    }
+
+    pub fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        bounds: &[f64],
+    ) -> Arc<Histogram> {
+        let histogram = Arc::new(Histogram::new(bounds));
+        self.register(name, help, labels, Metric::Histogram(histogram.clone()));
+        histogram
+    }
+
+    /// Renders every metric in the Prometheus text exposition format 0.0.4.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for family in families.iter() {
+            let name = family.name;
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.series[0].1.kind());
+            for (labels, metric) in &family.series {
+                match metric {
+                    Metric::Counter(counter) => {
+                        let _ = writeln!(out, "{}{} {}", name, braces(labels, ""), counter.get());
+                    }
+                    Metric::Gauge(gauge) => {
+                        let _ = writeln!(out, "{}{} {}", name, braces(labels, ""), gauge.get());
+                    }
+                    Metric::Histogram(histogram) => {
+                        let bounds = histogram.bounds.iter().copied().chain([f64::INFINITY]);
+                        let mut cumulative = 0;
+                        for (bound, bucket) in bounds.zip(&histogram.buckets) {
+                            cumulative += bucket.load(Ordering::Relaxed);
+                            let le = format!("le=\"{}\"", format_value(bound));
+                            let _ = writeln!(
+                                out,
+                                "{}_bucket{} {}",
+                                name,
+                                braces(labels, &le),
+                                cumulative
+                            );
+                        }
+                        let labels = braces(labels, "");
+                        let _ = writeln!(
+                            out,
+                            "{}_sum{} {}",
+                            name,
+                            labels,
+                            format_value(histogram.sum())
+                        );
+                        let _ = writeln!(out, "{}_count{} {}", name, labels, histogram.count());
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The metrics collected along the capture pipeline.
+pub struct PipelineMetrics {
+    pub registry: Registry,
+    frames_captured: Vec<(FrameStatus, Arc<Counter>)>,
+    pub frames_fresh: Arc<Counter>,
+    pub frames_repeated: Arc<Counter>,
+    /// Captured frames replaced by a newer one before the pacer sent them.
+    pub frames_dropped: Arc<Counter>,
+    pub conversion_time: Arc<Histogram>,
+    pub send_time: Arc<Histogram>,
+    pub buffers_in_use: Arc<Gauge>,
+    pub buffers_capacity: Arc<Gauge>,
+    pub connections: Arc<Gauge>,
+    pub restarts: Arc<Counter>,
+}
+
+impl PipelineMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let frames_captured = FrameStatus::ALL
+            .iter()
+            .map(|&status| {
+                let counter = registry.counter(
+                    "sckitndi_frames_captured_total",
+                    "Sample buffers delivered by ScreenCaptureKit, by SCFrameStatus.",
+                    &[("status", status.name())],
+                );
+                (status, counter)
+            })
+            .collect();
+        let frames_sent = |kind| {
+            registry.counter(
+                "sckitndi_frames_sent_total",
+                "Video frames sent to NDI; repeated frames resend the last capture.",
+                &[("kind", kind)],
+            )
+        };
+        let frames_fresh = frames_sent("fresh");
+        let frames_repeated = frames_sent("repeated");
+        let frames_dropped = registry.counter(
+            "sckitndi_frames_dropped_total",
+            "Captured frames replaced by a newer one before they were sent.",
+            &[],
+        );
+        let conversion_time = registry.histogram(
+            "sckitndi_conversion_seconds",
+            "Time spent copying a captured pixel buffer into a frame.",
+            &[],
+            FRAME_TIME_BUCKETS,
+        );
+        let send_time = registry.histogram(
+            "sckitndi_send_seconds",
+            "Time spent submitting a frame to NDI, including waiting for the previous one.",
+            &[],
+            FRAME_TIME_BUCKETS,
+        );
+        let buffers_in_use = registry.gauge(
+            "sckitndi_frame_buffers_in_use",
+            "Frame buffers currently held by the pipeline.",
+            &[],
+        );
+        let buffers_capacity = registry.gauge(
+            "sckitndi_frame_buffers_capacity",
+            "Frame buffers the pool keeps for reuse.",
+            &[],
+        );
+        let connections = registry.gauge(
+            "sckitndi_ndi_connections",
+            "Receivers currently connected to the NDI sender.",
+            &[],
+        );
+        let restarts = registry.counter(
+            "sckitndi_capture_restarts_total",
+            "Captures restarted by the watchdog after a stall or failure.",
+            &[],
+        );
+        Self {
+            registry,
+            frames_captured,
+            frames_fresh,
+            frames_repeated,
+            frames_dropped,
+            conversion_time,
+            send_time,
+            buffers_in_use,
+            buffers_capacity,
+            connections,
+            restarts,
+        }
+    }
+
+    pub fn frame_captured(&self, status: FrameStatus) {
+        if let Some((_, counter)) = self.frames_captured.iter().find(|(s, _)| *s == status) {
+            counter.inc();
+        }
+    }
+}
+
+impl Default for PipelineMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_gauges() {
+        let registry = Registry::new();
+        let restarts = registry.counter("restarts_total", "Restarts.", &[]);
+        let in_use = registry.gauge("in_use", "Buffers in use.", &[]);
+        restarts.inc();
+        restarts.inc();
+        in_use.set(5);
+        in_use.add(-7);
+        assert_eq!(
+            registry.render(),
+            "# HELP restarts_total Restarts.\n\
+             # TYPE restarts_total counter\n\
+             restarts_total 2\n\
+             # HELP in_use Buffers in use.\n\
+             # TYPE in_use gauge\n\
+             in_use -2\n"
+        );
+    }
+
+    #[test]
+    fn groups_series_of_a_name_under_one_header() {
+        let registry = Registry::new();
+        let fresh = registry.counter("sent_total", "Sent.", &[("kind", "fresh")]);
+        registry.counter("sent_total", "Sent.", &[("kind", "repeated")]);
+        registry.counter("other_total", "Other.", &[("a", "1"), ("b", "2")]);
+        fresh.inc();
+        assert_eq!(
+            registry.render(),
+            "# HELP sent_total Sent.\n\
+             # TYPE sent_total counter\n\
+             sent_total{kind=\"fresh\"} 1\n\
+             sent_total{kind=\"repeated\"} 0\n\
+             # HELP other_total Other.\n\
+             # TYPE other_total counter\n\
+             other_total{a=\"1\",b=\"2\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let registry = Registry::new();
+        registry.gauge("g", "G.", &[("name", "a\\b\"c\nd")]);
+        assert!(registry
+            .render()
+            .contains("g{name=\"a\\\\b\\\"c\\nd\"} 0\n"));
+    }
+
+    #[test]
+    fn renders_cumulative_histogram_buckets() {
+        let registry = Registry::new();
+        let histogram = registry.histogram("t_seconds", "T.", &[], &[0.1, 0.5]);
+        for value in [0.05, 0.1, 0.3, 2.] {
+            histogram.observe(value);
+        }
+        assert_eq!(
+            registry.render(),
+            "# HELP t_seconds T.\n\
+             # TYPE t_seconds histogram\n\
+             t_seconds_bucket{le=\"0.1\"} 2\n\
+             t_seconds_bucket{le=\"0.5\"} 3\n\
+             t_seconds_bucket{le=\"+Inf\"} 4\n\
+             t_seconds_sum 2.45\n\
+             t_seconds_count 4\n"
+        );
+        assert_eq!(histogram.mean(), Some(2.45 / 4.));
+    }
+
+    #[test]
+    fn adds_bucket_bounds_to_histogram_labels() {
+        let registry = Registry::new();
+        let histogram = registry.histogram("t_seconds", "T.", &[("sink", "main")], &[1.]);
+        histogram.observe_duration(Duration::from_millis(1500));
+        assert!(registry.render().ends_with(
+            "t_seconds_bucket{sink=\"main\",le=\"1\"} 0\n\
+             t_seconds_bucket{sink=\"main\",le=\"+Inf\"} 1\n\
+             t_seconds_sum{sink=\"main\"} 1.5\n\
+             t_seconds_count{sink=\"main\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn has_no_mean_before_the_first_observation() {
+        assert_eq!(Histogram::new(FRAME_TIME_BUCKETS).mean(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered with different types")]
+    fn rejects_a_name_with_another_type() {
+        let registry = Registry::new();
+        registry.counter("m", "M.", &[("a", "1")]);
+        registry.gauge("m", "M.", &[("a", "2")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered twice")]
+    fn rejects_duplicate_series() {
+        let registry = Registry::new();
+        registry.counter("m", "M.", &[("a", "1")]);
+        registry.counter("m", "M.", &[("a", "1")]);
+    }
+
+    #[test]
+    fn counts_captured_frames_by_status() {
+        let metrics = PipelineMetrics::new();
+        metrics.frame_captured(FrameStatus::Complete);
+        metrics.frame_captured(FrameStatus::Complete);
+        metrics.frame_captured(FrameStatus::Idle);
+        let rendered = metrics.registry.render();
+        assert!(rendered.contains("sckitndi_frames_captured_total{status=\"complete\"} 2\n"));
+        assert!(rendered.contains("sckitndi_frames_captured_total{status=\"idle\"} 1\n"));
+        assert!(rendered.contains("sckitndi_frames_captured_total{status=\"blank\"} 0\n"));
+    }
+}
@@ -1,37 +1,208 @@
-use std::{ffi::CString, ptr::null};
+use std::{
+    ffi::{CStr, CString},
+    ptr::{null, null_mut},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    frame::{Frame, FrameRate, PixelFormat},
+    pacer::VideoSink,
+    pool::InFlight,
+    remote::MetadataSource,
+    tally::{Tally, TallySource},
+    timing::{TimecodeMode, Timing, TIMECODE_SYNTHESIZE},
+};
 
 pub struct Sender {
     ndi_send_instance: ndi_sys::NDIlib_send_instance_t,
+    frame_rate: FrameRate,
+    timing: Timing,
+    frame_metadata: Mutex<Option<Arc<CString>>>,
+    in_flight: InFlight<(Arc<Frame>, Option<Arc<CString>>)>,
 }
 
 unsafe impl Sync for Sender {}
 unsafe impl Send for Sender {}
 
 impl Sender {
-    pub fn new(ndi_name: &str) -> Self {
-        let ndi_name = CString::new(ndi_name).unwrap();
+    pub fn new(ndi_name: &str, frame_rate: FrameRate, timecode_mode: TimecodeMode) -> Result<Self> {
+        let c_ndi_name = CString::new(ndi_name).context("NDI name contains a NUL byte")?;
         let send_decr = ndi_sys::NDIlib_send_create_t {
-            p_ndi_name: ndi_name.as_ptr(),
+            p_ndi_name: c_ndi_name.as_ptr(),
             p_groups: null(),
             clock_video: false,
             clock_audio: false,
         };
         let send_instance = unsafe { ndi_sys::NDIlib_send_create(&send_decr) };
-        Self {
+        if send_instance.is_null() {
+            return Err(anyhow!("Failed to create NDI sender {:?}", ndi_name));
+        }
+        tracing::info!(ndi_name, "created NDI sender");
+        Ok(Self {
             ndi_send_instance: send_instance,
+            frame_rate,
+            timing: Timing::new(timecode_mode),
+            frame_metadata: Mutex::new(None),
+            in_flight: InFlight::new(),
+        })
+    }
+
+    /// Replaces the metadata sent to every receiver when it connects.
+    pub fn set_connection_metadata<'a>(&self, elements: impl IntoIterator<Item = &'a str>) {
+        unsafe { ndi_sys::NDIlib_send_clear_connection_metadata(self.ndi_send_instance) };
+        for element in elements {
+            let Some(data) = metadata_string(element) else {
+                continue;
+            };
+            let metadata_frame = ndi_sys::NDIlib_metadata_frame_t {
+                p_data: data.as_ptr() as *mut _,
+                timecode: TIMECODE_SYNTHESIZE,
+                ..Default::default()
+            };
+            unsafe {
+                ndi_sys::NDIlib_send_add_connection_metadata(
+                    self.ndi_send_instance,
+                    &metadata_frame,
+                )
+            };
         }
     }
 
-    pub unsafe fn send_video(&self, width: i32, height: i32, data: *mut u8) {
-        let video_frame = ndi_sys::NDIlib_video_frame_v2_t {
-            xres: width,
-            yres: height,
-            FourCC: ndi_sys::NDIlib_FourCC_video_type_e::NDIlib_FourCC_video_type_BGRX,
+    pub fn send_metadata(&self, element: &str) {
+        let Some(data) = metadata_string(element) else {
+            return;
+        };
+        let metadata_frame = ndi_sys::NDIlib_metadata_frame_t {
+            p_data: data.as_ptr() as *mut _,
+            timecode: TIMECODE_SYNTHESIZE,
+            ..Default::default()
+        };
+        unsafe { ndi_sys::NDIlib_send_send_metadata(self.ndi_send_instance, &metadata_frame) };
+    }
+
+    /// Sets the metadata attached to every subsequent video frame.
+    pub fn set_frame_metadata(&self, element: Option<&str>) {
+        let data = element.and_then(metadata_string).map(Arc::new);
+        *self.frame_metadata.lock().unwrap() = data;
+    }
+
+    fn video_frame(
+        &self,
+        frame: &Frame,
+        metadata: Option<&CString>,
+    ) -> ndi_sys::NDIlib_video_frame_v2_t {
+        ndi_sys::NDIlib_video_frame_v2_t {
+            xres: frame.width() as i32,
+            yres: frame.height() as i32,
+            FourCC: match frame.format() {
+                PixelFormat::Bgra => {
+                    ndi_sys::NDIlib_FourCC_video_type_e::NDIlib_FourCC_video_type_BGRX
+                }
+                PixelFormat::Uyvy => {
+                    ndi_sys::NDIlib_FourCC_video_type_e::NDIlib_FourCC_video_type_UYVY
+                }
+            },
+            frame_rate_N: self.frame_rate.numerator() as i32,
+            frame_rate_D: self.frame_rate.denominator() as i32,
             frame_format_type:
                 ndi_sys::NDIlib_frame_format_type_e::NDIlib_frame_format_type_progressive,
-            p_data: data,
+            timecode: self.timing.timecode(frame.timestamp()),
+            p_data: frame.data().as_ptr() as *mut u8,
+            __bindgen_anon_1: ndi_sys::NDIlib_video_frame_v2_t__bindgen_ty_1 {
+                line_stride_in_bytes: frame.stride() as i32,
+            },
+            p_metadata: metadata.map_or(null(), |metadata| metadata.as_ptr()),
+            timestamp: frame.timestamp().unwrap_or(0),
             ..Default::default()
+        }
+    }
+
+    pub fn send_video_async(&self, frame: Arc<Frame>) {
+        let metadata = self.frame_metadata.lock().unwrap().clone();
+        self.in_flight
+            .submit((frame, metadata), |(frame, metadata)| {
+                let video_frame = self.video_frame(frame, metadata.as_deref());
+                unsafe {
+                    ndi_sys::NDIlib_send_send_video_async_v2(self.ndi_send_instance, &video_frame);
+                }
+            });
+    }
+
+    /// The number of receivers currently connected.
+    pub fn connections(&self) -> usize {
+        let connections =
+            unsafe { ndi_sys::NDIlib_send_get_no_connections(self.ndi_send_instance, 0) };
+        connections.max(0) as usize
+    }
+
+    /// Waits for the pending asynchronous send and releases its frame.
+    pub fn flush(&self) {
+        self.in_flight.flush(|| unsafe {
+            ndi_sys::NDIlib_send_send_video_async_v2(self.ndi_send_instance, null_mut());
+        });
+    }
+}
+
+fn metadata_string(element: &str) -> Option<CString> {
+    match CString::new(element) {
+        Ok(data) => Some(data),
+        Err(_) => {
+            tracing::warn!("dropping metadata containing a NUL byte: {:?}", element);
+            None
+        }
+    }
+}
+
+impl VideoSink for Sender {
+    fn send_video(&self, frame: Arc<Frame>) {
+        self.send_video_async(frame);
+    }
+}
+
+impl TallySource for Sender {
+    fn wait_for_tally(&self, timeout: Duration) -> Option<Tally> {
+        let mut tally = ndi_sys::NDIlib_tally_t::default();
+        let changed = unsafe {
+            ndi_sys::NDIlib_send_get_tally(
+                self.ndi_send_instance,
+                &mut tally,
+                timeout.as_millis() as u32,
+            )
+        };
+        changed.then_some(Tally {
+            on_program: tally.on_program,
+            on_preview: tally.on_preview,
+        })
+    }
+}
+
+impl MetadataSource for Sender {
+    fn capture_metadata(&self, timeout: Duration) -> Option<String> {
+        let mut metadata_frame = ndi_sys::NDIlib_metadata_frame_t::default();
+        let frame_type = unsafe {
+            ndi_sys::NDIlib_send_capture(
+                self.ndi_send_instance,
+                &mut metadata_frame,
+                timeout.as_millis() as u32,
+            )
         };
-        ndi_sys::NDIlib_send_send_video_v2(self.ndi_send_instance, &video_frame);
+        if frame_type != ndi_sys::NDIlib_frame_type_e::NDIlib_frame_type_metadata {
+            return None;
+        }
+        let data = unsafe { CStr::from_ptr(metadata_frame.p_data) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { ndi_sys::NDIlib_send_free_metadata(self.ndi_send_instance, &metadata_frame) };
+        Some(data)
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.flush();
+        unsafe { ndi_sys::NDIlib_send_destroy(self.ndi_send_instance) };
     }
 }
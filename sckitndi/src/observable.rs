@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+
+type Observer<T> = Arc<dyn Fn(&T) + Send + Sync>;
+
+/// A value that notifies its observers whenever it changes.
+pub struct Observable<T> {
+    value: Mutex<T>,
+    observers: Mutex<Vec<Observer<T>>>,
+}
+
+impl<T: Clone + PartialEq> Observable<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: Mutex::new(value),
+            observers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        self.value.lock().unwrap().clone()
+    }
+
+    /// Returns whether the value changed.
+    pub fn set(&self, value: T) -> bool {
+        {
+            let mut current = self.value.lock().unwrap();
+            if *current == value {
+                return false;
+            }
+            *current = value.clone();
+        }
+        // Observers may subscribe or set the value themselves, so they run
+        // on a copy of the list.
+        let observers = self.observers.lock().unwrap().clone();
+        for observer in &observers {
+            observer(&value);
+        }
+        true
+    }
+
+    pub fn subscribe(&self, observer: impl Fn(&T) + Send + Sync + 'static) {
+        self.observers.lock().unwrap().push(Arc::new(observer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_only_on_change() {
+        let observable = Observable::new(1);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        {
+            let seen = seen.clone();
+            observable.subscribe(move |value| seen.lock().unwrap().push(*value));
+        }
+        assert!(observable.set(2));
+        assert!(!observable.set(2));
+        assert!(observable.set(3));
+        assert_eq!(*seen.lock().unwrap(), [2, 3]);
+        assert_eq!(observable.get(), 3);
+    }
+
+    #[test]
+    fn observers_may_use_the_observable() {
+        let observable = Arc::new(Observable::new(0));
+        {
+            let inner = observable.clone();
+            observable.subscribe(move |value| {
+                inner.subscribe(|_| {});
+                if *value < 3 {
+                    inner.set(value + 1);
+                }
+                assert_eq!(inner.get(), (*value).max(3));
+            });
+        }
+        observable.set(1);
+        assert_eq!(observable.get(), 3);
+    }
+}
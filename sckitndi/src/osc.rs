@@ -0,0 +1,634 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::warn;
+
+use crate::{command::Command, control::Controller, geometry::Rect, status::Status, tally::Tally};
+
+const PREFIX: &str = "/sckitndi";
+const STATUS_ADDRESS: &str = "/sckitndi/status";
+const MAX_PACKET_LEN: usize = 64 * 1024;
+const MAX_CLIENTS: usize = 16;
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl Arg {
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Arg::Int(v) => Some(v as f64),
+            Arg::Long(v) => Some(v as f64),
+            Arg::Float(v) => Some(v as f64),
+            Arg::Double(v) => Some(v),
+            Arg::Bool(v) => Some(v as u8 as f64),
+            Arg::String(_) => None,
+        }
+    }
+
+    fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Arg::Int(v) => u32::try_from(v).ok(),
+            Arg::Long(v) => u32::try_from(v).ok(),
+            Arg::String(ref v) => v.parse().ok(),
+            _ => self
+                .as_f64()
+                .filter(|v| v.fract() == 0. && *v >= 0. && *v <= u32::MAX as f64)
+                .map(|v| v as u32),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+    pad(buf);
+}
+
+impl Message {
+    pub fn new(address: impl Into<String>, args: Vec<Arg>) -> Self {
+        Self {
+            address: address.into(),
+            args,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_string(&mut buf, &self.address);
+        let mut type_tags = String::from(",");
+        for arg in &self.args {
+            type_tags.push(match arg {
+                Arg::Int(_) => 'i',
+                Arg::Long(_) => 'h',
+                Arg::Float(_) => 'f',
+                Arg::Double(_) => 'd',
+                Arg::String(_) => 's',
+                Arg::Bool(true) => 'T',
+                Arg::Bool(false) => 'F',
+            });
+        }
+        write_string(&mut buf, &type_tags);
+        for arg in &self.args {
+            match arg {
+                Arg::Int(v) => buf.extend_from_slice(&v.to_be_bytes()),
+                Arg::Long(v) => buf.extend_from_slice(&v.to_be_bytes()),
+                Arg::Float(v) => buf.extend_from_slice(&v.to_be_bytes()),
+                Arg::Double(v) => buf.extend_from_slice(&v.to_be_bytes()),
+                Arg::String(v) => write_string(&mut buf, v),
+                Arg::Bool(_) => {}
+            }
+        }
+        buf
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            bail!("Truncated OSC packet");
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// Reads the big-endian size prefix of a blob or bundle element.
+    fn size(&mut self) -> Result<usize> {
+        let len = i32::from_be_bytes(self.array()?);
+        usize::try_from(len).map_err(|_| anyhow!("Invalid OSC size {}", len))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self
+            .buf
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow!("Unterminated OSC string"))?;
+        let s = std::str::from_utf8(&self.buf[..len])
+            .context("Invalid OSC string")?
+            .to_string();
+        self.take((len + 4) & !3)?;
+        Ok(s)
+    }
+}
+
+fn decode_message(buf: &[u8]) -> Result<Message> {
+    let mut reader = Reader { buf };
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        bail!("Invalid OSC address {:?}", address);
+    }
+    // Type tags are optional in OSC 1.0.
+    let type_tags = if reader.buf.is_empty() {
+        String::from(",")
+    } else {
+        reader.string()?
+    };
+    let type_tags = type_tags
+        .strip_prefix(',')
+        .ok_or_else(|| anyhow!("Invalid OSC type tags {:?}", type_tags))?;
+    let mut args = Vec::new();
+    for tag in type_tags.chars() {
+        let arg = match tag {
+            'i' => Arg::Int(i32::from_be_bytes(reader.array()?)),
+            'h' => Arg::Long(i64::from_be_bytes(reader.array()?)),
+            'f' => Arg::Float(f32::from_be_bytes(reader.array()?)),
+            'd' => Arg::Double(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => Arg::String(reader.string()?),
+            'T' => Arg::Bool(true),
+            'F' => Arg::Bool(false),
+            'N' | 'I' => continue,
+            'b' => {
+                let len = reader.size()?;
+                let padded = len
+                    .checked_add(3)
+                    .ok_or_else(|| anyhow!("Invalid OSC blob size {}", len))?;
+                reader.take(padded & !3)?;
+                continue;
+            }
+            _ => bail!("Unsupported OSC type tag {:?}", tag),
+        };
+        args.push(arg);
+    }
+    Ok(Message { address, args })
+}
+
+/// Decodes an OSC packet, flattening bundles into their messages.
+pub fn decode(packet: &[u8]) -> Result<Vec<Message>> {
+    if let Some(mut rest) = packet.strip_prefix(b"#bundle\0") {
+        let mut reader = Reader { buf: rest };
+        // Time tags are ignored; everything is applied immediately.
+        reader.take(8)?;
+        rest = reader.buf;
+        let mut messages = Vec::new();
+        while !rest.is_empty() {
+            let mut reader = Reader { buf: rest };
+            let len = reader.size()?;
+            let element = reader.take(len)?;
+            messages.extend(decode(element)?);
+            rest = reader.buf;
+        }
+        return Ok(messages);
+    }
+    Ok(vec![decode_message(packet)?])
+}
+
+/// Buttons on many control surfaces send 1 on press and 0 on release; only
+/// act on the press.
+fn is_trigger(args: &[Arg]) -> bool {
+    args.first().and_then(Arg::as_f64).is_none_or(|v| v != 0.)
+}
+
+fn id_arg(message: &Message) -> Result<u32> {
+    message
+        .args
+        .first()
+        .and_then(Arg::as_u32)
+        .ok_or_else(|| anyhow!("{} expects an id", message.address))
+}
+
+/// Maps a message to a [`Command`], or `None` if it isn't one.
+pub fn command(message: &Message) -> Option<Result<Command>> {
+    let path = message.address.strip_prefix(PREFIX)?;
+    let command = match path {
+        "/start" | "/stop" | "/pause" | "/slate" | "/resume" | "/crop/clear"
+            if !is_trigger(&message.args) =>
+        {
+            return None
+        }
+        "/start" => Ok(Command::Start),
+        "/stop" => Ok(Command::Stop),
+        "/pause" => Ok(Command::Pause),
+        "/slate" => Ok(Command::ShowSlate),
+        "/resume" => Ok(Command::Resume),
+        "/crop/clear" => Ok(Command::SetCrop(None)),
+        "/source/display" => id_arg(message).map(Command::SwitchDisplay),
+        "/source/window" => id_arg(message).map(Command::SwitchWindow),
+        "/scene" => match message.args.first() {
+            Some(Arg::String(name)) => Ok(Command::SwitchScene(name.clone())),
+            _ => Err(anyhow!("/scene expects a name")),
+        },
+        "/crop" => match message
+            .args
+            .iter()
+            .map(Arg::as_f64)
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(values) => match values[..] {
+                [x, y, width, height] => Ok(Command::SetCrop(Some(Rect::new(x, y, width, height)))),
+                _ => Err(anyhow!("/crop expects x y width height")),
+            },
+            None => Err(anyhow!("/crop expects numbers")),
+        },
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn flag(address: &str, value: bool) -> Message {
+    Message::new(
+        format!("{}{}", PREFIX, address),
+        vec![Arg::Int(value as i32)],
+    )
+}
+
+pub fn status_feedback(status: &Status) -> Vec<Message> {
+    vec![
+        flag("/state/running", status.running),
+        flag("/state/paused", status.paused),
+        flag("/state/slate", status.slate),
+    ]
+}
+
+pub fn tally_feedback(tally: &Tally) -> Vec<Message> {
+    vec![
+        flag("/tally/program", tally.on_program),
+        flag("/tally/preview", tally.on_preview),
+    ]
+}
+
+/// Receives OSC commands over UDP and sends state and tally feedback to every
+/// client that has talked to it.
+pub struct OscServer {
+    local_addr: SocketAddr,
+}
+
+impl OscServer {
+    pub fn bind(addr: SocketAddr, controller: Arc<dyn Controller>) -> Result<Self> {
+        let socket = UdpSocket::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+        let local_addr = socket.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::<SocketAddr>::new()));
+
+        {
+            let socket = socket.try_clone()?;
+            let clients = clients.clone();
+            let controller = controller.clone();
+            std::thread::Builder::new()
+                .name("osc".to_string())
+                .spawn(move || receive(&socket, &clients, &*controller))?;
+        }
+        std::thread::Builder::new()
+            .name("osc-feedback".to_string())
+            .spawn(move || {
+                let mut last = None;
+                loop {
+                    std::thread::sleep(FEEDBACK_INTERVAL);
+                    let current = (controller.status(), controller.tally());
+                    if last.as_ref() == Some(&current) {
+                        continue;
+                    }
+                    let mut messages = status_feedback(&current.0);
+                    messages.extend(tally_feedback(&current.1));
+                    let clients = clients.lock().unwrap().clone();
+                    for client in clients {
+                        send(&socket, client, &messages);
+                    }
+                    last = Some(current);
+                }
+            })?;
+        Ok(Self { local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+fn send(socket: &UdpSocket, to: SocketAddr, messages: &[Message]) {
+    for message in messages {
+        if let Err(err) = socket.send_to(&message.encode(), to) {
+            warn!("osc: failed to send feedback to {}: {}", to, err);
+        }
+    }
+}
+
+fn receive(socket: &UdpSocket, clients: &Mutex<Vec<SocketAddr>>, controller: &dyn Controller) {
+    let mut buf = vec![0; MAX_PACKET_LEN];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) => {
+                warn!("osc: {}", err);
+                continue;
+            }
+        };
+        let messages = match decode(&buf[..len]) {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("osc: ignoring packet from {}: {}", peer, err);
+                continue;
+            }
+        };
+        let is_new_client = {
+            let mut clients = clients.lock().unwrap();
+            let is_new = !clients.contains(&peer);
+            if is_new {
+                if clients.len() == MAX_CLIENTS {
+                    clients.remove(0);
+                }
+                clients.push(peer);
+            }
+            is_new
+        };
+        let mut asked_for_status = false;
+        for message in &messages {
+            match command(message) {
+                Some(Ok(command)) => controller.dispatch(command),
+                Some(Err(err)) => warn!("osc: {}", err),
+                None if message.address == STATUS_ADDRESS => asked_for_status = true,
+                None => {}
+            }
+        }
+        if is_new_client || asked_for_status {
+            let mut feedback = status_feedback(&controller.status());
+            feedback.extend(tally_feedback(&controller.tally()));
+            send(socket, peer, &feedback);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::fake::FakeController;
+
+    fn message(address: &str, args: Vec<Arg>) -> Message {
+        Message::new(address, args)
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend_from_slice(&1u64.to_be_bytes());
+        for element in elements {
+            packet.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            packet.extend_from_slice(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn encodes_padded_strings_and_type_tags() {
+        let encoded = message("/a", vec![Arg::Int(1), Arg::Bool(true)]).encode();
+        assert_eq!(encoded, b"/a\0\0,iT\0\0\0\0\x01");
+    }
+
+    #[test]
+    fn round_trips_every_argument_type() {
+        let original = message(
+            "/sckitndi/test",
+            vec![
+                Arg::Int(-7),
+                Arg::Long(1 << 40),
+                Arg::Float(0.5),
+                Arg::Double(-2.25),
+                Arg::String("four".to_string()),
+                Arg::Bool(true),
+                Arg::Bool(false),
+            ],
+        );
+        assert_eq!(decode(&original.encode()).unwrap(), vec![original]);
+    }
+
+    #[test]
+    fn accepts_messages_without_type_tags() {
+        assert_eq!(
+            decode(b"/sckitndi/start\0").unwrap(),
+            vec![message("/sckitndi/start", vec![])]
+        );
+    }
+
+    #[test]
+    fn skips_blobs_nil_and_impulse() {
+        let mut packet = Vec::new();
+        write_string(&mut packet, "/a");
+        write_string(&mut packet, ",bNIi");
+        packet.extend_from_slice(&5i32.to_be_bytes());
+        packet.extend_from_slice(&[1, 2, 3, 4, 5, 0, 0, 0]);
+        packet.extend_from_slice(&9i32.to_be_bytes());
+        assert_eq!(
+            decode(&packet).unwrap(),
+            vec![message("/a", vec![Arg::Int(9)])]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_blob_sizes() {
+        for len in [-1, i32::MIN, i32::MAX] {
+            let mut packet = Vec::new();
+            write_string(&mut packet, "/a");
+            write_string(&mut packet, ",b");
+            packet.extend_from_slice(&len.to_be_bytes());
+            assert!(decode(&packet).is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn flattens_nested_bundles() {
+        let start = message("/sckitndi/start", vec![]).encode();
+        let stop = message("/sckitndi/stop", vec![]).encode();
+        let packet = bundle(&[start, bundle(&[stop])]);
+        let addresses: Vec<_> = decode(&packet)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.address)
+            .collect();
+        assert_eq!(addresses, ["/sckitndi/start", "/sckitndi/stop"]);
+    }
+
+    #[test]
+    fn rejects_negative_bundle_element_sizes() {
+        let mut packet = bundle(&[]);
+        packet.extend_from_slice(&(-4i32).to_be_bytes());
+        assert!(decode(&packet).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"/unterminated").is_err());
+        assert!(decode(b"noslash\0").is_err());
+        assert!(decode(b"/a\0\0,i\0\0\0\0").is_err());
+        assert!(decode(b"/a\0\0,x\0\0").is_err());
+        assert!(decode(b"#bundle\0\0\0").is_err());
+    }
+
+    fn command_for(address: &str, args: Vec<Arg>) -> Option<Command> {
+        command(&message(address, args)).map(Result::unwrap)
+    }
+
+    #[test]
+    fn maps_messages_to_commands() {
+        assert_eq!(command_for("/sckitndi/start", vec![]), Some(Command::Start));
+        assert_eq!(
+            command_for("/sckitndi/stop", vec![Arg::Float(1.)]),
+            Some(Command::Stop)
+        );
+        assert_eq!(
+            command_for("/sckitndi/source/display", vec![Arg::String("2".into())]),
+            Some(Command::SwitchDisplay(2))
+        );
+        assert_eq!(
+            command_for("/sckitndi/source/window", vec![Arg::Double(3.)]),
+            Some(Command::SwitchWindow(3))
+        );
+        assert_eq!(
+            command_for("/sckitndi/scene", vec![Arg::String("wide".into())]),
+            Some(Command::SwitchScene("wide".to_string()))
+        );
+        assert_eq!(
+            command_for(
+                "/sckitndi/crop",
+                vec![Arg::Int(0), Arg::Int(0), Arg::Float(640.), Arg::Long(480)]
+            ),
+            Some(Command::SetCrop(Some(Rect::new(0., 0., 640., 480.))))
+        );
+        assert_eq!(
+            command_for("/sckitndi/crop/clear", vec![]),
+            Some(Command::SetCrop(None))
+        );
+    }
+
+    #[test]
+    fn ignores_button_releases_and_foreign_addresses() {
+        assert_eq!(command_for("/sckitndi/start", vec![Arg::Int(0)]), None);
+        assert_eq!(command_for("/sckitndi/pause", vec![Arg::Bool(false)]), None);
+        assert_eq!(command_for("/other/start", vec![]), None);
+        assert_eq!(command_for("/sckitndi/unknown", vec![]), None);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(
+            command(&message("/sckitndi/source/display", vec![Arg::Int(-1)]))
+                .unwrap()
+                .is_err()
+        );
+        assert!(command(&message("/sckitndi/scene", vec![Arg::Int(1)]))
+            .unwrap()
+            .is_err());
+        assert!(command(&message("/sckitndi/crop", vec![Arg::Int(1)]))
+            .unwrap()
+            .is_err());
+        assert!(
+            command(&message("/sckitndi/crop", vec![Arg::String("x".into())]))
+                .unwrap()
+                .is_err()
+        );
+    }
+
+    fn expected_feedback() -> Vec<Message> {
+        vec![
+            flag("/state/running", true),
+            flag("/state/paused", false),
+            flag("/state/slate", false),
+            flag("/tally/program", true),
+            flag("/tally/preview", false),
+        ]
+    }
+
+    fn client(server: &OscServer) -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(server.local_addr()).unwrap();
+        socket
+    }
+
+    /// Receives until the server has been quiet for a while. The feedback
+    /// thread may repeat what the receiver sent, so only distinct messages
+    /// are kept, in order of arrival.
+    fn receive_feedback(socket: &UdpSocket) -> Vec<Message> {
+        socket
+            .set_read_timeout(Some(FEEDBACK_INTERVAL * 5))
+            .unwrap();
+        let mut buf = vec![0; MAX_PACKET_LEN];
+        let mut messages = Vec::new();
+        while let Ok(len) = socket.recv(&mut buf) {
+            for message in decode(&buf[..len]).unwrap() {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn dispatches_commands_and_answers_new_clients_over_udp() {
+        let controller = Arc::new(FakeController::default());
+        let server = OscServer::bind(([127, 0, 0, 1], 0).into(), controller.clone()).unwrap();
+        let socket = client(&server);
+
+        let start = message("/sckitndi/start", vec![]).encode();
+        let crop = message(
+            "/sckitndi/crop",
+            vec![Arg::Int(1), Arg::Int(2), Arg::Int(3), Arg::Int(4)],
+        )
+        .encode();
+        socket.send(&bundle(&[start, crop])).unwrap();
+
+        assert_eq!(receive_feedback(&socket), expected_feedback());
+        assert_eq!(
+            *controller.dispatched.lock().unwrap(),
+            [
+                Command::Start,
+                Command::SetCrop(Some(Rect::new(1., 2., 3., 4.)))
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_status_requests_over_udp() {
+        let controller = Arc::new(FakeController::default());
+        let server = OscServer::bind(([127, 0, 0, 1], 0).into(), controller.clone()).unwrap();
+        let socket = client(&server);
+
+        socket.send(b"garbage").unwrap();
+        socket
+            .send(&message(STATUS_ADDRESS, vec![]).encode())
+            .unwrap();
+        assert_eq!(receive_feedback(&socket), expected_feedback());
+
+        // Known clients only get feedback when they ask for it or something
+        // changes.
+        socket
+            .send(&message(STATUS_ADDRESS, vec![]).encode())
+            .unwrap();
+        assert_eq!(receive_feedback(&socket), expected_feedback());
+        socket
+            .send(&message("/sckitndi/unknown", vec![]).encode())
+            .unwrap();
+        assert!(receive_feedback(&socket).is_empty());
+        assert!(controller.dispatched.lock().unwrap().is_empty());
+    }
+}
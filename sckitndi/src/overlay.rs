@@ -0,0 +1,674 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use font8x8::UnicodeFonts;
+use serde::{Deserialize, Serialize};
+
+use crate::{cursor::CursorOverlay, frame::Frame, mask::Color, pacer::VideoSink, pool::FramePool};
+
+/// Glyphs are 8x8 pixels before scaling.
+const GLYPH_SIZE: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// How far along each axis the anchor sits, in halves of the free space:
+    /// 0 for the start, 1 for the middle and 2 for the end.
+    fn alignment(self) -> (i64, i64) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextStyle {
+    /// Multiplies the 8 pixel glyphs.
+    pub scale: usize,
+    pub color: Color,
+    /// Drawn behind the text with a one glyph pixel margin.
+    pub background: Option<Color>,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            scale: 3,
+            color: Color {
+                r: 0xff,
+                g: 0xff,
+                b: 0xff,
+            },
+            background: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OverlayContent {
+    Image {
+        path: PathBuf,
+    },
+    Text {
+        text: String,
+        #[serde(default)]
+        style: TextStyle,
+    },
+    /// Local time, formatted with `strftime` syntax.
+    Clock {
+        #[serde(default = "default_clock_format")]
+        format: String,
+        #[serde(default)]
+        style: TextStyle,
+    },
+}
+
+fn default_clock_format() -> String {
+    "%H:%M:%S".to_string()
+}
+
+fn default_safe_area() -> f64 {
+    0.05
+}
+
+fn default_opacity() -> f32 {
+    1.
+}
+
+/// An image, text or clock burned into the output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverlaySpec {
+    #[serde(flatten)]
+    pub content: OverlayContent,
+    #[serde(default)]
+    pub anchor: Anchor,
+    /// The margin kept from each edge, as a fraction of the frame's width and
+    /// height. The default of 5% is the broadcast action safe area.
+    #[serde(default = "default_safe_area")]
+    pub safe_area: f64,
+    /// In output pixels, added after anchoring.
+    #[serde(default)]
+    pub offset_x: i64,
+    #[serde(default)]
+    pub offset_y: i64,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+/// Reads overlay specs from a JSON array.
+pub fn load_specs(path: &Path) -> Result<Vec<OverlaySpec>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Invalid overlays in {}", path.display()))
+}
+
+/// An RGBA image with straight alpha.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgba: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn load_png(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::decode_png(BufReader::new(file))
+            .with_context(|| format!("Failed to decode {}", path.display()))
+    }
+
+    pub fn decode_png(reader: impl Read) -> Result<Self> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let pixels = &buf[..info.buffer_size()];
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => pixels.to_vec(),
+            png::ColorType::Rgb => pixels
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 0xff])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => pixels
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 0xff]).collect(),
+            png::ColorType::Indexed => bail!("Unexpanded indexed PNG"),
+        };
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgba,
+        })
+    }
+
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, rgba: [u8; 4]) {
+        for row in y..y + height {
+            let start = (row * self.width + x) * 4;
+            for pixel in self.rgba[start..start + width * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&rgba);
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 8] {
+    font8x8::BASIC_FONTS
+        .get(c)
+        .or_else(|| font8x8::LATIN_FONTS.get(c))
+        .or_else(|| font8x8::BASIC_FONTS.get('?'))
+        .unwrap_or_default()
+}
+
+/// Renders a single line of text with the built-in 8x8 font.
+pub fn render_text(text: &str, style: &TextStyle) -> Image {
+    let scale = style.scale.max(1);
+    let cell = GLYPH_SIZE * scale;
+    let margin = if style.background.is_some() { scale } else { 0 };
+    let chars: Vec<char> = text.chars().collect();
+    let mut image = Image::new(chars.len() * cell + margin * 2, cell + margin * 2);
+    if let Some(background) = style.background {
+        image.fill_rect(
+            0,
+            0,
+            image.width,
+            image.height,
+            [background.r, background.g, background.b, 0xff],
+        );
+    }
+    let color = [style.color.r, style.color.g, style.color.b, 0xff];
+    for (i, &c) in chars.iter().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_SIZE {
+                if bits & (1 << col) != 0 {
+                    let x = margin + i * cell + col * scale;
+                    let y = margin + row * scale;
+                    image.fill_rect(x, y, scale, scale, color);
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Top-left corner of an `width`x`height` overlay anchored inside the safe
+/// area of a `frame_width`x`frame_height` frame.
+pub fn position(
+    anchor: Anchor,
+    safe_area: f64,
+    (offset_x, offset_y): (i64, i64),
+    (frame_width, frame_height): (usize, usize),
+    (width, height): (usize, usize),
+) -> (i64, i64) {
+    let inset = |size: usize| (size as f64 * safe_area.clamp(0., 0.5)).round() as i64;
+    let place = |halves: i64, frame: usize, size: usize| {
+        let inset = inset(frame);
+        let room = frame as i64 - 2 * inset - size as i64;
+        inset + room * halves / 2
+    };
+    let (h, v) = anchor.alignment();
+    (
+        place(h, frame_width, width) + offset_x,
+        place(v, frame_height, height) + offset_y,
+    )
+}
+
+/// Blends `image` onto a BGRA frame with its top-left corner at `(x, y)`,
+/// clipping whatever falls outside.
+pub fn blend(frame: &mut Frame, image: &Image, (x, y): (i64, i64), opacity: f32) {
+    let opacity = (opacity.clamp(0., 1.) * 255.).round() as u32;
+    if opacity == 0 {
+        return;
+    }
+    let (frame_width, frame_height, stride) = (frame.width(), frame.height(), frame.stride());
+    let data = frame.data_mut();
+    for row in 0..image.height {
+        let frame_y = y + row as i64;
+        if frame_y < 0 || frame_y >= frame_height as i64 {
+            continue;
+        }
+        for col in 0..image.width {
+            let frame_x = x + col as i64;
+            if frame_x < 0 || frame_x >= frame_width as i64 {
+                continue;
+            }
+            let src = &image.rgba[(row * image.width + col) * 4..][..4];
+            let alpha = (src[3] as u32 * opacity + 127) / 255;
+            if alpha == 0 {
+                continue;
+            }
+            let offset = frame_y as usize * stride + frame_x as usize * 4;
+            let dst = &mut data[offset..offset + 4];
+            let mix =
+                |s: u8, d: u8| ((s as u32 * alpha + d as u32 * (255 - alpha) + 127) / 255) as u8;
+            dst[0] = mix(src[2], dst[0]);
+            dst[1] = mix(src[1], dst[1]);
+            dst[2] = mix(src[0], dst[2]);
+            dst[3] = (alpha + (dst[3] as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+}
+
+enum Layer {
+    Static(Image),
+    Clock {
+        format: String,
+        style: TextStyle,
+        /// The last rendered time, reused until the text changes.
+        rendered: Mutex<Option<(String, Arc<Image>)>>,
+    },
+}
+
+struct Overlay {
+    layer: Layer,
+    anchor: Anchor,
+    safe_area: f64,
+    offset: (i64, i64),
+    opacity: f32,
+}
+
+impl Overlay {
+    fn new(spec: &OverlaySpec) -> Result<Self> {
+        let layer = match &spec.content {
+            OverlayContent::Image { path } => Layer::Static(Image::load_png(path)?),
+            OverlayContent::Text { text, style } => Layer::Static(render_text(text, style)),
+            OverlayContent::Clock { format, style } => {
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    bail!("Invalid clock format {:?}", format);
+                }
+                Layer::Clock {
+                    format: format.clone(),
+                    style: style.clone(),
+                    rendered: Mutex::new(None),
+                }
+            }
+        };
+        Ok(Self {
+            layer,
+            anchor: spec.anchor,
+            safe_area: spec.safe_area,
+            offset: (spec.offset_x, spec.offset_y),
+            opacity: spec.opacity,
+        })
+    }
+
+    fn composite(&self, frame: &mut Frame, now: &DateTime<Local>) {
+        let clock;
+        let image = match &self.layer {
+            Layer::Static(image) => image,
+            Layer::Clock {
+                format,
+                style,
+                rendered,
+            } => {
+                let text = now.format(format).to_string();
+                let mut rendered = rendered.lock().unwrap();
+                clock = match &*rendered {
+                    Some((last, image)) if *last == text => image.clone(),
+                    _ => {
+                        let image = Arc::new(render_text(&text, style));
+                        *rendered = Some((text, image.clone()));
+                        image
+                    }
+                };
+                &*clock
+            }
+        };
+        let at = position(
+            self.anchor,
+            self.safe_area,
+            self.offset,
+            (frame.width(), frame.height()),
+            (image.width, image.height),
+        );
+        blend(frame, image, at, self.opacity);
+    }
+}
+
+/// Burns overlays into frames in the order they were configured.
+pub struct Compositor {
+    overlays: Vec<Overlay>,
+}
+
+impl Compositor {
+    /// Loads every image up front so that a bad path fails at startup.
+    pub fn new(specs: &[OverlaySpec]) -> Result<Self> {
+        let overlays = specs.iter().map(Overlay::new).collect::<Result<_>>()?;
+        Ok(Self { overlays })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    pub fn composite(&self, frame: &mut Frame, now: &DateTime<Local>) {
+        for overlay in &self.overlays {
+            overlay.composite(frame, now);
+        }
+    }
+}
+
+/// Draws the cursor highlight and overlays onto a copy of each outgoing
+/// frame, so that repeated frames still show the current time and ripples.
+pub struct OverlaySink {
+    cursor: Option<CursorOverlay>,
+    /// Swapped when the scene changes.
+    compositor: Arc<Mutex<Arc<Compositor>>>,
+    /// Holds the copies drawn on.
+    pool: FramePool,
+    inner: Arc<dyn VideoSink>,
+}
+
+impl OverlaySink {
+    pub fn new(
+        cursor: Option<CursorOverlay>,
+        compositor: Arc<Mutex<Arc<Compositor>>>,
+        pool: FramePool,
+        inner: Arc<dyn VideoSink>,
+    ) -> Self {
+        Self {
+            cursor,
+            compositor,
+            pool,
+            inner,
+        }
+    }
+}
+
+impl VideoSink for OverlaySink {
+    fn send_video(&self, frame: Arc<Frame>) {
+        let mut frame = frame.copy_into(Some(&self.pool));
+        if let Some(cursor) = &self.cursor {
+            cursor.draw(&mut frame, Instant::now());
+        }
+        let compositor = self.compositor.lock().unwrap().clone();
+        compositor.composite(&mut frame, &Local::now());
+        self.inner.send_video(Arc::new(frame));
+    }
+
+    fn send_idle(&self) {
+        self.inner.send_idle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{metrics::Gauge, pacer::testing::Recorder};
+
+    const WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+    const CLEAR: [u8; 4] = [0; 4];
+
+    fn pixel(image: &Image, x: usize, y: usize) -> [u8; 4] {
+        image.rgba[(y * image.width + x) * 4..][..4]
+            .try_into()
+            .unwrap()
+    }
+
+    fn bgra(frame: &Frame, x: usize) -> [u8; 4] {
+        frame.data()[x * 4..][..4].try_into().unwrap()
+    }
+
+    fn encode_png(
+        color_type: png::ColorType,
+        width: u32,
+        palette: Option<&[u8]>,
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, 1);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(palette) = palette {
+            encoder.set_palette(palette.to_vec());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        png
+    }
+
+    #[test]
+    fn renders_text_glyph_by_glyph() {
+        let style = TextStyle {
+            scale: 1,
+            ..TextStyle::default()
+        };
+        let image = render_text("-", &style);
+        assert_eq!((image.width, image.height), (8, 8));
+        for y in 0..8 {
+            for x in 0..8 {
+                // '-' is bits 0 to 5 of row 3.
+                let expected = if y == 3 && x < 6 { WHITE } else { CLEAR };
+                assert_eq!(pixel(&image, x, y), expected, "({x}, {y})");
+            }
+        }
+        assert_eq!(render_text("--", &style).width, 16);
+        assert_eq!(render_text("", &style).width, 0);
+    }
+
+    #[test]
+    fn scales_text_and_pads_its_background() {
+        let style = TextStyle {
+            scale: 2,
+            color: Color { r: 1, g: 2, b: 3 },
+            background: Some(Color { r: 4, g: 5, b: 6 }),
+        };
+        let image = render_text("_", &style);
+        // One 16 pixel glyph with a 2 pixel margin.
+        assert_eq!((image.width, image.height), (20, 20));
+        for y in 0..20 {
+            for x in 0..20 {
+                // '_' is the whole of row 7, drawn at rows 16 and 17.
+                let text = (16..18).contains(&y) && (2..18).contains(&x);
+                let expected = if text {
+                    [1, 2, 3, 0xff]
+                } else {
+                    [4, 5, 6, 0xff]
+                };
+                assert_eq!(pixel(&image, x, y), expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn anchors_inside_the_safe_area() {
+        let place = |anchor| position(anchor, 0.1, (0, 0), (1000, 500), (200, 100));
+        assert_eq!(place(Anchor::TopLeft), (100, 50));
+        assert_eq!(place(Anchor::Top), (400, 50));
+        assert_eq!(place(Anchor::TopRight), (700, 50));
+        assert_eq!(place(Anchor::Left), (100, 200));
+        assert_eq!(place(Anchor::Center), (400, 200));
+        assert_eq!(place(Anchor::Right), (700, 200));
+        assert_eq!(place(Anchor::BottomLeft), (100, 350));
+        assert_eq!(place(Anchor::Bottom), (400, 350));
+        assert_eq!(place(Anchor::BottomRight), (700, 350));
+    }
+
+    #[test]
+    fn offsets_after_anchoring_and_clamps_the_safe_area() {
+        assert_eq!(
+            position(Anchor::BottomRight, 0., (5, -5), (1000, 500), (200, 100)),
+            (805, 395)
+        );
+        assert_eq!(
+            position(Anchor::TopLeft, -1., (0, 0), (1000, 500), (200, 100)),
+            (0, 0)
+        );
+        // At most half of each side, which leaves no room at all.
+        assert_eq!(
+            position(Anchor::TopLeft, 2., (0, 0), (1000, 500), (200, 100)),
+            (500, 250)
+        );
+    }
+
+    #[test]
+    fn blends_rgba_onto_bgra() {
+        let mut frame = Frame::solid(3, 1, [100, 100, 100, 0xff]);
+        let image = Image {
+            width: 2,
+            height: 1,
+            rgba: vec![0xff, 0, 0, 0xff, 0, 0, 0xff, 128],
+        };
+        blend(&mut frame, &image, (1, 0), 1.);
+        assert_eq!(bgra(&frame, 0), [100, 100, 100, 0xff]);
+        // Opaque red replaces the pixel.
+        assert_eq!(bgra(&frame, 1), [0, 0, 0xff, 0xff]);
+        // Half transparent blue: (255 * 128 + 100 * 127) / 255 and
+        // (100 * 127) / 255, rounded.
+        assert_eq!(bgra(&frame, 2), [178, 50, 50, 0xff]);
+    }
+
+    #[test]
+    fn scales_alpha_by_opacity() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            rgba: vec![0xff, 0xff, 0xff, 0xff],
+        };
+        let mut frame = Frame::solid(1, 1, [0, 0, 0, 0]);
+        blend(&mut frame, &image, (0, 0), 0.);
+        assert_eq!(bgra(&frame, 0), [0, 0, 0, 0]);
+        blend(&mut frame, &image, (0, 0), 0.5);
+        assert_eq!(bgra(&frame, 0), [128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn clips_what_falls_outside_the_frame() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            rgba: [0xff, 0, 0, 0xff].repeat(4),
+        };
+        let black = [0, 0, 0, 0xff];
+        let red = [0, 0, 0xff, 0xff];
+        let mut frame = Frame::solid(2, 2, black);
+        blend(&mut frame, &image, (-1, -1), 1.);
+        assert_eq!(frame.data(), [red, black, black, black].concat());
+        let mut frame = Frame::solid(2, 2, black);
+        blend(&mut frame, &image, (1, 1), 1.);
+        assert_eq!(frame.data(), [black, black, black, red].concat());
+        let mut frame = Frame::solid(2, 2, black);
+        blend(&mut frame, &image, (2, -2), 1.);
+        assert_eq!(frame.data(), black.repeat(4));
+    }
+
+    #[test]
+    fn decodes_every_png_color_type_to_rgba() {
+        let decode = |png: Vec<u8>| Image::decode_png(png.as_slice()).unwrap().rgba;
+        let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            decode(encode_png(png::ColorType::Rgba, 2, None, &rgba)),
+            rgba
+        );
+        assert_eq!(
+            decode(encode_png(
+                png::ColorType::Rgb,
+                2,
+                None,
+                &[1, 2, 3, 4, 5, 6]
+            )),
+            [1, 2, 3, 0xff, 4, 5, 6, 0xff]
+        );
+        assert_eq!(
+            decode(encode_png(
+                png::ColorType::GrayscaleAlpha,
+                2,
+                None,
+                &[1, 2, 3, 4]
+            )),
+            [1, 1, 1, 2, 3, 3, 3, 4]
+        );
+        assert_eq!(
+            decode(encode_png(png::ColorType::Grayscale, 2, None, &[1, 2])),
+            [1, 1, 1, 0xff, 2, 2, 2, 0xff]
+        );
+        let palette = [1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            decode(encode_png(
+                png::ColorType::Indexed,
+                2,
+                Some(&palette),
+                &[1, 0]
+            )),
+            [4, 5, 6, 0xff, 1, 2, 3, 0xff]
+        );
+        let image = Image::decode_png(encode_png(png::ColorType::Rgb, 2, None, &[0; 6]).as_slice())
+            .unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!(Image::decode_png(&b"not a png"[..]).is_err());
+    }
+
+    #[test]
+    fn draws_on_a_copy_from_its_pool() {
+        let spec: OverlaySpec = serde_json::from_str(
+            r#"{"type": "text", "text": "_", "style": {"scale": 1}, "safe_area": 0}"#,
+        )
+        .unwrap();
+        let compositor = Arc::new(Mutex::new(Arc::new(Compositor::new(&[spec]).unwrap())));
+        let in_use = Arc::new(Gauge::default());
+        let recorder = Arc::new(Recorder::default());
+        let sink = OverlaySink::new(
+            None,
+            compositor,
+            FramePool::new(2, in_use.clone()),
+            recorder.clone(),
+        );
+
+        let black = [0, 0, 0, 0xff];
+        let frame = Arc::new(Frame::solid(8, 8, black));
+        sink.send_video(frame.clone());
+        assert_eq!(frame.data(), black.repeat(64));
+        assert_eq!(in_use.get(), 1);
+        let sent = recorder.take().pop().unwrap();
+        assert_eq!(&sent.data()[..7 * 32], &black.repeat(56)[..]);
+        assert_eq!(&sent.data()[7 * 32..], &WHITE.repeat(8)[..]);
+        drop(sent);
+        assert_eq!(in_use.get(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::{
+    frame::{Frame, FrameRate},
+    metrics::PipelineMetrics,
+};
+
+pub trait VideoSink: Send + Sync {
+    fn send_video(&self, frame: Arc<Frame>);
+
+    /// Called on ticks before the capture has produced a frame, so that a
+    /// sink with a picture of its own, like the slate, can send it.
+    fn send_idle(&self) {}
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PacerStats {
+    pub fresh: u64,
+    pub repeated: u64,
+}
+
+#[derive(Default)]
+struct Slot {
+    frame: Option<Arc<Frame>>,
+    fresh: bool,
+    stopped: bool,
+}
+
+struct Shared {
+    slot: Mutex<Slot>,
+    wake: Condvar,
+    metrics: Arc<PipelineMetrics>,
+}
+
+/// Sends the most recent frame to a sink at a constant cadence, repeating it
+/// when the capture hasn't produced a new one since the last tick.
+///
+/// ScreenCaptureKit only delivers frames when the screen changes, while NDI
+/// receivers expect a steady frame rate.
+pub struct Pacer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Pacer {
+    pub fn new(
+        frame_rate: FrameRate,
+        sink: Arc<dyn VideoSink>,
+        metrics: Arc<PipelineMetrics>,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            slot: Mutex::new(Slot::default()),
+            wake: Condvar::new(),
+            metrics,
+        });
+        let thread = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("pacer".to_string())
+                .spawn(move || run(&shared, frame_rate, &*sink))
+                .unwrap()
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn push(&self, frame: Frame) {
+        let mut slot = self.shared.slot.lock().unwrap();
+        slot.frame = Some(Arc::new(frame));
+        if std::mem::replace(&mut slot.fresh, true) {
+            self.shared.metrics.frames_dropped.inc();
+        }
+    }
+
+    pub fn stats(&self) -> PacerStats {
+        PacerStats {
+            fresh: self.shared.metrics.frames_fresh.get(),
+            repeated: self.shared.metrics.frames_repeated.get(),
+        }
+    }
+}
+
+impl Drop for Pacer {
+    fn drop(&mut self) {
+        self.shared.slot.lock().unwrap().stopped = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared, frame_rate: FrameRate, sink: &dyn VideoSink) {
+    let interval = frame_rate.interval();
+    let mut deadline = Instant::now() + interval;
+    loop {
+        let frame = {
+            let mut slot = shared.slot.lock().unwrap();
+            loop {
+                if slot.stopped {
+                    return;
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                slot = shared.wake.wait_timeout(slot, deadline - now).unwrap().0;
+            }
+            let fresh = std::mem::take(&mut slot.fresh);
+            slot.frame.clone().map(|frame| (frame, fresh))
+        };
+        match frame {
+            Some((frame, fresh)) => {
+                let metrics = &shared.metrics;
+                if fresh {
+                    metrics.frames_fresh.inc();
+                } else {
+                    metrics.frames_repeated.inc();
+                }
+                let started = Instant::now();
+                sink.send_video(frame);
+                metrics.send_time.observe_duration(started.elapsed());
+            }
+            None => sink.send_idle(),
+        }
+
+        deadline += interval;
+        let now = Instant::now();
+        if deadline < now {
+            // The sink fell behind by more than a frame; skip the missed ticks
+            // instead of bursting to catch up.
+            deadline = now + interval;
+        }
+    }
+}
+
+/// Sinks for testing the stages of the pipeline.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// Records each frame it is sent, and when it arrived.
+    #[derive(Default)]
+    pub struct Recorder(Mutex<Vec<(Instant, Arc<Frame>)>>);
+
+    impl Recorder {
+        /// The frames sent so far.
+        pub fn frames(&self) -> Vec<Arc<Frame>> {
+            self.sent().into_iter().map(|(_, frame)| frame).collect()
+        }
+
+        /// The frames sent so far, with when each arrived.
+        pub fn sent(&self) -> Vec<(Instant, Arc<Frame>)> {
+            self.0.lock().unwrap().clone()
+        }
+
+        /// Takes the frames sent so far, so that dropping them returns their
+        /// buffers to the pool.
+        pub fn take(&self) -> Vec<Arc<Frame>> {
+            let sent = std::mem::take(&mut *self.0.lock().unwrap());
+            sent.into_iter().map(|(_, frame)| frame).collect()
+        }
+    }
+
+    impl VideoSink for Recorder {
+        fn send_video(&self, frame: Arc<Frame>) {
+            self.0.lock().unwrap().push((Instant::now(), frame));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{frame::PixelFormat, pacer::testing::Recorder};
+
+    fn frame() -> Frame {
+        Frame::black(PixelFormat::Bgra, 2, 2, None)
+    }
+
+    #[test]
+    fn repeats_the_last_frame_while_idle() {
+        let recorder = Arc::new(Recorder::default());
+        let metrics = Arc::new(PipelineMetrics::new());
+        let pacer = Pacer::new(FrameRate::new(100, 1).unwrap(), recorder.clone(), metrics);
+        pacer.push(frame());
+        std::thread::sleep(Duration::from_millis(200));
+        drop(pacer);
+
+        let sent = recorder.sent();
+        assert!(sent.len() >= 10, "sent {} frames", sent.len());
+        assert!(sent.iter().all(|(_, frame)| Arc::ptr_eq(frame, &sent[0].1)));
+    }
+
+    #[test]
+    fn counts_fresh_repeated_and_dropped_frames() {
+        let recorder = Arc::new(Recorder::default());
+        let metrics = Arc::new(PipelineMetrics::new());
+        let pacer = Pacer::new(
+            FrameRate::new(100, 1).unwrap(),
+            recorder.clone(),
+            metrics.clone(),
+        );
+        pacer.push(frame());
+        pacer.push(frame());
+        std::thread::sleep(Duration::from_millis(55));
+        pacer.push(frame());
+        std::thread::sleep(Duration::from_millis(55));
+        let stats = pacer.stats();
+        drop(pacer);
+
+        assert_eq!(stats.fresh, 2);
+        assert!(stats.repeated >= 4, "{stats:?}");
+        assert_eq!(metrics.frames_dropped.get(), 1);
+        assert_eq!(
+            recorder.frames().len() as u64,
+            metrics.frames_fresh.get() + metrics.frames_repeated.get()
+        );
+    }
+
+    #[test]
+    fn sends_at_a_steady_cadence() {
+        let recorder = Arc::new(Recorder::default());
+        let metrics = Arc::new(PipelineMetrics::new());
+        let pacer = Pacer::new(FrameRate::new(50, 1).unwrap(), recorder.clone(), metrics);
+        pacer.push(frame());
+        std::thread::sleep(Duration::from_millis(530));
+        drop(pacer);
+
+        let sent = recorder.sent();
+        let ticks: Vec<_> = sent.iter().map(|(at, _)| *at).collect();
+        // Deadlines advance by the interval rather than from the last send,
+        // so scheduling jitter doesn't accumulate.
+        let elapsed = *ticks.last().unwrap() - ticks[0];
+        let expected = Duration::from_millis(20) * (ticks.len() as u32 - 1);
+        assert!(
+            elapsed.abs_diff(expected) < Duration::from_millis(15),
+            "{} ticks over {elapsed:?}",
+            ticks.len()
+        );
+        assert!(ticks
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] > Duration::from_millis(5)));
+    }
+
+    /// Counts idle ticks, and fails on frames.
+    #[derive(Default)]
+    struct Idle(AtomicUsize);
+
+    impl VideoSink for Idle {
+        fn send_video(&self, _frame: Arc<Frame>) {
+            panic!("sent a frame");
+        }
+
+        fn send_idle(&self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn ticks_idle_before_the_first_frame() {
+        let idle = Arc::new(Idle::default());
+        let metrics = Arc::new(PipelineMetrics::new());
+        let pacer = Pacer::new(
+            FrameRate::new(100, 1).unwrap(),
+            idle.clone(),
+            metrics.clone(),
+        );
+        std::thread::sleep(Duration::from_millis(55));
+        drop(pacer);
+
+        let ticks = idle.0.load(Ordering::Relaxed);
+        assert!(ticks >= 3, "{ticks} idle ticks");
+        assert_eq!(
+            metrics.frames_fresh.get() + metrics.frames_repeated.get(),
+            0
+        );
+    }
+}
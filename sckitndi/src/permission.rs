@@ -0,0 +1,318 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::observable::Observable;
+
+pub const SETTINGS_URL: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture";
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const GUIDANCE: &str = "Screen Recording permission is required. Open System Settings, \
+allow ScreenCaptureKit2NDI under Privacy & Security > Screen Recording, and capture will start \
+automatically. macOS may ask you to quit and reopen the app first.";
+
+/// Opens the Screen Recording pane of System Settings.
+pub fn open_settings() -> Result<()> {
+    std::process::Command::new("open")
+        .arg(SETTINGS_URL)
+        .spawn()
+        .context("Failed to open System Settings")?;
+    Ok(())
+}
+
+/// The system's Screen Recording permission.
+pub trait ScreenCaptureAccess: Send + Sync {
+    /// Whether access is granted, without prompting.
+    fn preflight(&self) -> bool;
+    /// Prompts the user if they haven't decided yet and returns whether access
+    /// is granted.
+    fn request(&self) -> bool;
+}
+
+#[cfg(target_os = "macos")]
+pub struct SystemAccess;
+
+#[cfg(target_os = "macos")]
+impl ScreenCaptureAccess for SystemAccess {
+    fn preflight(&self) -> bool {
+        sckit::preflight_screen_capture_access()
+    }
+
+    fn request(&self) -> bool {
+        sckit::request_screen_capture_access()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    #[default]
+    Unknown,
+    Granted,
+    Denied,
+}
+
+/// Decides whether capturing may proceed, prompting for access at most once.
+pub struct PermissionGate {
+    access: Arc<dyn ScreenCaptureAccess>,
+    requested: AtomicBool,
+    /// The last preflight result.
+    preflight: AtomicBool,
+    /// Set when ScreenCaptureKit refused to capture. Preflight can't be
+    /// trusted then, so access is only assumed again after the user retries
+    /// or preflight changes from denied to granted.
+    refused: AtomicBool,
+    permission: Observable<Permission>,
+}
+
+impl PermissionGate {
+    pub fn new(access: Arc<dyn ScreenCaptureAccess>) -> Self {
+        Self {
+            access,
+            requested: AtomicBool::new(false),
+            preflight: AtomicBool::new(false),
+            refused: AtomicBool::new(false),
+            permission: Observable::new(Permission::Unknown),
+        }
+    }
+
+    pub fn permission(&self) -> &Observable<Permission> {
+        &self.permission
+    }
+
+    /// Checks access before starting a capture. The first time access is
+    /// missing the system prompt is shown; after that it is only rechecked.
+    pub fn check(&self) -> Permission {
+        let granted = self.preflight()
+            || (!self.requested.swap(true, Ordering::Relaxed) && self.access.request());
+        self.update(granted)
+    }
+
+    /// Rechecks access without prompting. Only meaningful once denied; the
+    /// system may keep reporting denial until the app is relaunched.
+    pub fn recheck(&self) -> Permission {
+        if self.permission.get() != Permission::Denied {
+            return self.permission.get();
+        }
+        let granted = self.preflight();
+        self.update(granted)
+    }
+
+    /// Forgets a denial reported by ScreenCaptureKit, so that the next check
+    /// trusts preflight again. Called when the user asks to start capturing.
+    pub fn retry(&self) {
+        self.refused.store(false, Ordering::Relaxed);
+    }
+
+    /// Records a denial reported by ScreenCaptureKit even though preflight
+    /// passed. Returns whether `err` was one.
+    pub fn observe_error(&self, err: &anyhow::Error) -> bool {
+        let denied = sckit::Error::is_permission_denied(err);
+        if denied {
+            self.refused.store(true, Ordering::Relaxed);
+            self.permission.set(Permission::Denied);
+        }
+        denied
+    }
+
+    fn preflight(&self) -> bool {
+        let granted = self.access.preflight();
+        let was_granted = self.preflight.swap(granted, Ordering::Relaxed);
+        if granted && !was_granted {
+            self.refused.store(false, Ordering::Relaxed);
+        }
+        granted
+    }
+
+    fn update(&self, granted: bool) -> Permission {
+        let permission = if granted && !self.refused.load(Ordering::Relaxed) {
+            Permission::Granted
+        } else {
+            Permission::Denied
+        };
+        self.permission.set(permission);
+        permission
+    }
+}
+
+/// Rechecks a denied [`PermissionGate`] in the background so that capture can
+/// resume as soon as the user grants access.
+pub struct PermissionMonitor {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PermissionMonitor {
+    pub fn new(gate: Arc<PermissionGate>) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name("permission".to_string())
+                .spawn(move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        std::thread::park_timeout(RETRY_INTERVAL);
+                        gate.recheck();
+                    }
+                })
+                .unwrap()
+        };
+        Self {
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PermissionMonitor {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// Stands in for the system, answering preflight and prompts as told.
+    #[derive(Default)]
+    struct FakeAccess {
+        granted: AtomicBool,
+        grant_on_request: AtomicBool,
+        requests: AtomicUsize,
+    }
+
+    impl FakeAccess {
+        fn set_granted(&self, granted: bool) {
+            self.granted.store(granted, Ordering::SeqCst);
+        }
+    }
+
+    impl ScreenCaptureAccess for FakeAccess {
+        fn preflight(&self) -> bool {
+            self.granted.load(Ordering::SeqCst)
+        }
+
+        fn request(&self) -> bool {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if self.grant_on_request.load(Ordering::SeqCst) {
+                self.set_granted(true);
+            }
+            self.preflight()
+        }
+    }
+
+    fn gate() -> (PermissionGate, Arc<FakeAccess>) {
+        let access = Arc::new(FakeAccess::default());
+        (PermissionGate::new(access.clone()), access)
+    }
+
+    fn declined() -> anyhow::Error {
+        anyhow::Error::new(sckit::Error {
+            context: "Failed to start capture",
+            domain: sckit::SC_STREAM_ERROR_DOMAIN.to_string(),
+            code: sckit::SC_STREAM_ERROR_USER_DECLINED,
+            description: None,
+        })
+        .context("capture failed")
+    }
+
+    #[test]
+    fn grants_without_prompting_when_preflight_passes() {
+        let (gate, access) = gate();
+        access.set_granted(true);
+        assert_eq!(gate.check(), Permission::Granted);
+        assert_eq!(gate.permission().get(), Permission::Granted);
+        assert_eq!(access.requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn prompts_only_once() {
+        let (gate, access) = gate();
+        assert_eq!(gate.check(), Permission::Denied);
+        assert_eq!(gate.check(), Permission::Denied);
+        assert_eq!(access.requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn grants_when_the_prompt_is_accepted() {
+        let (gate, access) = gate();
+        access.grant_on_request.store(true, Ordering::SeqCst);
+        assert_eq!(gate.check(), Permission::Granted);
+    }
+
+    #[test]
+    fn rechecks_only_once_denied() {
+        let (gate, access) = gate();
+        assert_eq!(gate.recheck(), Permission::Unknown);
+        assert_eq!(gate.check(), Permission::Denied);
+        assert_eq!(gate.recheck(), Permission::Denied);
+        access.set_granted(true);
+        assert_eq!(gate.recheck(), Permission::Granted);
+        access.set_granted(false);
+        assert_eq!(gate.recheck(), Permission::Granted);
+    }
+
+    #[test]
+    fn ignores_other_errors() {
+        let (gate, access) = gate();
+        access.set_granted(true);
+        gate.check();
+        assert!(!gate.observe_error(&anyhow::anyhow!("stream stalled")));
+        assert_eq!(gate.permission().get(), Permission::Granted);
+    }
+
+    #[test]
+    fn stays_denied_after_a_refusal_while_preflight_passes() {
+        let (gate, access) = gate();
+        access.set_granted(true);
+        assert_eq!(gate.check(), Permission::Granted);
+
+        assert!(gate.observe_error(&declined()));
+        assert_eq!(gate.permission().get(), Permission::Denied);
+        for _ in 0..3 {
+            assert_eq!(gate.recheck(), Permission::Denied);
+        }
+        assert_eq!(gate.check(), Permission::Denied);
+    }
+
+    #[test]
+    fn grants_again_once_preflight_changes_from_denied_to_granted() {
+        let (gate, access) = gate();
+        access.set_granted(true);
+        gate.check();
+        gate.observe_error(&declined());
+
+        access.set_granted(false);
+        assert_eq!(gate.recheck(), Permission::Denied);
+        access.set_granted(true);
+        assert_eq!(gate.recheck(), Permission::Granted);
+    }
+
+    #[test]
+    fn grants_again_when_the_user_retries() {
+        let (gate, access) = gate();
+        access.set_granted(true);
+        gate.check();
+        gate.observe_error(&declined());
+
+        gate.retry();
+        assert_eq!(gate.check(), Permission::Granted);
+    }
+}
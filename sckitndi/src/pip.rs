@@ -0,0 +1,360 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    frame::Frame,
+    geometry::{Rect, Size},
+    mask::{self, Color, Mask, MaskStyle},
+    pool::FramePool,
+    redact::TitlePattern,
+    scale::{fit_rect, Filter, Fit, Scaler},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerSource {
+    /// The first window whose title matches.
+    Window(TitlePattern),
+    Display(u32),
+}
+
+impl std::fmt::Display for LayerSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayerSource::Window(pattern) => write!(f, "window {:?}", pattern.to_string()),
+            LayerSource::Display(id) => write!(f, "display {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Border {
+    /// In output pixels, drawn outside the picture.
+    pub width: usize,
+    pub color: Color,
+}
+
+/// A capture drawn over the main one. The box is given in fractions of the
+/// output frame's width and height.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerSpec {
+    pub source: LayerSource,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Layers are drawn from the lowest `z` up, all above the main capture.
+    #[serde(default)]
+    pub z: i32,
+    #[serde(default)]
+    pub border: Option<Border>,
+    /// How the picture fills the box.
+    #[serde(default)]
+    pub fit: Fit,
+}
+
+/// Reads layer specs from a JSON array.
+pub fn load_layout(path: &Path) -> Result<Vec<LayerSpec>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let layers: Vec<LayerSpec> = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Invalid layout in {}", path.display()))?;
+    for layer in &layers {
+        if !(layer.width > 0. && layer.height > 0.) {
+            bail!("Layer of {} has an empty box", layer.source);
+        }
+    }
+    Ok(layers)
+}
+
+/// The layer's box in a `frame` sized output, in whole pixels with an even
+/// width.
+pub fn layer_box(spec: &LayerSpec, frame: Size) -> Rect {
+    let (frame_width, frame_height) = (frame.width as f64, frame.height as f64);
+    Rect::new(
+        (spec.x * frame_width).round(),
+        (spec.y * frame_height).round(),
+        ((spec.width * frame_width).round() as usize & !1) as f64,
+        (spec.height * frame_height).round(),
+    )
+}
+
+/// Where a layer's frames come from.
+pub trait LayerFeed: Send + Sync {
+    /// The most recent frame, or `None` while there is nothing to show.
+    fn latest(&self) -> Option<Arc<Frame>>;
+}
+
+/// Copies `src` into `frame` with its top-left corner at `(x, y)`, clipped to
+/// the frame.
+pub fn blit(frame: &mut Frame, src: &Frame, (x, y): (i64, i64)) {
+    let (frame_width, frame_height, stride) = (frame.width(), frame.height(), frame.stride());
+    let left = x.max(0);
+    let right = (x + src.width() as i64).min(frame_width as i64);
+    if left >= right {
+        return;
+    }
+    let len = (right - left) as usize * 4;
+    let src_left = (left - x) as usize * 4;
+    let data = frame.data_mut();
+    for row in 0..src.height() {
+        let frame_y = y + row as i64;
+        if frame_y < 0 || frame_y >= frame_height as i64 {
+            continue;
+        }
+        let offset = frame_y as usize * stride + left as usize * 4;
+        data[offset..offset + len]
+            .copy_from_slice(&src.data()[row * src.stride() + src_left..][..len]);
+    }
+}
+
+struct Layer {
+    spec: LayerSpec,
+    feed: Arc<dyn LayerFeed>,
+    /// Kept while the picture size stays the same.
+    scaler: Mutex<Option<(Size, Scaler)>>,
+}
+
+/// Draws the layers of a picture-in-picture layout over outgoing frames.
+pub struct PipCompositor {
+    layers: Vec<Layer>,
+    pool: FramePool,
+}
+
+impl PipCompositor {
+    pub fn new(
+        layers: impl IntoIterator<Item = (LayerSpec, Arc<dyn LayerFeed>)>,
+        pool: FramePool,
+    ) -> Self {
+        let mut layers: Vec<Layer> = layers
+            .into_iter()
+            .map(|(spec, feed)| Layer {
+                spec,
+                feed,
+                scaler: Mutex::new(None),
+            })
+            .collect();
+        layers.sort_by_key(|layer| layer.spec.z);
+        Self { layers, pool }
+    }
+
+    pub fn composite(&self, frame: &mut Frame) {
+        let frame_size = Size::new(frame.width(), frame.height());
+        for layer in &self.layers {
+            let layer_box = layer_box(&layer.spec, frame_size);
+            if layer_box.width < 2. || layer_box.height < 1. {
+                continue;
+            }
+            let Some(source) = layer.feed.latest() else {
+                continue;
+            };
+            let picture = fit_rect(
+                Size::new(source.width(), source.height()),
+                Size::new(layer_box.width as usize, layer_box.height as usize),
+                layer.spec.fit,
+            );
+            let size = Size::new(picture.width as usize, picture.height as usize);
+            let (x, y) = (layer_box.x + picture.x, layer_box.y + picture.y);
+            if let Some(border) = layer.spec.border {
+                let width = border.width as f64;
+                let outline = Rect::new(
+                    x - width,
+                    y - width,
+                    picture.width + 2. * width,
+                    picture.height + 2. * width,
+                );
+                mask::apply(
+                    frame,
+                    &[Mask {
+                        rect: outline,
+                        style: MaskStyle::Fill {
+                            color: border.color,
+                        },
+                    }],
+                );
+            }
+            let position = (x as i64, y as i64);
+            if (source.width(), source.height()) == (size.width, size.height) {
+                blit(frame, &source, position);
+                continue;
+            }
+            let mut scaler = layer.scaler.lock().unwrap();
+            if scaler.as_ref().is_none_or(|(cached, _)| *cached != size) {
+                *scaler = Some((size, Scaler::new(size, Filter::Bilinear, Fit::Stretch)));
+            }
+            let (_, scaler) = scaler.as_ref().unwrap();
+            blit(frame, &scaler.scale(&source, Some(&self.pool)), position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::metrics::Gauge;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 0xff];
+    const RED: [u8; 4] = [0, 0, 0xff, 0xff];
+    const BLUE: [u8; 4] = [0xff, 0, 0, 0xff];
+    const WHITE: [u8; 4] = [0xff; 4];
+
+    struct Still(Option<Arc<Frame>>);
+
+    impl LayerFeed for Still {
+        fn latest(&self) -> Option<Arc<Frame>> {
+            self.0.clone()
+        }
+    }
+
+    fn spec((x, y, width, height): (f64, f64, f64, f64), z: i32) -> LayerSpec {
+        LayerSpec {
+            source: LayerSource::Display(1),
+            x,
+            y,
+            width,
+            height,
+            z,
+            border: None,
+            fit: Fit::Letterbox,
+        }
+    }
+
+    fn compositor(layers: Vec<(LayerSpec, Option<Frame>)>) -> PipCompositor {
+        let layers = layers.into_iter().map(|(spec, frame)| {
+            let feed: Arc<dyn LayerFeed> = Arc::new(Still(frame.map(Arc::new)));
+            (spec, feed)
+        });
+        PipCompositor::new(layers, FramePool::new(1, Arc::new(Gauge::default())))
+    }
+
+    fn pixel(frame: &Frame, x: usize, y: usize) -> [u8; 4] {
+        frame.data()[y * frame.stride() + x * 4..][..4]
+            .try_into()
+            .unwrap()
+    }
+
+    /// Each row of a frame as a string, `.` for black and the first letter of
+    /// the color otherwise.
+    fn picture(frame: &Frame) -> Vec<String> {
+        (0..frame.height())
+            .map(|y| {
+                (0..frame.width())
+                    .map(|x| match pixel(frame, x, y) {
+                        BLACK => '.',
+                        RED => 'r',
+                        BLUE => 'b',
+                        WHITE => 'w',
+                        other => panic!("unexpected pixel {other:?}"),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn places_boxes_in_whole_pixels_with_even_widths() {
+        let layer = spec((0.1, 0.25, 0.33, 0.5), 0);
+        assert_eq!(
+            layer_box(&layer, Size::new(100, 10)),
+            Rect::new(10., 3., 32., 5.)
+        );
+    }
+
+    #[test]
+    fn draws_layers_from_the_lowest_z_up() {
+        let mut frame = Frame::solid(8, 2, BLACK);
+        compositor(vec![
+            (spec((0.25, 0., 0.5, 1.), 1), Some(Frame::solid(4, 2, RED))),
+            (spec((0., 0., 0.5, 1.), 0), Some(Frame::solid(4, 2, BLUE))),
+        ])
+        .composite(&mut frame);
+        assert_eq!(picture(&frame), ["bbrrrr..", "bbrrrr.."]);
+
+        let mut frame = Frame::solid(8, 2, BLACK);
+        compositor(vec![
+            (spec((0.25, 0., 0.5, 1.), -1), Some(Frame::solid(4, 2, RED))),
+            (spec((0., 0., 0.5, 1.), 0), Some(Frame::solid(4, 2, BLUE))),
+        ])
+        .composite(&mut frame);
+        assert_eq!(picture(&frame), ["bbbbrr..", "bbbbrr.."]);
+    }
+
+    #[test]
+    fn draws_borders_outside_the_picture() {
+        let mut layer = spec((0.25, 0.25, 0.5, 0.5), 0);
+        layer.border = Some(Border {
+            width: 1,
+            color: Color {
+                r: 0xff,
+                g: 0xff,
+                b: 0xff,
+            },
+        });
+        let mut frame = Frame::solid(8, 8, BLACK);
+        compositor(vec![(layer, Some(Frame::solid(4, 4, RED)))]).composite(&mut frame);
+        assert_eq!(
+            picture(&frame),
+            [
+                "........", ".wwwwww.", ".wrrrrw.", ".wrrrrw.", ".wrrrrw.", ".wrrrrw.", ".wwwwww.",
+                "........",
+            ]
+        );
+    }
+
+    #[test]
+    fn fits_the_picture_inside_its_box() {
+        let mut frame = Frame::solid(8, 8, BLACK);
+        compositor(vec![(
+            spec((0., 0., 0.5, 1.), 0),
+            Some(Frame::solid(2, 2, RED)),
+        )])
+        .composite(&mut frame);
+        assert_eq!(
+            picture(&frame),
+            [
+                "........", "........", "rrrr....", "rrrr....", "rrrr....", "rrrr....", "........",
+                "........",
+            ]
+        );
+    }
+
+    #[test]
+    fn clips_layers_and_borders_to_the_frame() {
+        let mut corner = spec((0.75, 0.5, 0.5, 1.), 0);
+        corner.border = Some(Border {
+            width: 1,
+            color: Color {
+                r: 0xff,
+                g: 0xff,
+                b: 0xff,
+            },
+        });
+        let mut frame = Frame::solid(8, 4, BLACK);
+        compositor(vec![
+            (
+                spec((-0.25, -0.5, 0.5, 1.), 0),
+                Some(Frame::solid(4, 4, BLUE)),
+            ),
+            (corner, Some(Frame::solid(4, 4, RED))),
+        ])
+        .composite(&mut frame);
+        assert_eq!(
+            picture(&frame),
+            ["bb......", "bb...www", ".....wrr", ".....wrr"]
+        );
+    }
+
+    #[test]
+    fn leaves_out_layers_without_a_frame() {
+        let mut frame = Frame::solid(4, 2, BLACK);
+        compositor(vec![(spec((0., 0., 1., 1.), 0), None)]).composite(&mut frame);
+        assert_eq!(picture(&frame), ["....", "...."]);
+    }
+}
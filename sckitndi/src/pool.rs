@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{frame::Frame, metrics::Gauge};
+
+/// A bounded free list of frame buffers, so steady-state capture doesn't
+/// allocate a new buffer for every frame.
+#[derive(Clone)]
+pub struct FramePool {
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+    capacity: usize,
+    in_use: Arc<Gauge>,
+}
+
+impl FramePool {
+    /// `in_use` tracks how many buffers have been taken and not recycled.
+    pub fn new(capacity: usize, in_use: Arc<Gauge>) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(Vec::with_capacity(capacity))),
+            capacity,
+            in_use,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn take(&self, len: usize) -> Vec<u8> {
+        let mut free = self.free.lock().unwrap();
+        let mut data = match free.iter().position(|buf| buf.len() == len) {
+            Some(pos) => free.swap_remove(pos),
+            None => free.pop().unwrap_or_default(),
+        };
+        data.resize(len, 0);
+        self.in_use.add(1);
+        data
+    }
+
+    pub fn recycle(&self, data: Vec<u8>) {
+        self.in_use.add(-1);
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.capacity {
+            free.push(data);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `base` must point to `height` rows of `stride` bytes each.
+    pub unsafe fn copy_from_raw(
+        &self,
+        width: usize,
+        height: usize,
+        stride: usize,
+        base: *const u8,
+    ) -> Frame {
+        let mut data = self.take(stride * height);
+        data.copy_from_slice(std::slice::from_raw_parts(base, stride * height));
+        Frame::with_buffer(width, height, stride, data, Some(self.clone()))
+    }
+}
+
+/// Keeps the buffer of an asynchronous submission alive until the next
+/// submission (or flush) has returned.
+///
+/// `NDIlib_send_send_video_async_v2` returns before the frame has been sent
+/// and only guarantees that it is done with a buffer once the following send
+/// call returns.
+pub struct InFlight<T> {
+    current: Mutex<Option<T>>,
+}
+
+impl<T> InFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn submit<R>(&self, next: T, submit: impl FnOnce(&T) -> R) -> R {
+        let mut current = self.current.lock().unwrap();
+        let ret = submit(&next);
+        let previous = current.replace(next);
+        drop(current);
+        drop(previous);
+        ret
+    }
+
+    pub fn flush(&self, flush: impl FnOnce()) {
+        let mut current = self.current.lock().unwrap();
+        flush();
+        let previous = current.take();
+        drop(current);
+        drop(previous);
+    }
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::frame::PixelFormat;
+
+    fn pool(capacity: usize) -> (FramePool, Arc<Gauge>) {
+        let in_use = Arc::new(Gauge::default());
+        (FramePool::new(capacity, in_use.clone()), in_use)
+    }
+
+    #[test]
+    fn reuses_recycled_buffers() {
+        let (pool, in_use) = pool(2);
+        let frame = Frame::black(PixelFormat::Bgra, 4, 2, Some(&pool));
+        let ptr = frame.data().as_ptr();
+        assert_eq!(in_use.get(), 1);
+        drop(frame);
+        assert_eq!(in_use.get(), 0);
+
+        let frame = Frame::black(PixelFormat::Bgra, 4, 2, Some(&pool));
+        assert_eq!(frame.data().as_ptr(), ptr);
+        assert_eq!(in_use.get(), 1);
+    }
+
+    #[test]
+    fn prefers_a_buffer_of_the_requested_size() {
+        let (pool, _) = pool(4);
+        let small = pool.take(16);
+        let large = pool.take(64);
+        let large_ptr = large.as_ptr();
+        pool.recycle(large);
+        pool.recycle(small);
+
+        let data = pool.take(64);
+        assert_eq!(data.as_ptr(), large_ptr);
+        assert_eq!(data.len(), 64);
+    }
+
+    #[test]
+    fn resizes_a_buffer_of_another_size() {
+        let (pool, _) = pool(1);
+        pool.recycle(vec![1; 8]);
+        let data = pool.take(12);
+        assert_eq!(data.len(), 12);
+    }
+
+    #[test]
+    fn keeps_at_most_capacity_buffers() {
+        let (pool, in_use) = pool(1);
+        let (a, b) = (pool.take(8), pool.take(8));
+        assert_eq!(in_use.get(), 2);
+        pool.recycle(a);
+        pool.recycle(b);
+        assert_eq!(in_use.get(), 0);
+        assert_eq!(pool.free.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn clones_take_their_own_buffer() {
+        let (pool, in_use) = pool(2);
+        let mut frame = Frame::black(PixelFormat::Bgra, 2, 2, Some(&pool));
+        frame.data_mut()[0] = 7;
+        let clone = frame.clone();
+        assert_eq!(in_use.get(), 2);
+        assert_ne!(clone.data().as_ptr(), frame.data().as_ptr());
+        assert_eq!(clone.data(), frame.data());
+    }
+
+    /// Counts drops, standing in for a frame buffer.
+    struct Buffer<'a>(&'a AtomicUsize);
+
+    impl Drop for Buffer<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn releases_a_submission_once_the_next_has_returned() {
+        let dropped = AtomicUsize::new(0);
+        let in_flight = InFlight::new();
+
+        in_flight.submit(Buffer(&dropped), |_| {});
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        in_flight.submit(Buffer(&dropped), |_| {
+            // The previous buffer is still alive while the next send runs.
+            assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        });
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+        in_flight.flush(|| assert_eq!(dropped.load(Ordering::SeqCst), 1));
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+}
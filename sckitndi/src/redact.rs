@@ -0,0 +1,325 @@
+use std::{collections::BTreeSet, str::FromStr, sync::Mutex};
+
+use anyhow::{bail, Result};
+
+/// A case-insensitive window title pattern. `*` matches any run of
+/// characters and `?` any single one; a pattern without wildcards matches
+/// anywhere in the title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitlePattern {
+    source: String,
+    pattern: Vec<char>,
+}
+
+impl TitlePattern {
+    pub fn matches(&self, title: &str) -> bool {
+        let title: Vec<char> = title.chars().flat_map(char::to_lowercase).collect();
+        glob(&self.pattern, &title)
+    }
+}
+
+impl FromStr for TitlePattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            bail!("Empty title pattern");
+        }
+        let mut pattern: Vec<char> = s.chars().flat_map(char::to_lowercase).collect();
+        if !pattern.iter().any(|&c| c == '*' || c == '?') {
+            pattern.insert(0, '*');
+            pattern.push('*');
+        }
+        Ok(Self {
+            source: s.to_string(),
+            pattern,
+        })
+    }
+}
+
+impl std::fmt::Display for TitlePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl serde::Serialize for TitlePattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TitlePattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        pattern.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Matches with backtracking to the last `*`, which is linear for patterns
+/// with a single star and fine for the short patterns used here.
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// A window as the redaction rules see it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowTitle {
+    pub id: u32,
+    pub title: String,
+}
+
+/// Windows that started or stopped matching since the filter was last
+/// applied.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Diff {
+    pub added: Vec<u32>,
+    pub removed: Vec<u32>,
+}
+
+impl Diff {
+    pub fn between(before: &BTreeSet<u32>, after: &BTreeSet<u32>) -> Self {
+        Self {
+            added: after.difference(before).copied().collect(),
+            removed: before.difference(after).copied().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Tracks which windows the title rules hide from display captures.
+pub struct Redactor {
+    patterns: Mutex<Vec<TitlePattern>>,
+    applied: Mutex<BTreeSet<u32>>,
+}
+
+impl Redactor {
+    pub fn new(patterns: Vec<TitlePattern>) -> Self {
+        Self {
+            patterns: Mutex::new(patterns),
+            applied: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.patterns.lock().unwrap().is_empty()
+    }
+
+    /// Replaces the patterns; the next content filter picks them up.
+    pub fn set_patterns(&self, patterns: Vec<TitlePattern>) {
+        *self.patterns.lock().unwrap() = patterns;
+    }
+
+    /// Ids of the windows whose titles match any pattern.
+    pub fn matching(&self, windows: impl IntoIterator<Item = WindowTitle>) -> BTreeSet<u32> {
+        let patterns = self.patterns.lock().unwrap();
+        windows
+            .into_iter()
+            .filter(|window| patterns.iter().any(|p| p.matches(&window.title)))
+            .map(|window| window.id)
+            .collect()
+    }
+
+    /// Records the windows the current content filter hides.
+    pub fn applied(&self, redacted: BTreeSet<u32>) {
+        *self.applied.lock().unwrap() = redacted;
+    }
+
+    /// How `redacted` differs from what the current content filter hides.
+    pub fn diff(&self, redacted: &BTreeSet<u32>) -> Diff {
+        Diff::between(&self.applied.lock().unwrap(), redacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn pattern(s: &str) -> TitlePattern {
+        s.parse().unwrap()
+    }
+
+    fn set(ids: &[u32]) -> BTreeSet<u32> {
+        ids.iter().copied().collect()
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/redact")
+            .join(name)
+    }
+
+    /// Non-empty lines that aren't comments.
+    fn fixture_lines(name: &str) -> Vec<String> {
+        std::fs::read_to_string(fixture(name))
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Compares `actual` with a snapshot file, or rewrites the file when
+    /// `UPDATE_SNAPSHOTS` is set.
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = fixture(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            expected == actual,
+            "{} is out of date; rerun with UPDATE_SNAPSHOTS=1 and review the diff.\n{}",
+            path.display(),
+            actual
+        );
+    }
+
+    #[test]
+    fn globs_match_the_whole_text() {
+        let cases = [
+            ("abc", "abc", true),
+            ("abc", "abcd", false),
+            ("a?c", "abc", true),
+            ("a?c", "ac", false),
+            ("a*", "a", true),
+            ("*c", "abc", true),
+            ("a*c", "abbbc", true),
+            ("a*c", "abcb", false),
+            ("*b*b*", "abab", true),
+            ("*b*b*", "ab", false),
+            ("**", "", true),
+            ("", "", true),
+            ("", "a", false),
+            ("?", "", false),
+        ];
+        for (pattern, text, matches) in cases {
+            assert_eq!(
+                glob(&chars(pattern), &chars(text)),
+                matches,
+                "{:?} {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn matches_titles_case_insensitively_and_anywhere_without_wildcards() {
+        assert!(pattern("secret").matches("Top SECRET plans"));
+        assert!(pattern("ÄRZTE").matches("ärzte termin"));
+        assert!(!pattern("secret?").matches("Top secret"));
+        assert!(pattern("top*").matches("Top secret"));
+        assert!(!pattern("secret*").matches("Top secret"));
+    }
+
+    #[test]
+    fn keeps_the_pattern_as_written() {
+        assert_eq!(pattern("Pass*").to_string(), "Pass*");
+        assert_eq!(
+            serde_json::to_string(&pattern("Pass*")).unwrap(),
+            "\"Pass*\""
+        );
+        let parsed: Vec<TitlePattern> = serde_json::from_str("[\"Pass*\", \"mail\"]").unwrap();
+        assert_eq!(parsed, [pattern("Pass*"), pattern("mail")]);
+        assert!("".parse::<TitlePattern>().is_err());
+        assert!(serde_json::from_str::<TitlePattern>("\"\"").is_err());
+    }
+
+    #[test]
+    fn matches_fixture_windows() {
+        let windows: Vec<WindowTitle> = fixture_lines("windows.txt")
+            .iter()
+            .map(|line| {
+                let (id, title) = line.split_once('\t').unwrap();
+                WindowTitle {
+                    id: id.parse().unwrap(),
+                    title: title.to_string(),
+                }
+            })
+            .collect();
+        let mut snapshot = String::new();
+        for line in fixture_lines("patterns.txt") {
+            let redactor = Redactor::new(vec![pattern(&line)]);
+            let ids: Vec<_> = redactor
+                .matching(windows.iter().cloned())
+                .into_iter()
+                .map(|id| id.to_string())
+                .collect();
+            snapshot += &format!("{} => [{}]\n", line, ids.join(", "));
+        }
+        assert_snapshot("matches.snap", &snapshot);
+    }
+
+    #[test]
+    fn diffs_redacted_windows() {
+        assert_eq!(
+            Diff::between(&set(&[1, 2, 3]), &set(&[2, 3, 4, 5])),
+            Diff {
+                added: vec![4, 5],
+                removed: vec![1],
+            }
+        );
+        assert!(Diff::between(&set(&[1]), &set(&[1])).is_empty());
+        assert!(Diff::between(&set(&[]), &set(&[])).is_empty());
+    }
+
+    #[test]
+    fn tracks_the_applied_filter() {
+        let redactor = Redactor::new(vec![pattern("secret")]);
+        assert!(redactor.is_enabled());
+        let windows = || {
+            [(1, "Secret"), (2, "Public"), (3, "more secrets")].map(|(id, title)| WindowTitle {
+                id,
+                title: title.to_string(),
+            })
+        };
+        let matching = redactor.matching(windows());
+        assert_eq!(matching, set(&[1, 3]));
+        assert_eq!(redactor.diff(&matching).added, [1, 3]);
+
+        redactor.applied(matching.clone());
+        assert!(redactor.diff(&matching).is_empty());
+
+        redactor.set_patterns(vec![pattern("public")]);
+        let diff = redactor.diff(&redactor.matching(windows()));
+        assert_eq!(diff.added, [2]);
+        assert_eq!(diff.removed, [1, 3]);
+
+        redactor.set_patterns(Vec::new());
+        assert!(!redactor.is_enabled());
+        assert!(redactor.matching(windows()).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::command::{self, Command};
+
+pub trait MetadataSource: Send + Sync {
+    /// Waits up to `timeout` for a metadata frame from any receiver.
+    fn capture_metadata(&self, timeout: Duration) -> Option<String>;
+}
+
+const POLL_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub fn dispatch(xml: &str, dispatch: &dyn Fn(Command)) {
+    match command::parse_commands(xml) {
+        Ok(commands) => commands.into_iter().for_each(dispatch),
+        Err(err) => tracing::warn!("ignoring malformed metadata {:?}: {}", xml, err),
+    }
+}
+
+/// Listens for commands sent by receivers as NDI metadata frames.
+pub struct RemoteControl {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RemoteControl {
+    pub fn new(
+        source: Arc<dyn MetadataSource>,
+        on_command: impl Fn(Command) + Send + 'static,
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name("remote-control".to_string())
+                .spawn(move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        if let Some(xml) = source.capture_metadata(POLL_TIMEOUT) {
+                            dispatch(&xml, &on_command);
+                        }
+                    }
+                })
+                .unwrap()
+        };
+        Self {
+            stopped,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for RemoteControl {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn dispatched(xml: &str) -> Vec<Command> {
+        let commands = Mutex::new(Vec::new());
+        dispatch(xml, &|command| commands.lock().unwrap().push(command));
+        commands.into_inner().unwrap()
+    }
+
+    #[test]
+    fn forwards_every_command_in_order() {
+        assert_eq!(
+            dispatched(r#"<switch_window id="7"/><ndi_capabilities/><pause/><resume/>"#),
+            [Command::SwitchWindow(7), Command::Pause, Command::Resume]
+        );
+    }
+
+    #[test]
+    fn forwards_nothing_from_malformed_metadata() {
+        assert!(dispatched("<pause/><switch_display").is_empty());
+        assert!(dispatched(r#"<pause/><switch_display id="main"/>"#).is_empty());
+        assert!(dispatched(r#"<switch_scene name="Keynote/>"#).is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+
+use crate::{
+    convert::bgra_to_uyvy,
+    frame::{Frame, PixelFormat},
+    geometry::Size,
+    metrics::{Counter, PipelineMetrics},
+    pacer::VideoSink,
+    pool::FramePool,
+    scale::{Filter, Fit, Scaler},
+};
+
+/// An extra output of the capture at another size or format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenditionSpec {
+    /// Appended to the NDI name of the main output.
+    pub name: String,
+    pub size: Size,
+    pub format: PixelFormat,
+}
+
+impl FromStr for RenditionSpec {
+    type Err = anyhow::Error;
+
+    /// Parses `name:WIDTHxHEIGHT` with an optional `:bgra` or `:uyvy`, such as
+    /// `preview:640x360:uyvy`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default().trim();
+        if name.is_empty() {
+            bail!("Rendition {:?} has no name", s);
+        }
+        let size: Size = parts
+            .next()
+            .ok_or_else(|| anyhow!("Rendition {:?} has no size", s))?
+            .parse()
+            .with_context(|| format!("Invalid size for rendition {:?}", name))?;
+        if !size.width.is_multiple_of(2) {
+            bail!("Rendition {:?} must have an even width", name);
+        }
+        let format = match parts.next() {
+            None | Some("bgra") => PixelFormat::Bgra,
+            Some("uyvy") => PixelFormat::Uyvy,
+            Some(format) => bail!(
+                "Unknown pixel format {:?} for rendition {:?}; expected bgra or uyvy",
+                format,
+                name
+            ),
+        };
+        if parts.next().is_some() {
+            bail!("Rendition {:?} has trailing fields", s);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            size,
+            format,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    frame: Option<Arc<Frame>>,
+    stopped: bool,
+}
+
+struct Shared {
+    slot: Mutex<Slot>,
+    wake: Condvar,
+    dropped: Arc<Counter>,
+}
+
+/// Scales and converts frames for one rendition on its own thread.
+///
+/// Only the most recent frame waits to be processed, so a rendition that
+/// can't keep up drops its own frames without holding up the others.
+pub struct Rendition {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Rendition {
+    pub fn new(
+        spec: &RenditionSpec,
+        filter: Filter,
+        fit: Fit,
+        sink: Arc<dyn VideoSink>,
+        metrics: &PipelineMetrics,
+    ) -> Self {
+        let labels = [("rendition", spec.name.as_str())];
+        let sent = metrics.registry.counter(
+            "sckitndi_rendition_frames_sent_total",
+            "Video frames sent to the NDI sender of a rendition.",
+            &labels,
+        );
+        let dropped = metrics.registry.counter(
+            "sckitndi_rendition_frames_dropped_total",
+            "Frames a rendition skipped because it was still processing the previous one.",
+            &labels,
+        );
+        // One frame being scaled, one converted and one in flight in NDI.
+        let pool = FramePool::new(3, metrics.buffers_in_use.clone());
+        metrics.buffers_capacity.add(pool.capacity() as i64);
+        let shared = Arc::new(Shared {
+            slot: Mutex::new(Slot::default()),
+            wake: Condvar::new(),
+            dropped,
+        });
+        let scaler = Scaler::new(spec.size, filter, fit);
+        let format = spec.format;
+        let thread = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name(format!("rendition-{}", spec.name))
+                .spawn(move || {
+                    while let Some(frame) = next_frame(&shared) {
+                        let mut frame = scaler.scale(&frame, Some(&pool));
+                        if format == PixelFormat::Uyvy && frame.format() == PixelFormat::Bgra {
+                            frame = bgra_to_uyvy(&frame, Some(&pool));
+                        }
+                        sink.send_video(Arc::new(frame));
+                        sent.inc();
+                    }
+                })
+                .unwrap()
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn push(&self, frame: Arc<Frame>) {
+        let mut slot = self.shared.slot.lock().unwrap();
+        if slot.frame.replace(frame).is_some() {
+            self.shared.dropped.inc();
+        }
+        self.shared.wake.notify_one();
+    }
+}
+
+fn next_frame(shared: &Shared) -> Option<Arc<Frame>> {
+    let mut slot = shared.slot.lock().unwrap();
+    loop {
+        if slot.stopped {
+            return None;
+        }
+        if let Some(frame) = slot.frame.take() {
+            return Some(frame);
+        }
+        slot = shared.wake.wait(slot).unwrap();
+    }
+}
+
+impl Drop for Rendition {
+    fn drop(&mut self) {
+        self.shared.slot.lock().unwrap().stopped = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Hands every frame to each rendition before sending it to the main output.
+/// The renditions share the frame rather than copying it.
+pub struct FanOut {
+    main: Arc<dyn VideoSink>,
+    renditions: Vec<Rendition>,
+}
+
+impl FanOut {
+    pub fn new(main: Arc<dyn VideoSink>, renditions: Vec<Rendition>) -> Self {
+        Self { main, renditions }
+    }
+}
+
+impl VideoSink for FanOut {
+    fn send_video(&self, frame: Arc<Frame>) {
+        for rendition in &self.renditions {
+            rendition.push(frame.clone());
+        }
+        self.main.send_video(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver, Sender},
+        time::Duration,
+    };
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Passes frames on to a channel.
+    struct Channel(Mutex<Sender<Arc<Frame>>>);
+
+    impl VideoSink for Channel {
+        fn send_video(&self, frame: Arc<Frame>) {
+            self.0.lock().unwrap().send(frame).unwrap();
+        }
+    }
+
+    fn channel() -> (Arc<Channel>, Receiver<Arc<Frame>>) {
+        let (tx, rx) = mpsc::channel();
+        (Arc::new(Channel(Mutex::new(tx))), rx)
+    }
+
+    /// Holds each frame until the test releases it, like a stalled NDI send.
+    struct Blocked {
+        received: Mutex<Sender<Arc<Frame>>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl VideoSink for Blocked {
+        fn send_video(&self, frame: Arc<Frame>) {
+            self.received.lock().unwrap().send(frame).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+        }
+    }
+
+    fn frame(timestamp: i64) -> Arc<Frame> {
+        let mut frame = Frame::black(PixelFormat::Bgra, 64, 36, None);
+        frame.set_timestamp(Some(timestamp));
+        Arc::new(frame)
+    }
+
+    fn rendition(spec: &str, sink: Arc<dyn VideoSink>, metrics: &PipelineMetrics) -> Rendition {
+        Rendition::new(
+            &spec.parse().unwrap(),
+            Filter::Bilinear,
+            Fit::Letterbox,
+            sink,
+            metrics,
+        )
+    }
+
+    #[test]
+    fn parses_specs() {
+        let spec: RenditionSpec = " preview:640x360:uyvy".parse().unwrap();
+        assert_eq!(
+            spec,
+            RenditionSpec {
+                name: "preview".to_string(),
+                size: Size::new(640, 360),
+                format: PixelFormat::Uyvy,
+            }
+        );
+        let spec: RenditionSpec = "small:320x180".parse().unwrap();
+        assert_eq!(spec.format, PixelFormat::Bgra);
+        for invalid in [
+            ":640x360",
+            "preview",
+            "preview:640",
+            "preview:641x360",
+            "preview:640x360:nv12",
+            "preview:640x360:uyvy:extra",
+        ] {
+            assert!(invalid.parse::<RenditionSpec>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn sends_each_rendition_at_its_size_and_format() {
+        let metrics = PipelineMetrics::new();
+        let (main, main_rx) = channel();
+        let (small, small_rx) = channel();
+        let (preview, preview_rx) = channel();
+        let fan_out = FanOut::new(
+            main,
+            vec![
+                rendition("small:32x18", small, &metrics),
+                rendition("preview:16x16:uyvy", preview, &metrics),
+            ],
+        );
+        fan_out.send_video(frame(1));
+
+        let main = main_rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!((main.width(), main.height()), (64, 36));
+        let small = small_rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(
+            (small.format(), small.width(), small.height()),
+            (PixelFormat::Bgra, 32, 18)
+        );
+        let preview = preview_rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(
+            (preview.format(), preview.width(), preview.height()),
+            (PixelFormat::Uyvy, 16, 16)
+        );
+        assert_eq!(preview.timestamp(), Some(1));
+    }
+
+    #[test]
+    fn drops_frames_for_a_blocked_rendition_without_holding_up_the_rest() {
+        let metrics = PipelineMetrics::new();
+        let (main, main_rx) = channel();
+        let (received_tx, received) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let blocked = Arc::new(Blocked {
+            received: Mutex::new(received_tx),
+            release: Mutex::new(release_rx),
+        });
+        let fan_out = FanOut::new(main, vec![rendition("slow:32x18", blocked, &metrics)]);
+        fan_out.send_video(frame(1));
+        assert_eq!(received.recv_timeout(TIMEOUT).unwrap().timestamp(), Some(1));
+
+        // The rendition is stuck on frame 1; the main output carries on.
+        for timestamp in 2..=4 {
+            fan_out.send_video(frame(timestamp));
+        }
+        let sent: Vec<_> = main_rx.try_iter().map(|f| f.timestamp().unwrap()).collect();
+        assert_eq!(sent, [1, 2, 3, 4]);
+        // Frames 2 and 3 were each replaced while waiting.
+        assert_eq!(fan_out.renditions[0].shared.dropped.get(), 2);
+
+        release.send(()).unwrap();
+        assert_eq!(received.recv_timeout(TIMEOUT).unwrap().timestamp(), Some(4));
+        release.send(()).unwrap();
+        drop(fan_out);
+        assert!(received.try_recv().is_err());
+    }
+}
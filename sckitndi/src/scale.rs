@@ -0,0 +1,744 @@
+use std::{str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    frame::{Frame, PixelFormat},
+    geometry::{Rect, Size},
+    pool::FramePool,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    #[default]
+    Bilinear,
+    /// Catmull-Rom; sharper than bilinear, with slight ringing on hard edges.
+    Bicubic,
+    /// Averages exactly the source area each output pixel covers; best for
+    /// shrinking text.
+    Area,
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "bilinear" => Ok(Filter::Bilinear),
+            "bicubic" => Ok(Filter::Bicubic),
+            "area" => Ok(Filter::Area),
+            _ => Err(anyhow!(
+                "Unknown scale filter {:?}; expected bilinear, bicubic or area",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    /// Keeps the aspect ratio, adding black bars above and below or at the
+    /// sides.
+    #[default]
+    Letterbox,
+    Stretch,
+}
+
+impl FromStr for Fit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "letterbox" => Ok(Fit::Letterbox),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(anyhow!(
+                "Unknown scale fit {:?}; expected letterbox or stretch",
+                s
+            )),
+        }
+    }
+}
+
+/// Where a `source` sized picture lands in an `output` sized frame, in whole
+/// pixels. Horizontal position and width are even so that 4:2:2 chroma pairs
+/// line up.
+pub fn fit_rect(source: Size, output: Size, fit: Fit) -> Rect {
+    let (width, height) = match fit {
+        Fit::Stretch => (output.width, output.height),
+        Fit::Letterbox => {
+            let scale = f64::min(
+                output.width as f64 / source.width as f64,
+                output.height as f64 / source.height as f64,
+            );
+            let width = ((source.width as f64 * scale).round() as usize).min(output.width);
+            let height = ((source.height as f64 * scale).round() as usize).min(output.height);
+            ((width & !1).max(2).min(output.width), height.max(1))
+        }
+    };
+    let x = ((output.width - width) / 2) & !1;
+    let y = (output.height - height) / 2;
+    Rect::new(x as f64, y as f64, width as f64, height as f64)
+}
+
+/// Fractional bits of the fixed-point weights.
+const WEIGHT_BITS: u32 = 14;
+const WEIGHT_ONE: i32 = 1 << WEIGHT_BITS;
+const WEIGHT_HALF: i32 = WEIGHT_ONE / 2;
+
+/// The source pixels one output pixel is computed from.
+struct Contribution {
+    start: usize,
+    weights: Vec<f64>,
+}
+
+/// Fixed-point weights for resampling along one axis. Every output pixel
+/// reads the same number of source pixels, padded with zero weights, so the
+/// inner loops have a fixed shape.
+struct Weights {
+    taps: usize,
+    starts: Vec<usize>,
+    /// `taps` per output pixel, summing to exactly `WEIGHT_ONE`.
+    weights: Vec<i32>,
+}
+
+impl Weights {
+    fn new(src_len: usize, contributions: Vec<Contribution>) -> Self {
+        let taps = contributions
+            .iter()
+            .map(|c| c.weights.len())
+            .max()
+            .unwrap_or(1);
+        let mut starts = Vec::with_capacity(contributions.len());
+        let mut weights = vec![0; contributions.len() * taps];
+        for (contribution, out) in contributions.iter().zip(weights.chunks_exact_mut(taps)) {
+            let start = contribution.start.min(src_len - taps);
+            let offset = contribution.start - start;
+            let out = &mut out[offset..offset + contribution.weights.len()];
+            for (w, out) in contribution.weights.iter().zip(out.iter_mut()) {
+                *out = (w * WEIGHT_ONE as f64).round() as i32;
+            }
+            // Put the rounding error on the heaviest weight so that flat
+            // areas stay exactly flat.
+            let error = WEIGHT_ONE - out.iter().sum::<i32>();
+            if let Some(heaviest) = out.iter_mut().max() {
+                *heaviest += error;
+            }
+            starts.push(start);
+        }
+        Self {
+            taps,
+            starts,
+            weights,
+        }
+    }
+
+    fn get(&self, i: usize) -> (usize, &[i32]) {
+        (
+            self.starts[i],
+            &self.weights[i * self.taps..(i + 1) * self.taps],
+        )
+    }
+}
+
+fn triangle(x: f64) -> f64 {
+    (1. - x.abs()).max(0.)
+}
+
+fn catmull_rom(x: f64) -> f64 {
+    let x = x.abs();
+    if x < 1. {
+        1.5 * x * x * x - 2.5 * x * x + 1.
+    } else if x < 2. {
+        -0.5 * x * x * x + 2.5 * x * x - 4. * x + 2.
+    } else {
+        0.
+    }
+}
+
+/// Weights for resampling `src_len` pixels to `dst_len` along one axis.
+fn weights(src_len: usize, dst_len: usize, filter: Filter) -> Weights {
+    let scale = src_len as f64 / dst_len as f64;
+    let contributions = (0..dst_len)
+        .map(|i| {
+            let (start, weights) = match filter {
+                Filter::Area => {
+                    let (left, right) = (i as f64 * scale, (i + 1) as f64 * scale);
+                    let start = left.floor() as usize;
+                    let end = (right.ceil() as usize).min(src_len);
+                    let weights = (start..end)
+                        .map(|j| right.min(j as f64 + 1.) - left.max(j as f64))
+                        .collect();
+                    (start, weights)
+                }
+                Filter::Bilinear | Filter::Bicubic => {
+                    let (support, kernel): (f64, fn(f64) -> f64) = match filter {
+                        Filter::Bicubic => (2., catmull_rom),
+                        _ => (1., triangle),
+                    };
+                    // Widening the kernel when shrinking lets every source
+                    // pixel contribute instead of aliasing.
+                    let stretch = scale.max(1.);
+                    let center = (i as f64 + 0.5) * scale;
+                    let start = (center - support * stretch).floor().max(0.) as usize;
+                    let end = ((center + support * stretch).ceil() as usize).min(src_len);
+                    let weights = (start..end)
+                        .map(|j| kernel((j as f64 + 0.5 - center) / stretch))
+                        .collect();
+                    (start, weights)
+                }
+            };
+            normalize(start, weights, center_pixel(i, scale, src_len))
+        })
+        .collect();
+    Weights::new(src_len, contributions)
+}
+
+fn center_pixel(i: usize, scale: f64, src_len: usize) -> usize {
+    (((i as f64 + 0.5) * scale) as usize).min(src_len - 1)
+}
+
+fn normalize(start: usize, weights: Vec<f64>, fallback: usize) -> Contribution {
+    let sum: f64 = weights.iter().sum();
+    if sum.abs() < f64::EPSILON {
+        return Contribution {
+            start: fallback,
+            weights: vec![1.],
+        };
+    }
+    Contribution {
+        start,
+        weights: weights.iter().map(|w| w / sum).collect(),
+    }
+}
+
+fn to_sample(sum: i32) -> u8 {
+    ((sum + WEIGHT_HALF) >> WEIGHT_BITS).clamp(0, 255) as u8
+}
+
+/// Interleaved 8-bit samples.
+struct Plane<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+/// Contributions for one resampling, kept while the sizes stay the same.
+struct Kernel {
+    sizes: (Size, Size),
+    columns: Weights,
+    rows: Weights,
+}
+
+#[derive(Default)]
+struct Scratch {
+    /// Horizontally resampled source rows, as a ring of the most recent ones
+    /// keyed by source row.
+    rows: Vec<u8>,
+    row_ids: Vec<Option<usize>>,
+    /// One output row being accumulated.
+    row: Vec<i32>,
+    kernels: Vec<Kernel>,
+}
+
+/// Enough kernels for the planes of one format.
+const MAX_KERNELS: usize = 2;
+
+impl Scratch {
+    /// Index of the kernel for resampling `src` to `dst`.
+    fn kernel(&mut self, src: Size, dst: Size, filter: Filter) -> usize {
+        let sizes = (src, dst);
+        if let Some(i) = self.kernels.iter().position(|k| k.sizes == sizes) {
+            return i;
+        }
+        if self.kernels.len() == MAX_KERNELS {
+            self.kernels.remove(0);
+        }
+        self.kernels.push(Kernel {
+            sizes,
+            columns: weights(src.width, dst.width, filter),
+            rows: weights(src.height, dst.height, filter),
+        });
+        self.kernels.len() - 1
+    }
+}
+
+/// Resamples `src` of `C` channels per pixel into the top-left
+/// `width`x`height` pixels of `dst`.
+fn resample<const C: usize>(
+    src: &Plane,
+    dst: &mut [u8],
+    dst_stride: usize,
+    (width, height): (usize, usize),
+    filter: Filter,
+    scratch: &mut Scratch,
+) {
+    let index = scratch.kernel(
+        Size::new(src.width, src.height),
+        Size::new(width, height),
+        filter,
+    );
+    let Scratch {
+        rows,
+        row_ids,
+        row,
+        kernels,
+    } = scratch;
+    let kernel = &kernels[index];
+
+    // Each output row resamples its source rows horizontally, then combines
+    // them vertically. Consecutive output rows share most source rows, so
+    // those are kept in a ring big enough for the widest contribution.
+    let row_len = width * C;
+    let (columns, ring) = (&kernel.columns, kernel.rows.taps);
+    rows.clear();
+    rows.resize(row_len * ring, 0);
+    row_ids.clear();
+    row_ids.resize(ring, None);
+    for y in 0..height {
+        let (start, weights) = kernel.rows.get(y);
+        for source_row in start..start + ring {
+            let slot = source_row % ring;
+            if row_ids[slot] == Some(source_row) {
+                continue;
+            }
+            row_ids[slot] = Some(source_row);
+            let src_row = &src.data[source_row * src.stride..][..src.width * C];
+            let out = &mut rows[slot * row_len..][..row_len];
+            let taps = columns.taps;
+            for ((out, &start), weights) in out
+                .chunks_exact_mut(C)
+                .zip(&columns.starts)
+                .zip(columns.weights.chunks_exact(taps))
+            {
+                let pixels = &src_row[start * C..][..taps * C];
+                let mut sum = [0i32; C];
+                for (k, w) in weights.iter().enumerate() {
+                    let pixel: &[u8; C] = pixels[k * C..][..C].try_into().unwrap();
+                    for c in 0..C {
+                        sum[c] += w * pixel[c] as i32;
+                    }
+                }
+                out.copy_from_slice(&sum.map(to_sample));
+            }
+        }
+        row.clear();
+        row.resize(row_len, 0);
+        for (k, w) in weights.iter().enumerate() {
+            let slot = (start + k) % ring;
+            let src_row = &rows[slot * row_len..][..row_len];
+            for (sum, sample) in row.iter_mut().zip(src_row) {
+                *sum += w * *sample as i32;
+            }
+        }
+        let out = &mut dst[y * dst_stride..][..row_len];
+        for (sample, sum) in out.iter_mut().zip(row.iter()) {
+            *sample = to_sample(*sum);
+        }
+    }
+}
+
+/// Scales frames to a fixed output size.
+pub struct Scaler {
+    size: Size,
+    filter: Filter,
+    fit: Fit,
+    scratch: Mutex<Scratch>,
+}
+
+impl Scaler {
+    /// `size.width` must be even.
+    pub fn new(size: Size, filter: Filter, fit: Fit) -> Self {
+        debug_assert!(size.width.is_multiple_of(2));
+        Self {
+            size,
+            filter,
+            fit,
+            scratch: Mutex::new(Scratch::default()),
+        }
+    }
+
+    /// Where a `source` sized frame lands in the output.
+    pub fn output_rect(&self, source: Size) -> Rect {
+        fit_rect(source, self.size, self.fit)
+    }
+
+    pub fn scale(&self, frame: &Frame, pool: Option<&FramePool>) -> Frame {
+        let source = Size::new(frame.width(), frame.height());
+        let rect = self.output_rect(source);
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        let (width, height) = (rect.width as usize, rect.height as usize);
+        let format = frame.format();
+        let mut out = Frame::black(format, self.size.width, self.size.height, pool);
+        out.set_timestamp(frame.timestamp());
+        out.set_cursor(frame.cursor());
+        let stride = out.stride();
+        let dst = &mut out.data_mut()[y * stride + x * format.bytes_per_pixel()..];
+        let mut scratch = self.scratch.lock().unwrap();
+        match format {
+            PixelFormat::Bgra => {
+                let src = Plane {
+                    data: frame.data(),
+                    width: source.width,
+                    height: source.height,
+                    stride: frame.stride(),
+                };
+                resample::<4>(
+                    &src,
+                    dst,
+                    stride,
+                    (width, height),
+                    self.filter,
+                    &mut scratch,
+                );
+            }
+            PixelFormat::Uyvy => {
+                let (luma, chroma) = split_uyvy(frame);
+                let mut scaled_luma = vec![0; width * height];
+                let mut scaled_chroma = vec![0; width * height];
+                let plane = |data, channels| Plane {
+                    data,
+                    width: source.width / channels,
+                    height: source.height,
+                    stride: source.width,
+                };
+                resample::<1>(
+                    &plane(&luma, 1),
+                    &mut scaled_luma,
+                    width,
+                    (width, height),
+                    self.filter,
+                    &mut scratch,
+                );
+                resample::<2>(
+                    &plane(&chroma, 2),
+                    &mut scaled_chroma,
+                    width,
+                    (width / 2, height),
+                    self.filter,
+                    &mut scratch,
+                );
+                for row in 0..height {
+                    let out = &mut dst[row * stride..][..width * 2];
+                    let luma = &scaled_luma[row * width..][..width];
+                    let chroma = &scaled_chroma[row * width..][..width];
+                    for (i, pair) in out.chunks_exact_mut(4).enumerate() {
+                        pair.copy_from_slice(&[
+                            chroma[i * 2],
+                            luma[i * 2],
+                            chroma[i * 2 + 1],
+                            luma[i * 2 + 1],
+                        ]);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Splits a UYVY frame into a luma plane and an interleaved half-width chroma
+/// plane, both with rows `width` bytes long.
+fn split_uyvy(frame: &Frame) -> (Vec<u8>, Vec<u8>) {
+    let (width, height) = (frame.width(), frame.height());
+    let mut luma = Vec::with_capacity(width * height);
+    let mut chroma = Vec::with_capacity(width * height);
+    for row in frame.data().chunks(frame.stride()).take(height) {
+        for pair in row[..width * 2].chunks_exact(4) {
+            luma.extend_from_slice(&[pair[1], pair[3]]);
+            chroma.extend_from_slice(&[pair[0], pair[2]]);
+        }
+    }
+    (luma, chroma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 3] = [Filter::Bilinear, Filter::Bicubic, Filter::Area];
+
+    /// Gradients crossed with hard edges, different in every channel.
+    fn pattern(width: usize, height: usize, channels: usize) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| {
+                (0..width).flat_map(move |x| {
+                    (0..channels).map(move |c| ((x * 37 + y * 91 + c * 53) ^ (x * y)) as u8)
+                })
+            })
+            .collect()
+    }
+
+    fn frame(format: PixelFormat, width: usize, height: usize, data: Vec<u8>) -> Frame {
+        let stride = width * format.bytes_per_pixel();
+        Frame::with_format(format, width, height, stride, data, None)
+    }
+
+    fn stretch(frame: &Frame, (width, height): (usize, usize), filter: Filter) -> Frame {
+        Scaler::new(Size::new(width, height), filter, Fit::Stretch).scale(frame, None)
+    }
+
+    /// Normalized floating-point weights of every source pixel for each
+    /// output pixel, written out directly from the filter definitions.
+    fn reference_weights(src_len: usize, dst_len: usize, filter: Filter) -> Vec<Vec<f64>> {
+        let scale = src_len as f64 / dst_len as f64;
+        (0..dst_len)
+            .map(|i| {
+                let weights: Vec<f64> = (0..src_len)
+                    .map(|j| {
+                        let j = j as f64;
+                        match filter {
+                            Filter::Area => {
+                                let (left, right) = (i as f64 * scale, (i + 1) as f64 * scale);
+                                (right.min(j + 1.) - left.max(j)).max(0.)
+                            }
+                            Filter::Bilinear | Filter::Bicubic => {
+                                let kernel = match filter {
+                                    Filter::Bicubic => catmull_rom,
+                                    _ => triangle,
+                                };
+                                let stretch = scale.max(1.);
+                                kernel((j + 0.5 - (i as f64 + 0.5) * scale) / stretch)
+                            }
+                        }
+                    })
+                    .collect();
+                let sum: f64 = weights.iter().sum();
+                weights.iter().map(|w| w / sum).collect()
+            })
+            .collect()
+    }
+
+    /// Resamples interleaved `channels` rows first and columns second,
+    /// rounding in between like the fixed-point resampler.
+    fn reference(
+        src: &[u8],
+        (width, height): (usize, usize),
+        channels: usize,
+        (dst_width, dst_height): (usize, usize),
+        filter: Filter,
+    ) -> Vec<u8> {
+        let sample = |v: f64| v.round().clamp(0., 255.) as u8;
+        let columns = reference_weights(width, dst_width, filter);
+        let rows = reference_weights(height, dst_height, filter);
+        let mut wide = vec![0; dst_width * height * channels];
+        for y in 0..height {
+            for (x, weights) in columns.iter().enumerate() {
+                for c in 0..channels {
+                    let sum = weights
+                        .iter()
+                        .enumerate()
+                        .map(|(j, w)| w * src[(y * width + j) * channels + c] as f64);
+                    wide[(y * dst_width + x) * channels + c] = sample(sum.sum());
+                }
+            }
+        }
+        let mut out = vec![0; dst_width * dst_height * channels];
+        for (y, weights) in rows.iter().enumerate() {
+            for x in 0..dst_width * channels {
+                let sum = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(j, w)| w * wide[j * dst_width * channels + x] as f64);
+                out[y * dst_width * channels + x] = sample(sum.sum());
+            }
+        }
+        out
+    }
+
+    fn assert_close(actual: &[u8], expected: &[u8], context: &str) {
+        assert_eq!(actual.len(), expected.len(), "{context}");
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!(
+                a.abs_diff(*e) <= 1,
+                "{context}: sample {i} is {a}, expected {e}"
+            );
+        }
+    }
+
+    /// Widths are even as UYVY needs.
+    const SIZES: [((usize, usize), (usize, usize)); 4] = [
+        ((16, 10), (6, 4)),
+        ((14, 7), (8, 6)),
+        ((6, 5), (14, 11)),
+        ((12, 8), (12, 8)),
+    ];
+
+    #[test]
+    fn matches_the_reference_on_bgra() {
+        for filter in FILTERS {
+            for (src, dst) in SIZES {
+                let data = pattern(src.0, src.1, 4);
+                let out = stretch(
+                    &frame(PixelFormat::Bgra, src.0, src.1, data.clone()),
+                    dst,
+                    filter,
+                );
+                assert_close(
+                    out.data(),
+                    &reference(&data, src, 4, dst, filter),
+                    &format!("{filter:?} {src:?} to {dst:?}"),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_reference_on_uyvy() {
+        for filter in FILTERS {
+            for (src, dst) in SIZES {
+                let data = pattern(src.0, src.1, 2);
+                let out = stretch(
+                    &frame(PixelFormat::Uyvy, src.0, src.1, data.clone()),
+                    dst,
+                    filter,
+                );
+                let source = frame(PixelFormat::Uyvy, src.0, src.1, data);
+                let (luma, chroma) = split_uyvy(&source);
+                let (out_luma, out_chroma) = split_uyvy(&out);
+                let context = format!("{filter:?} {src:?} to {dst:?}");
+                assert_close(&out_luma, &reference(&luma, src, 1, dst, filter), &context);
+                // Chroma pairs are resampled together at half width.
+                let half = |(width, height): (usize, usize)| (width / 2, height);
+                assert_close(
+                    &out_chroma,
+                    &reference(&chroma, half(src), 2, half(dst), filter),
+                    &context,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn copies_at_the_same_size() {
+        for filter in FILTERS {
+            let data = pattern(12, 8, 4);
+            let out = stretch(
+                &frame(PixelFormat::Bgra, 12, 8, data.clone()),
+                (12, 8),
+                filter,
+            );
+            assert_eq!(out.data(), data, "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_flat_areas_flat() {
+        for filter in FILTERS {
+            for dst in [(6, 4), (22, 14)] {
+                let bgra = [12, 34, 56, 78];
+                let out = stretch(
+                    &frame(PixelFormat::Bgra, 10, 6, bgra.repeat(60)),
+                    dst,
+                    filter,
+                );
+                assert_eq!(out.data(), bgra.repeat(dst.0 * dst.1), "{filter:?} {dst:?}");
+                let uyvy = [90, 60, 170, 60];
+                let out = stretch(
+                    &frame(PixelFormat::Uyvy, 10, 6, uyvy.repeat(30)),
+                    dst,
+                    filter,
+                );
+                assert_eq!(
+                    out.data(),
+                    uyvy.repeat(dst.0 * dst.1 / 2),
+                    "{filter:?} {dst:?}"
+                );
+            }
+        }
+    }
+
+    fn gray_row(filter: Filter, src: &[u8], width: usize) -> Vec<u8> {
+        let data = src.iter().flat_map(|&v| [v, v, v, 0xff]).collect();
+        let out = stretch(
+            &frame(PixelFormat::Bgra, src.len(), 1, data),
+            (width, 1),
+            filter,
+        );
+        out.data().chunks_exact(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn interpolates_known_values() {
+        assert_eq!(gray_row(Filter::Bilinear, &[0, 200], 4), [0, 50, 150, 200]);
+        assert_eq!(gray_row(Filter::Area, &[0, 100, 200, 40], 2), [50, 120]);
+        // Output pixels straddle the middle source pixel.
+        assert_eq!(gray_row(Filter::Area, &[0, 90, 180], 2), [30, 150]);
+        // Catmull-Rom overshoots on hard edges; the overshoot is clamped.
+        let edge = gray_row(Filter::Bicubic, &[0, 0, 255, 255], 8);
+        assert_eq!((edge[0], edge[7]), (0, 255));
+        assert!(edge.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn letterboxes_inside_black_bars() {
+        let scaler = Scaler::new(Size::new(4, 4), Filter::Bilinear, Fit::Letterbox);
+        let data = pattern(4, 2, 4);
+        let mut source = frame(PixelFormat::Bgra, 4, 2, data.clone());
+        source.set_timestamp(Some(42));
+        let out = scaler.scale(&source, None);
+        let black = Frame::black(PixelFormat::Bgra, 4, 1, None).data().to_vec();
+        assert_eq!(&out.data()[..16], &black[..]);
+        assert_eq!(&out.data()[16..48], &data[..]);
+        assert_eq!(&out.data()[48..], &black[..]);
+        assert_eq!(out.timestamp(), Some(42));
+    }
+
+    #[test]
+    fn keeps_kernels_for_both_uyvy_planes() {
+        let scaler = Scaler::new(Size::new(8, 4), Filter::Bicubic, Fit::Stretch);
+        let source = frame(PixelFormat::Uyvy, 12, 6, pattern(12, 6, 2));
+        let first = scaler.scale(&source, None);
+        let other = frame(PixelFormat::Bgra, 5, 3, pattern(5, 3, 4));
+        scaler.scale(&other, None);
+        assert_eq!(scaler.scale(&source, None).data(), first.data());
+    }
+
+    #[test]
+    fn fits_wide_sources_with_bars_above_and_below() {
+        assert_eq!(
+            fit_rect(Size::new(1920, 1080), Size::new(1280, 1024), Fit::Letterbox),
+            Rect::new(0., 152., 1280., 720.)
+        );
+    }
+
+    #[test]
+    fn fits_tall_sources_with_bars_at_the_sides() {
+        assert_eq!(
+            fit_rect(Size::new(1000, 1000), Size::new(1920, 1080), Fit::Letterbox),
+            Rect::new(420., 0., 1080., 1080.)
+        );
+        // Widths and horizontal offsets stay even.
+        assert_eq!(
+            fit_rect(Size::new(999, 1000), Size::new(1920, 1080), Fit::Letterbox),
+            Rect::new(420., 0., 1078., 1080.)
+        );
+    }
+
+    #[test]
+    fn fits_tiny_and_stretched_sources() {
+        assert_eq!(
+            fit_rect(Size::new(1, 1000), Size::new(1920, 1080), Fit::Letterbox),
+            Rect::new(958., 0., 2., 1080.)
+        );
+        assert_eq!(
+            fit_rect(Size::new(1000, 1), Size::new(1920, 1080), Fit::Letterbox),
+            Rect::new(0., 539., 1920., 2.)
+        );
+        assert_eq!(
+            fit_rect(Size::new(640, 480), Size::new(1920, 1080), Fit::Stretch),
+            Rect::from_size(1920., 1080.)
+        );
+    }
+
+    #[test]
+    fn parses_filters_and_fits() {
+        assert_eq!("area".parse::<Filter>().unwrap(), Filter::Area);
+        assert!("nearest".parse::<Filter>().is_err());
+        assert_eq!("stretch".parse::<Fit>().unwrap(), Fit::Stretch);
+        assert!("crop".parse::<Fit>().is_err());
+    }
+}
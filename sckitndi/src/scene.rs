@@ -0,0 +1,182 @@
+use std::{collections::BTreeSet, fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    config::Config,
+    frame::PixelFormat,
+    geometry::{Rect, Size},
+    mask::Mask,
+    overlay::{Compositor, OverlaySpec},
+    pip::LayerSource,
+    redact::TitlePattern,
+    scale::Scaler,
+};
+
+/// A named preset of what to capture and how to present it. Fields a scene
+/// leaves out keep the setting from the environment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneSpec {
+    pub name: String,
+    /// What to capture; the environment's default is the first display.
+    #[serde(default)]
+    pub source: Option<LayerSource>,
+    /// `Some(None)`, from an explicit `null`, captures the whole source even
+    /// if the environment sets a crop.
+    #[serde(
+        default,
+        deserialize_with = "explicit_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub crop: Option<Option<Rect>>,
+    #[serde(default)]
+    pub masks: Option<Vec<Mask>>,
+    #[serde(default)]
+    pub redact_titles: Option<Vec<TitlePattern>>,
+    #[serde(default)]
+    pub overlays: Option<Vec<OverlaySpec>>,
+    #[serde(default)]
+    pub output_size: Option<Size>,
+    #[serde(default)]
+    pub pixel_format: Option<PixelFormat>,
+}
+
+/// Tells an explicit `null` apart from a missing field, which `default`
+/// leaves as `None`.
+fn explicit_null<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Reads scene specs from a JSON array.
+pub fn load_scenes(path: &Path) -> Result<Vec<SceneSpec>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let scenes: Vec<SceneSpec> = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Invalid scenes in {}", path.display()))?;
+    let mut names = BTreeSet::new();
+    for scene in &scenes {
+        if scene.name.is_empty() {
+            bail!("A scene has no name");
+        }
+        if !names.insert(scene.name.as_str()) {
+            bail!("Scene {:?} is defined twice", scene.name);
+        }
+        if scene
+            .output_size
+            .is_some_and(|size| !size.width.is_multiple_of(2))
+        {
+            bail!("Scene {:?} must have an even output width", scene.name);
+        }
+    }
+    Ok(scenes)
+}
+
+/// A scene with the environment's settings filled in and its overlays and
+/// scaler built, so that switching to it is instant.
+pub struct Scene {
+    pub name: String,
+    pub source: Option<LayerSource>,
+    pub crop: Option<Rect>,
+    pub masks: Vec<Mask>,
+    pub redact_titles: Vec<TitlePattern>,
+    pub compositor: Arc<Compositor>,
+    pub scaler: Option<Arc<Scaler>>,
+    pub pixel_format: PixelFormat,
+}
+
+impl Scene {
+    pub fn new(spec: &SceneSpec, config: &Config) -> Result<Self> {
+        let overlays = spec.overlays.as_ref().unwrap_or(&config.overlays);
+        let compositor = Compositor::new(overlays)
+            .with_context(|| format!("Invalid overlays in scene {:?}", spec.name))?;
+        Ok(Self {
+            name: spec.name.clone(),
+            source: spec.source.clone(),
+            crop: spec.crop.unwrap_or(config.crop),
+            masks: spec.masks.clone().unwrap_or_else(|| config.masks.clone()),
+            redact_titles: spec
+                .redact_titles
+                .clone()
+                .unwrap_or_else(|| config.redact_titles.clone()),
+            compositor: Arc::new(compositor),
+            scaler: spec
+                .output_size
+                .or(config.output_size)
+                .map(|size| Arc::new(Scaler::new(size, config.scale_filter, config.scale_fit))),
+            pixel_format: spec.pixel_format.unwrap_or(config.pixel_format),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(json: &str, config: &Config) -> Scene {
+        let spec: SceneSpec = serde_json::from_str(json).unwrap();
+        Scene::new(&spec, config).unwrap()
+    }
+
+    fn cropped() -> Config {
+        Config {
+            crop: Some(Rect::new(0., 40., 1920., 1040.)),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn keeps_the_environments_crop_when_left_out() {
+        assert_eq!(
+            scene(r#"{"name": "a"}"#, &cropped()).crop,
+            Some(Rect::new(0., 40., 1920., 1040.))
+        );
+        let uncropped = Config {
+            crop: None,
+            ..Config::default()
+        };
+        assert_eq!(scene(r#"{"name": "a"}"#, &uncropped).crop, None);
+    }
+
+    #[test]
+    fn clears_the_crop_with_null() {
+        assert_eq!(
+            scene(r#"{"name": "a", "crop": null}"#, &cropped()).crop,
+            None
+        );
+    }
+
+    #[test]
+    fn replaces_the_crop() {
+        let json = r#"{"name": "a", "crop": {"x": 1, "y": 2, "width": 3, "height": 4}}"#;
+        assert_eq!(
+            scene(json, &cropped()).crop,
+            Some(Rect::new(1., 2., 3., 4.))
+        );
+    }
+
+    #[test]
+    fn serializes_a_cleared_crop_as_null() {
+        let spec: SceneSpec = serde_json::from_str(r#"{"name": "a", "crop": null}"#).unwrap();
+        let json = serde_json::to_value(&spec).unwrap();
+        assert_eq!(json["crop"], serde_json::Value::Null);
+        assert_eq!(serde_json::from_value::<SceneSpec>(json).unwrap(), spec);
+        let spec: SceneSpec = serde_json::from_str(r#"{"name": "a"}"#).unwrap();
+        let json = serde_json::to_value(&spec).unwrap();
+        assert!(json.get("crop").is_none());
+        assert_eq!(serde_json::from_value::<SceneSpec>(json).unwrap(), spec);
+    }
+
+    #[test]
+    fn clears_masks_with_an_empty_array() {
+        let config = Config {
+            masks: vec!["fill:0,0,10,10".parse().unwrap()],
+            ..Config::default()
+        };
+        assert_eq!(scene(r#"{"name": "a"}"#, &config).masks, config.masks);
+        assert!(scene(r#"{"name": "a", "masks": []}"#, &config)
+            .masks
+            .is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Serialize;
+
+use crate::{
+    frame::{Frame, PixelFormat},
+    geometry::{Rect, Size},
+    mask::{self, Color, Mask, MaskStyle},
+    overlay::{Anchor, Compositor, OverlayContent, OverlaySpec, TextStyle},
+    pacer::VideoSink,
+};
+
+/// What the output shows instead of the capture while the slate is up.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlateSpec {
+    pub color: Color,
+    /// Centered, or below the image if there is one; empty for none.
+    pub text: String,
+    /// Centered at its own size.
+    pub image: Option<PathBuf>,
+}
+
+impl Default for SlateSpec {
+    fn default() -> Self {
+        Self {
+            color: Color::BLACK,
+            text: "Be right back".to_string(),
+            image: None,
+        }
+    }
+}
+
+/// The size of a slate sent before the capture has produced a frame, when no
+/// output size is set.
+const FALLBACK_SIZE: Size = Size::new(1920, 1080);
+
+/// Replaces every outgoing frame with the slate while it is up.
+///
+/// The slate is drawn at the size of the frames it replaces, so receivers
+/// see no format change, and it is sent at the pacer's cadence like any other
+/// frame, including before the capture has started.
+pub struct SlateSink {
+    color: Color,
+    compositor: Compositor,
+    active: AtomicBool,
+    /// The size of the last frame seen, to draw idle slates at.
+    size: Mutex<Size>,
+    /// The slate as last drawn, reused while the size stays the same.
+    drawn: Mutex<Option<Arc<Frame>>>,
+    inner: Arc<dyn VideoSink>,
+}
+
+impl SlateSink {
+    /// Loads the image up front so that a bad path fails at startup.
+    /// `output_size` is the size to draw at until a frame has been seen.
+    pub fn new(
+        spec: &SlateSpec,
+        output_size: Option<Size>,
+        inner: Arc<dyn VideoSink>,
+    ) -> Result<Self> {
+        let overlay = |content, anchor| OverlaySpec {
+            content,
+            anchor,
+            safe_area: 0.05,
+            offset_x: 0,
+            offset_y: 0,
+            opacity: 1.,
+        };
+        let mut overlays = Vec::new();
+        if let Some(path) = &spec.image {
+            overlays.push(overlay(
+                OverlayContent::Image { path: path.clone() },
+                Anchor::Center,
+            ));
+        }
+        if !spec.text.is_empty() {
+            let text = OverlayContent::Text {
+                text: spec.text.clone(),
+                style: TextStyle {
+                    scale: 6,
+                    ..TextStyle::default()
+                },
+            };
+            let anchor = match spec.image {
+                Some(_) => Anchor::Bottom,
+                None => Anchor::Center,
+            };
+            overlays.push(overlay(text, anchor));
+        }
+        let compositor = Compositor::new(&overlays).context("Invalid slate")?;
+        Ok(Self {
+            color: spec.color,
+            compositor,
+            active: AtomicBool::new(false),
+            size: Mutex::new(output_size.unwrap_or(FALLBACK_SIZE)),
+            drawn: Mutex::new(None),
+            inner,
+        })
+    }
+
+    /// Puts the slate up or takes it down, returning whether it was up.
+    pub fn set_active(&self, active: bool) -> bool {
+        self.active.swap(active, Ordering::Relaxed)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn draw(&self, width: usize, height: usize) -> Arc<Frame> {
+        let mut drawn = self.drawn.lock().unwrap();
+        if let Some(frame) = drawn
+            .as_ref()
+            .filter(|frame| (frame.width(), frame.height()) == (width, height))
+        {
+            return frame.clone();
+        }
+        let mut frame = Frame::black(PixelFormat::Bgra, width, height, None);
+        mask::apply(
+            &mut frame,
+            &[Mask {
+                rect: Rect::from_size(width as f64, height as f64),
+                style: MaskStyle::Fill { color: self.color },
+            }],
+        );
+        self.compositor.composite(&mut frame, &Local::now());
+        let frame = Arc::new(frame);
+        *drawn = Some(frame.clone());
+        frame
+    }
+}
+
+impl VideoSink for SlateSink {
+    fn send_video(&self, frame: Arc<Frame>) {
+        *self.size.lock().unwrap() = Size::new(frame.width(), frame.height());
+        let frame = if self.is_active() {
+            self.draw(frame.width(), frame.height())
+        } else {
+            frame
+        };
+        self.inner.send_video(frame);
+    }
+
+    fn send_idle(&self) {
+        if self.is_active() {
+            let size = *self.size.lock().unwrap();
+            self.inner.send_video(self.draw(size.width, size.height));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pacer::testing::Recorder;
+
+    const RED: Color = Color {
+        r: 0xff,
+        g: 0,
+        b: 0,
+    };
+
+    fn slate_sink(output_size: Option<Size>) -> (SlateSink, Arc<Recorder>) {
+        let spec = SlateSpec {
+            color: RED,
+            text: String::new(),
+            image: None,
+        };
+        let sender = Arc::new(Recorder::default());
+        let sink = SlateSink::new(&spec, output_size, sender.clone()).unwrap();
+        (sink, sender)
+    }
+
+    const GRAY: [u8; 4] = [0x80, 0x80, 0x80, 0xff];
+
+    fn is_slate(frame: &Frame) -> bool {
+        frame
+            .data()
+            .chunks(4)
+            .all(|pixel| pixel == [0, 0, 0xff, 0xff])
+    }
+
+    #[test]
+    fn replaces_frames_at_their_size_while_up() {
+        let (sink, sender) = slate_sink(None);
+        let frame = Arc::new(Frame::solid(4, 2, GRAY));
+        sink.send_video(frame.clone());
+        assert!(!sink.set_active(true));
+        sink.send_video(frame.clone());
+        sink.send_video(frame.clone());
+        assert!(sink.set_active(false));
+        sink.send_video(frame.clone());
+
+        let sent = sender.sent();
+        assert!(Arc::ptr_eq(&sent[0].1, &frame));
+        assert_eq!((sent[1].1.width(), sent[1].1.height()), (4, 2));
+        assert!(is_slate(&sent[1].1));
+        // Drawn once and reused.
+        assert!(Arc::ptr_eq(&sent[1].1, &sent[2].1));
+        assert!(Arc::ptr_eq(&sent[3].1, &frame));
+    }
+
+    #[test]
+    fn redraws_when_the_size_changes() {
+        let (sink, sender) = slate_sink(None);
+        sink.set_active(true);
+        sink.send_video(Arc::new(Frame::solid(4, 2, GRAY)));
+        sink.send_video(Arc::new(Frame::solid(6, 4, GRAY)));
+
+        let sent = sender.sent();
+        assert_eq!((sent[1].1.width(), sent[1].1.height()), (6, 4));
+        assert!(is_slate(&sent[1].1));
+    }
+
+    #[test]
+    fn sends_the_slate_on_idle_ticks_only_while_up() {
+        let (sink, sender) = slate_sink(Some(Size::new(8, 6)));
+        sink.send_idle();
+        assert!(sender.frames().is_empty());
+
+        sink.set_active(true);
+        sink.send_idle();
+        sink.send_video(Arc::new(Frame::solid(4, 2, GRAY)));
+        sink.send_idle();
+
+        let sent = sender.sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!((sent[0].1.width(), sent[0].1.height()), (8, 6));
+        // Once a frame has been seen, idle slates match it.
+        assert_eq!((sent[2].1.width(), sent[2].1.height()), (4, 2));
+        assert!(sent.iter().all(|(_, frame)| is_slate(frame)));
+    }
+
+    #[test]
+    fn keeps_the_output_running_through_the_slate() {
+        let (sink, sender) = slate_sink(Some(Size::new(4, 2)));
+        let capture = Arc::new(Frame::solid(4, 2, GRAY));
+        // Up before the capture has produced anything, then taken down and
+        // put up again while it runs.
+        let phases = [
+            (true, None),
+            (true, Some(&capture)),
+            (false, Some(&capture)),
+            (true, Some(&capture)),
+            (false, Some(&capture)),
+        ];
+        for (active, latest) in phases {
+            sink.set_active(active);
+            // Ticked the way the pacer does: idle until the capture has a
+            // frame, then its latest frame.
+            for _ in 0..5 {
+                match latest {
+                    Some(frame) => sink.send_video(frame.clone()),
+                    None => sink.send_idle(),
+                }
+            }
+        }
+
+        let sent = sender.frames();
+        assert_eq!(sent.len(), 25, "one frame per tick");
+        assert!(sent
+            .iter()
+            .all(|frame| (frame.width(), frame.height()) == (4, 2)));
+        let slates: Vec<_> = sent.iter().map(|frame| is_slate(frame)).collect();
+        let expected: Vec<_> = [true, true, false, true, false]
+            .into_iter()
+            .flat_map(|slate| [slate; 5])
+            .collect();
+        assert_eq!(slates, expected);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{command::Target, geometry::Rect, mask::Mask, permission::Permission};
+
+/// What to capture; `target: None` captures the first display.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Source {
+    pub target: Option<Target>,
+    pub crop: Option<Rect>,
+    pub masks: Vec<Mask>,
+}
+
+impl Source {
+    /// Switches to `target` with the masks saved for it, saving the current
+    /// masks for when the current target is captured again.
+    pub fn retarget(
+        &mut self,
+        target: Option<Target>,
+        saved_masks: &mut HashMap<Option<Target>, Vec<Mask>>,
+    ) {
+        if self.target == target {
+            return;
+        }
+        let masks = saved_masks.remove(&target).unwrap_or_default();
+        saved_masks.insert(self.target, std::mem::replace(&mut self.masks, masks));
+        self.target = target;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    pub running: bool,
+    pub paused: bool,
+    /// Whether the slate is up instead of the capture.
+    pub slate: bool,
+    pub source: Source,
+    /// The scene last switched to.
+    pub scene: Option<String>,
+    pub permission: Permission,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask::{Color, MaskStyle};
+
+    fn mask(x: f64) -> Mask {
+        Mask {
+            rect: Rect::new(x, 0., 10., 10.),
+            style: MaskStyle::Fill {
+                color: Color::BLACK,
+            },
+        }
+    }
+
+    #[test]
+    fn keeps_masks_per_source() {
+        let mut saved_masks = HashMap::new();
+        let mut source = Source {
+            target: None,
+            crop: None,
+            masks: vec![mask(0.)],
+        };
+
+        source.retarget(Some(Target::Window(7)), &mut saved_masks);
+        assert!(source.masks.is_empty());
+        source.masks = vec![mask(1.)];
+
+        source.retarget(Some(Target::Display(2)), &mut saved_masks);
+        assert!(source.masks.is_empty());
+
+        source.retarget(None, &mut saved_masks);
+        assert_eq!(source.masks, [mask(0.)]);
+        source.retarget(Some(Target::Window(7)), &mut saved_masks);
+        assert_eq!(source.masks, [mask(1.)]);
+
+        source.retarget(Some(Target::Window(7)), &mut saved_masks);
+        assert_eq!(source.masks, [mask(1.)]);
+    }
+}
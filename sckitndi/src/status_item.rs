@@ -0,0 +1,41 @@
+use cocoa_foundation::{
+    base::{id, nil},
+    foundation::NSString,
+};
+use objc::{class, msg_send, rc::StrongPtr, sel, sel_impl};
+
+/// `NSVariableStatusItemLength`.
+const VARIABLE_LENGTH: f64 = -1.;
+
+/// An item in the menu bar, which stays visible while the window is hidden
+/// or behind others.
+pub struct StatusItem(StrongPtr);
+
+impl StatusItem {
+    /// Must be called on the main thread.
+    pub fn new() -> Self {
+        unsafe {
+            let bar: id = msg_send![class!(NSStatusBar), systemStatusBar];
+            let item: id = msg_send![bar, statusItemWithLength: VARIABLE_LENGTH];
+            Self(StrongPtr::retain(item))
+        }
+    }
+
+    /// Must be called on the main thread.
+    pub fn set_title(&self, title: &str) {
+        unsafe {
+            let button: id = msg_send![*self.0, button];
+            let title = StrongPtr::new(NSString::alloc(nil).init_str(title));
+            let _: () = msg_send![button, setTitle: *title];
+        }
+    }
+}
+
+impl Drop for StatusItem {
+    fn drop(&mut self) {
+        unsafe {
+            let bar: id = msg_send![class!(NSStatusBar), systemStatusBar];
+            let _: () = msg_send![bar, removeStatusItem: *self.0];
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::observable::Observable;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub on_program: bool,
+    pub on_preview: bool,
+}
+
+impl Tally {
+    pub fn label(&self) -> &'static str {
+        match (self.on_program, self.on_preview) {
+            (true, _) => "PROGRAM",
+            (false, true) => "PREVIEW",
+            (false, false) => "OFF",
+        }
+    }
+}
+
+pub trait TallySource: Send + Sync {
+    /// Waits up to `timeout` for the tally to change and returns the current
+    /// tally, or `None` if it didn't change.
+    fn wait_for_tally(&self, timeout: Duration) -> Option<Tally>;
+}
+
+const POLL_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Watches a [`TallySource`] on a background thread and publishes changes.
+pub struct TallyMonitor {
+    tally: Arc<Observable<Tally>>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TallyMonitor {
+    pub fn new(source: Arc<dyn TallySource>) -> Self {
+        let tally = Arc::new(Observable::new(Tally::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let tally = tally.clone();
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name("tally".to_string())
+                .spawn(move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        if let Some(current) = source.wait_for_tally(POLL_TIMEOUT) {
+                            tally.set(current);
+                        }
+                    }
+                })
+                .unwrap()
+        };
+        Self {
+            tally,
+            stopped,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn tally(&self) -> &Observable<Tally> {
+        &self.tally
+    }
+}
+
+impl Drop for TallyMonitor {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex};
+
+    use super::*;
+
+    const PROGRAM: Tally = Tally {
+        on_program: true,
+        on_preview: false,
+    };
+    const PREVIEW: Tally = Tally {
+        on_program: false,
+        on_preview: true,
+    };
+
+    /// Replays scripted tally changes like a sender with receivers
+    /// switching it between program and preview.
+    struct FakeSender(Mutex<VecDeque<Option<Tally>>>);
+
+    impl TallySource for FakeSender {
+        fn wait_for_tally(&self, timeout: Duration) -> Option<Tally> {
+            let next = self.0.lock().unwrap().pop_front();
+            match next {
+                Some(tally) => tally,
+                None => {
+                    std::thread::sleep(timeout);
+                    None
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(Tally::default().label(), "OFF");
+        assert_eq!(PREVIEW.label(), "PREVIEW");
+        let both = Tally {
+            on_program: true,
+            on_preview: true,
+        };
+        assert_eq!(both.label(), "PROGRAM");
+    }
+
+    #[test]
+    fn publishes_changes_from_the_sender() {
+        let sender = Arc::new(FakeSender(Mutex::new(VecDeque::new())));
+        let monitor = TallyMonitor::new(sender.clone());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        {
+            let seen = seen.clone();
+            monitor
+                .tally()
+                .subscribe(move |tally| seen.lock().unwrap().push(*tally));
+        }
+        sender.0.lock().unwrap().extend([
+            Some(PREVIEW),
+            None,
+            Some(PREVIEW),
+            Some(PROGRAM),
+            Some(Tally::default()),
+        ]);
+        while !sender.0.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(monitor);
+        assert_eq!(*seen.lock().unwrap(), [PREVIEW, PROGRAM, Tally::default()]);
+    }
+
+    #[test]
+    fn stops_on_drop() {
+        let sender = Arc::new(FakeSender(Mutex::new(VecDeque::new())));
+        let monitor = TallyMonitor::new(sender.clone());
+        drop(monitor);
+        assert_eq!(Arc::strong_count(&sender), 1);
+    }
+}
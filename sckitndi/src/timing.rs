@@ -0,0 +1,162 @@
+use std::{
+    str::FromStr,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use sckit::CMTime;
+use serde::Serialize;
+
+/// NDI timecodes and timestamps are in units of 100ns.
+pub const NDI_TICKS_PER_SECOND: i64 = 10_000_000;
+
+/// `NDIlib_send_timecode_synthesize`, which bindgen can't see because it is
+/// defined as `INT64_MAX`.
+pub const TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimecodeMode {
+    /// Let the NDI SDK synthesize timecodes from the send rate.
+    #[default]
+    Synthesize,
+    /// UTC time at the moment of sending.
+    WallClock,
+    /// Presentation timestamp of the captured sample buffer.
+    CapturePts,
+}
+
+impl FromStr for TimecodeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "synthesize" => Ok(Self::Synthesize),
+            "wall-clock" => Ok(Self::WallClock),
+            "capture-pts" => Ok(Self::CapturePts),
+            _ => Err(anyhow::anyhow!("Unknown timecode mode: {}", s)),
+        }
+    }
+}
+
+/// Converts a `CMTime` to NDI ticks, rounding to the nearest tick.
+///
+/// Returns `None` for non-numeric or out of range times.
+pub fn cmtime_to_ticks(time: CMTime) -> Option<i64> {
+    let ticks = time.convert_scale(NDI_TICKS_PER_SECOND as i32);
+    ticks.is_numeric().then(|| ticks.value())
+}
+
+pub fn wall_clock_ticks() -> i64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_nanos() / 100) as i64
+}
+
+/// Picks the timecode of each outgoing frame according to a [`TimecodeMode`].
+pub struct Timing {
+    mode: TimecodeMode,
+    last: Mutex<Option<(i64, Instant)>>,
+}
+
+impl Timing {
+    pub fn new(mode: TimecodeMode) -> Self {
+        Self {
+            mode,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn timecode(&self, pts: Option<i64>) -> i64 {
+        match self.mode {
+            TimecodeMode::Synthesize => TIMECODE_SYNTHESIZE,
+            TimecodeMode::WallClock => wall_clock_ticks(),
+            TimecodeMode::CapturePts => match pts {
+                Some(pts) => self.advance(pts, Instant::now()),
+                None => TIMECODE_SYNTHESIZE,
+            },
+        }
+    }
+
+    /// Repeated frames carry the PTS of their original capture; move their
+    /// timecode forward by the time elapsed since the original was sent so
+    /// that timecodes stay monotonic.
+    fn advance(&self, pts: i64, now: Instant) -> i64 {
+        let mut last = self.last.lock().unwrap();
+        match *last {
+            Some((last_pts, sent_at)) if last_pts == pts => {
+                let elapsed = now.duration_since(sent_at).as_nanos() / 100;
+                pts.saturating_add(elapsed as i64)
+            }
+            _ => {
+                *last = Some((pts, now));
+                pts
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn parses_timecode_modes() {
+        assert_eq!(
+            "wall-clock".parse::<TimecodeMode>().unwrap(),
+            TimecodeMode::WallClock
+        );
+        assert_eq!(
+            "capture-pts".parse::<TimecodeMode>().unwrap(),
+            TimecodeMode::CapturePts
+        );
+        assert!("capture_pts".parse::<TimecodeMode>().is_err());
+    }
+
+    #[test]
+    fn converts_cmtimes_to_ticks() {
+        assert_eq!(cmtime_to_ticks(CMTime::new(3, 2)), Some(15_000_000));
+        assert_eq!(cmtime_to_ticks(CMTime::new(-1, 1)), Some(-10_000_000));
+        // 1001/30000 s is 333666.67 ticks.
+        assert_eq!(cmtime_to_ticks(CMTime::new(1001, 30000)), Some(333_667));
+        assert_eq!(cmtime_to_ticks(CMTime::new(1, 3)), Some(3_333_333));
+    }
+
+    #[test]
+    fn rejects_cmtimes_without_a_value() {
+        assert_eq!(cmtime_to_ticks(CMTime::INVALID), None);
+        assert_eq!(cmtime_to_ticks(CMTime::from_fps(30, 0)), None);
+    }
+
+    #[test]
+    fn synthesizes_without_a_pts() {
+        let timing = Timing::new(TimecodeMode::CapturePts);
+        assert_eq!(timing.timecode(None), TIMECODE_SYNTHESIZE);
+        let timing = Timing::new(TimecodeMode::Synthesize);
+        assert_eq!(timing.timecode(Some(42)), TIMECODE_SYNTHESIZE);
+    }
+
+    #[test]
+    fn advances_repeated_frames() {
+        let timing = Timing::new(TimecodeMode::CapturePts);
+        let start = Instant::now();
+        assert_eq!(timing.advance(1_000, start), 1_000);
+        assert_eq!(
+            timing.advance(1_000, start + Duration::from_millis(20)),
+            201_000
+        );
+        // A new frame restarts from its own PTS.
+        assert_eq!(
+            timing.advance(400_000, start + Duration::from_millis(40)),
+            400_000
+        );
+        assert_eq!(timing.advance(i64::MAX - 1, start), i64::MAX - 1);
+        assert_eq!(
+            timing.advance(i64::MAX - 1, start + Duration::from_secs(1)),
+            i64::MAX
+        );
+    }
+}
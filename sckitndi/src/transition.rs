@@ -0,0 +1,456 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    frame::{Frame, PixelFormat},
+    geometry::Size,
+    pacer::VideoSink,
+    pool::FramePool,
+    scale::{Filter, Fit, Scaler},
+};
+
+/// How long the last frame of the old source is held at most while waiting
+/// for the new one, in case the new source never delivers a frame.
+const HOLD_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    /// Switches on the first frame of the new source.
+    #[default]
+    Cut,
+    Crossfade,
+    /// Fades to black over the first half and up from black over the second.
+    DipToBlack,
+}
+
+impl FromStr for TransitionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cut" => Ok(TransitionKind::Cut),
+            "crossfade" => Ok(TransitionKind::Crossfade),
+            "dip_to_black" => Ok(TransitionKind::DipToBlack),
+            _ => Err(anyhow!(
+                "Unknown transition {:?}; expected cut, crossfade or dip_to_black",
+                s
+            )),
+        }
+    }
+}
+
+impl TransitionKind {
+    /// How much the old and the new picture show `progress` of the way
+    /// through the transition, from 0 to 1; black makes up the rest.
+    pub fn weights(self, progress: f64) -> (f64, f64) {
+        let progress = progress.clamp(0., 1.);
+        match self {
+            TransitionKind::Cut => (0., 1.),
+            TransitionKind::Crossfade => (1. - progress, progress),
+            TransitionKind::DipToBlack if progress < 0.5 => (1. - progress * 2., 0.),
+            TransitionKind::DipToBlack => (0., progress * 2. - 1.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: Duration,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            kind: TransitionKind::Cut,
+            duration: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Mixes two BGRA frames of the same size into `out`, with the weights of
+/// [`TransitionKind::weights`] and opaque black making up the rest.
+pub fn mix(from: &Frame, to: &Frame, (from_weight, to_weight): (f64, f64), out: &mut Frame) {
+    debug_assert_eq!(from.format(), PixelFormat::Bgra);
+    debug_assert_eq!(to.format(), PixelFormat::Bgra);
+    debug_assert_eq!((from.width(), from.height()), (out.width(), out.height()));
+    debug_assert_eq!((to.width(), to.height()), (out.width(), out.height()));
+    let (width, height) = (out.width(), out.height());
+    let from_weight = (from_weight.clamp(0., 1.) * 256.).round() as u32;
+    let to_weight = (to_weight.clamp(0., 1.) * 256.).round() as u32;
+    let black_weight = 256u32.saturating_sub(from_weight + to_weight);
+    let black = [0, 0, 0, 255].map(|v: u32| v * black_weight + 128);
+    let (from_stride, to_stride, out_stride) = (from.stride(), to.stride(), out.stride());
+    let (from, to) = (from.data(), to.data());
+    let out = out.data_mut();
+    for y in 0..height {
+        let from_row = &from[y * from_stride..][..width * 4];
+        let to_row = &to[y * to_stride..][..width * 4];
+        let out_row = &mut out[y * out_stride..][..width * 4];
+        for ((a, b), c) in from_row
+            .chunks_exact(4)
+            .zip(to_row.chunks_exact(4))
+            .zip(out_row.chunks_exact_mut(4))
+        {
+            for i in 0..4 {
+                let v = a[i] as u32 * from_weight + b[i] as u32 * to_weight + black[i];
+                c[i] = (v >> 8).min(255) as u8;
+            }
+        }
+    }
+}
+
+enum State {
+    /// Frames pass straight through.
+    Live,
+    /// The source is changing; `held` is sent instead of incoming frames.
+    Holding { held: Arc<Frame>, since: Instant },
+    /// The source has changed; `held` is sent until a frame other than
+    /// `stale`, which may still show the old source, arrives.
+    Armed {
+        held: Arc<Frame>,
+        stale: Option<Arc<Frame>>,
+        since: Instant,
+    },
+    /// Transitioning from `from`, scaled to the new source's size, to the
+    /// incoming frames.
+    Running { from: Arc<Frame>, started: Instant },
+}
+
+struct Inner {
+    state: State,
+    /// The last frame received, as the pacer repeats it.
+    last: Option<Arc<Frame>>,
+    /// The last frame sent on.
+    shown: Option<Arc<Frame>>,
+}
+
+/// Covers source changes: holds the last frame of the old source while the
+/// stream is reconfigured, then transitions into the new source's frames.
+///
+/// It sits right after the pacer, so a transition advances on every tick
+/// even while the new source is static.
+pub struct TransitionSink {
+    transition: Transition,
+    inner: Mutex<Inner>,
+    pool: FramePool,
+    sink: Arc<dyn VideoSink>,
+}
+
+impl TransitionSink {
+    pub fn new(transition: Transition, pool: FramePool, sink: Arc<dyn VideoSink>) -> Self {
+        Self {
+            transition,
+            inner: Mutex::new(Inner {
+                state: State::Live,
+                last: None,
+                shown: None,
+            }),
+            pool,
+            sink,
+        }
+    }
+
+    /// Freezes the output on what it shows now. A transition already under
+    /// way is frozen mid-way.
+    pub fn hold(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let (State::Live | State::Running { .. }, Some(shown)) = (&inner.state, &inner.shown) {
+            inner.state = State::Holding {
+                held: shown.clone(),
+                since: Instant::now(),
+            };
+        }
+    }
+
+    /// Marks the source change done; the next new frame starts the
+    /// transition.
+    pub fn release(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let stale = inner.last.clone();
+        if let State::Holding { held, since } = &inner.state {
+            inner.state = State::Armed {
+                held: held.clone(),
+                stale,
+                since: *since,
+            };
+        }
+    }
+
+    /// The frame to send for `frame` arriving at `now`.
+    fn render(&self, inner: &mut Inner, frame: Arc<Frame>, now: Instant) -> Arc<Frame> {
+        inner.last = Some(frame.clone());
+        match &inner.state {
+            State::Live => frame,
+            State::Holding { held, since } if now - *since < HOLD_TIMEOUT => held.clone(),
+            State::Armed { held, stale, since }
+                if now - *since < HOLD_TIMEOUT
+                    && stale
+                        .as_ref()
+                        .is_some_and(|stale| Arc::ptr_eq(stale, &frame)) =>
+            {
+                held.clone()
+            }
+            State::Holding { held, .. } | State::Armed { held, .. } => {
+                let held = held.clone();
+                self.begin(inner, held, &frame, now);
+                self.render(inner, frame, now)
+            }
+            State::Running { from, started } => {
+                let started = *started;
+                let progress =
+                    (now - started).as_secs_f64() / self.transition.duration.as_secs_f64();
+                if progress >= 1. {
+                    inner.state = State::Live;
+                    return frame;
+                }
+                // The new source may change size mid-way, such as when its
+                // window is resized.
+                let from = self.fit(from.clone(), &frame);
+                inner.state = State::Running {
+                    from: from.clone(),
+                    started,
+                };
+                let mut out = Frame::black(
+                    PixelFormat::Bgra,
+                    frame.width(),
+                    frame.height(),
+                    Some(&self.pool),
+                );
+                out.set_timestamp(frame.timestamp());
+                out.set_cursor(frame.cursor());
+                mix(
+                    &from,
+                    &frame,
+                    self.transition.kind.weights(progress),
+                    &mut out,
+                );
+                Arc::new(out)
+            }
+        }
+    }
+
+    /// Starts the transition from `held` into frames like `to`.
+    fn begin(&self, inner: &mut Inner, held: Arc<Frame>, to: &Frame, now: Instant) {
+        if self.transition.kind == TransitionKind::Cut || self.transition.duration.is_zero() {
+            inner.state = State::Live;
+            return;
+        }
+        inner.state = State::Running {
+            from: self.fit(held, to),
+            started: now,
+        };
+    }
+
+    /// `from` scaled to the size of `to`, if it isn't already.
+    fn fit(&self, from: Arc<Frame>, to: &Frame) -> Arc<Frame> {
+        if (from.width(), from.height()) == (to.width(), to.height()) {
+            return from;
+        }
+        let scaler = Scaler::new(
+            Size::new(to.width(), to.height()),
+            Filter::Bilinear,
+            Fit::Letterbox,
+        );
+        Arc::new(scaler.scale(&from, Some(&self.pool)))
+    }
+}
+
+impl VideoSink for TransitionSink {
+    fn send_video(&self, frame: Arc<Frame>) {
+        let frame = {
+            let mut inner = self.inner.lock().unwrap();
+            let frame = self.render(&mut inner, frame, Instant::now());
+            inner.shown = Some(frame.clone());
+            frame
+        };
+        self.sink.send_video(frame);
+    }
+
+    fn send_idle(&self) {
+        self.sink.send_idle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::metrics::Gauge;
+
+    struct Discard;
+
+    impl VideoSink for Discard {
+        fn send_video(&self, _frame: Arc<Frame>) {}
+    }
+
+    fn gray(value: u8) -> [u8; 4] {
+        [value, value, value, 0xff]
+    }
+
+    fn transition_sink(kind: TransitionKind) -> TransitionSink {
+        let transition = Transition {
+            kind,
+            duration: Duration::from_secs(1),
+        };
+        let pool = FramePool::new(3, Arc::new(Gauge::default()));
+        TransitionSink::new(transition, pool, Arc::new(Discard))
+    }
+
+    /// Sends `frame` as if it arrived at `now`, returning what went out.
+    fn send(sink: &TransitionSink, frame: &Arc<Frame>, now: Instant) -> Arc<Frame> {
+        let mut inner = sink.inner.lock().unwrap();
+        let out = sink.render(&mut inner, frame.clone(), now);
+        inner.shown = Some(out.clone());
+        out
+    }
+
+    /// The gray level of every pixel, which must all be the same.
+    fn level(frame: &Frame) -> u8 {
+        let first = frame.data()[0];
+        assert!(frame
+            .data()
+            .chunks_exact(4)
+            .all(|pixel| pixel == [first, first, first, 0xff]));
+        first
+    }
+
+    /// Switches from a frame of `from` to one of `to`, returning when the
+    /// transition started.
+    fn switch(sink: &TransitionSink, from: &Arc<Frame>, to: &Arc<Frame>) -> Instant {
+        let start = Instant::now();
+        send(sink, from, start);
+        sink.hold();
+        sink.release();
+        // The pacer repeats the old source's last frame until a new one comes.
+        assert!(Arc::ptr_eq(&send(sink, from, start), from));
+        send(sink, to, start);
+        start
+    }
+
+    #[test]
+    fn weighs_the_pictures_by_kind() {
+        assert_eq!(TransitionKind::Cut.weights(0.2), (0., 1.));
+        assert_eq!(TransitionKind::Crossfade.weights(0.25), (0.75, 0.25));
+        assert_eq!(TransitionKind::DipToBlack.weights(0.25), (0.5, 0.));
+        assert_eq!(TransitionKind::DipToBlack.weights(0.75), (0., 0.5));
+        assert_eq!(TransitionKind::Crossfade.weights(2.), (0., 1.));
+    }
+
+    #[test]
+    fn mixes_with_black_making_up_the_rest() {
+        let (from, to) = (
+            Arc::new(Frame::solid(2, 2, gray(100))),
+            Arc::new(Frame::solid(2, 2, gray(200))),
+        );
+        let mut out = Frame::black(PixelFormat::Bgra, 2, 2, None);
+        mix(&from, &to, (0.5, 0.5), &mut out);
+        assert_eq!(level(&out), 150);
+        mix(&from, &to, (0.5, 0.), &mut out);
+        assert_eq!(level(&out), 50);
+        mix(&from, &to, (0., 0.), &mut out);
+        assert_eq!(level(&out), 0);
+        mix(&from, &to, (1., 0.), &mut out);
+        assert_eq!(level(&out), 100);
+    }
+
+    #[test]
+    fn passes_frames_through_while_live() {
+        let sink = transition_sink(TransitionKind::Crossfade);
+        let frame = Arc::new(Frame::solid(2, 2, gray(10)));
+        assert!(Arc::ptr_eq(&send(&sink, &frame, Instant::now()), &frame));
+    }
+
+    #[test]
+    fn holds_the_last_frame_until_released() {
+        let sink = transition_sink(TransitionKind::Crossfade);
+        let start = Instant::now();
+        let old = Arc::new(Frame::solid(2, 2, gray(0)));
+        send(&sink, &old, start);
+        sink.hold();
+        let new = Arc::new(Frame::solid(2, 2, gray(200)));
+        assert!(Arc::ptr_eq(&send(&sink, &new, start), &old));
+    }
+
+    #[test]
+    fn gives_up_holding_after_a_timeout() {
+        let sink = transition_sink(TransitionKind::Cut);
+        let start = Instant::now();
+        send(&sink, &Arc::new(Frame::solid(2, 2, gray(0))), start);
+        sink.hold();
+        let new = Arc::new(Frame::solid(2, 2, gray(200)));
+        let later = start + HOLD_TIMEOUT + Duration::from_millis(10);
+        assert!(Arc::ptr_eq(&send(&sink, &new, later), &new));
+    }
+
+    #[test]
+    fn crossfades_into_the_new_source() {
+        let sink = transition_sink(TransitionKind::Crossfade);
+        let new = Arc::new(Frame::solid(2, 2, gray(200)));
+        let start = switch(&sink, &Arc::new(Frame::solid(2, 2, gray(0))), &new);
+        let at = |millis| send(&sink, &new, start + Duration::from_millis(millis));
+        assert_eq!(level(&at(250)), 50);
+        assert_eq!(level(&at(500)), 100);
+        assert!(Arc::ptr_eq(&at(1000), &new));
+        assert!(Arc::ptr_eq(&at(500), &new));
+    }
+
+    #[test]
+    fn dips_to_black() {
+        let sink = transition_sink(TransitionKind::DipToBlack);
+        let new = Arc::new(Frame::solid(2, 2, gray(200)));
+        let start = switch(&sink, &Arc::new(Frame::solid(2, 2, gray(100))), &new);
+        let at = |millis| level(&send(&sink, &new, start + Duration::from_millis(millis)));
+        assert_eq!(at(250), 50);
+        assert_eq!(at(500), 0);
+        assert_eq!(at(750), 100);
+    }
+
+    #[test]
+    fn cuts_without_mixing() {
+        let sink = transition_sink(TransitionKind::Cut);
+        let new = Arc::new(Frame::solid(2, 2, gray(200)));
+        let start = switch(&sink, &Arc::new(Frame::solid(2, 2, gray(0))), &new);
+        assert!(Arc::ptr_eq(&send(&sink, &new, start), &new));
+    }
+
+    #[test]
+    fn scales_the_old_picture_to_the_new_size() {
+        let sink = transition_sink(TransitionKind::Crossfade);
+        let new = Arc::new(Frame::solid(8, 4, gray(200)));
+        let start = switch(&sink, &Arc::new(Frame::solid(4, 4, gray(100))), &new);
+        let out = send(&sink, &new, start + Duration::from_millis(500));
+        assert_eq!((out.width(), out.height()), (8, 4));
+        // The old picture is letterboxed into the middle of the frame.
+        let row: Vec<u8> = out.data()[..32].chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [100, 100, 150, 150, 150, 150, 100, 100]);
+    }
+
+    #[test]
+    fn follows_a_size_change_while_running() {
+        let sink = transition_sink(TransitionKind::Crossfade);
+        let start = switch(
+            &sink,
+            &Arc::new(Frame::solid(4, 2, gray(0))),
+            &Arc::new(Frame::solid(4, 2, gray(200))),
+        );
+        let at =
+            |frame: &Arc<Frame>, millis| send(&sink, frame, start + Duration::from_millis(millis));
+        let larger = Arc::new(Frame::solid(8, 4, gray(200)));
+        let out = at(&larger, 500);
+        assert_eq!((out.width(), out.height()), (8, 4));
+        assert_eq!(level(&out), 100);
+        let smaller = Arc::new(Frame::solid(2, 2, gray(200)));
+        let out = at(&smaller, 750);
+        assert_eq!((out.width(), out.height()), (2, 2));
+        assert_eq!(level(&out), 150);
+        assert!(Arc::ptr_eq(&at(&smaller, 1000), &smaller));
+    }
+}
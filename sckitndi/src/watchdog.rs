@@ -0,0 +1,363 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Doubles from `initial` up to `max`; `attempt` starts at 1.
+pub fn backoff(attempt: u32, initial: Duration, max: Duration) -> Duration {
+    let factor = 1u32
+        .checked_shl(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    initial.saturating_mul(factor).min(max)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restart {
+    /// Consecutive restarts since frames last arrived, starting at 1.
+    pub attempt: u32,
+    pub reason: String,
+    /// How long the watchdog waits before it restarts again.
+    pub backoff: Duration,
+}
+
+struct State {
+    armed: bool,
+    last_activity: Instant,
+    failure: Option<String>,
+    attempt: u32,
+    not_before: Instant,
+}
+
+/// Decides when a capture that should be running needs to be restarted.
+///
+/// The capture counts as stalled when it has reported a failure or nothing
+/// has arrived for `stall_timeout`. Restarts back off exponentially until a
+/// frame arrives again.
+pub struct Watchdog<C: Clock = SystemClock> {
+    clock: C,
+    stall_timeout: Option<Duration>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    state: Mutex<State>,
+}
+
+impl Watchdog {
+    pub fn new(stall_timeout: Option<Duration>) -> Self {
+        Self::with_clock(SystemClock, stall_timeout, INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+impl<C: Clock> Watchdog<C> {
+    pub fn with_clock(
+        clock: C,
+        stall_timeout: Option<Duration>,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        let now = clock.now();
+        Self {
+            clock,
+            stall_timeout,
+            initial_backoff,
+            max_backoff,
+            state: Mutex::new(State {
+                armed: false,
+                last_activity: now,
+                failure: None,
+                attempt: 0,
+                not_before: now,
+            }),
+        }
+    }
+
+    /// The capture was asked to run; start watching it.
+    pub fn arm(&self) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        if state.armed {
+            return;
+        }
+        *state = State {
+            armed: true,
+            last_activity: now,
+            failure: None,
+            attempt: 0,
+            not_before: now,
+        };
+    }
+
+    /// The capture was asked to stop; stop watching it.
+    pub fn disarm(&self) {
+        self.state.lock().unwrap().armed = false;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.state.lock().unwrap().armed
+    }
+
+    /// Records that the stream delivered something. Returns the number of
+    /// restarts it took to recover, if it had stalled.
+    pub fn activity(&self) -> Option<u32> {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = now;
+        state.failure = None;
+        let attempts = std::mem::take(&mut state.attempt);
+        (state.armed && attempts > 0).then_some(attempts)
+    }
+
+    /// Records that the stream failed or stopped on its own.
+    pub fn failed(&self, reason: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        if state.armed {
+            state.failure = Some(reason.into());
+        }
+    }
+
+    /// Called periodically; returns a restart when one is due.
+    pub fn poll(&self) -> Option<Restart> {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        if !state.armed || now < state.not_before {
+            return None;
+        }
+        let idle = now.saturating_duration_since(state.last_activity);
+        let reason = match (&state.failure, self.stall_timeout) {
+            (Some(failure), _) => failure.clone(),
+            (None, Some(timeout)) if idle >= timeout => {
+                format!("no frames for {:.1}s", idle.as_secs_f64())
+            }
+            _ => return None,
+        };
+        state.attempt += 1;
+        let backoff = backoff(state.attempt, self.initial_backoff, self.max_backoff);
+        state.not_before = now + backoff;
+        // Give the restarted stream a full timeout before it counts as
+        // stalled again.
+        state.last_activity = now;
+        state.failure = None;
+        Some(Restart {
+            attempt: state.attempt,
+            reason,
+            backoff,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+    struct FakeClock {
+        start: Instant,
+        elapsed: Mutex<Duration>,
+    }
+
+    impl FakeClock {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                start: Instant::now(),
+                elapsed: Mutex::new(Duration::ZERO),
+            })
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.elapsed.lock().unwrap() += by;
+        }
+
+        fn elapsed(&self) -> Duration {
+            *self.elapsed.lock().unwrap()
+        }
+    }
+
+    impl Clock for Arc<FakeClock> {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed()
+        }
+    }
+
+    fn watchdog(stall_timeout: Option<Duration>) -> (Watchdog<Arc<FakeClock>>, Arc<FakeClock>) {
+        let clock = FakeClock::new();
+        let watchdog =
+            Watchdog::with_clock(clock.clone(), stall_timeout, INITIAL_BACKOFF, MAX_BACKOFF);
+        (watchdog, clock)
+    }
+
+    #[test]
+    fn doubles_the_backoff_up_to_the_maximum() {
+        let backoffs: Vec<_> = (1..=8)
+            .map(|attempt| backoff(attempt, INITIAL_BACKOFF, MAX_BACKOFF).as_secs())
+            .collect();
+        assert_eq!(backoffs, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff(0, INITIAL_BACKOFF, MAX_BACKOFF), INITIAL_BACKOFF);
+        assert_eq!(backoff(u32::MAX, INITIAL_BACKOFF, MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn does_nothing_until_armed() {
+        let (watchdog, clock) = watchdog(Some(STALL_TIMEOUT));
+        watchdog.failed("failed");
+        clock.advance(STALL_TIMEOUT * 2);
+        assert_eq!(watchdog.poll(), None);
+        assert!(!watchdog.is_armed());
+    }
+
+    #[test]
+    fn restarts_a_stalled_capture() {
+        let (watchdog, clock) = watchdog(Some(STALL_TIMEOUT));
+        watchdog.arm();
+        clock.advance(STALL_TIMEOUT - Duration::from_millis(1));
+        assert_eq!(watchdog.poll(), None);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(
+            watchdog.poll(),
+            Some(Restart {
+                attempt: 1,
+                reason: "no frames for 10.0s".to_string(),
+                backoff: INITIAL_BACKOFF,
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_a_capture_without_a_stall_timeout() {
+        let (watchdog, clock) = watchdog(None);
+        watchdog.arm();
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(watchdog.poll(), None);
+    }
+
+    #[test]
+    fn restarts_after_a_failure_without_waiting_for_the_timeout() {
+        let (watchdog, _) = watchdog(None);
+        watchdog.arm();
+        watchdog.failed("stream stopped");
+        let restart = watchdog.poll().unwrap();
+        assert_eq!(restart.reason, "stream stopped");
+        assert_eq!(watchdog.poll(), None);
+    }
+
+    #[test]
+    fn activity_keeps_the_capture_alive() {
+        let (watchdog, clock) = watchdog(Some(STALL_TIMEOUT));
+        watchdog.arm();
+        for _ in 0..5 {
+            clock.advance(STALL_TIMEOUT / 2);
+            assert_eq!(watchdog.activity(), None);
+            assert_eq!(watchdog.poll(), None);
+        }
+    }
+
+    #[test]
+    fn ignores_failures_once_disarmed() {
+        let (watchdog, _) = watchdog(None);
+        watchdog.arm();
+        watchdog.disarm();
+        watchdog.failed("stream stopped");
+        assert_eq!(watchdog.poll(), None);
+    }
+
+    /// Stands in for the grabber: a capture that delivers frames only while
+    /// `healthy` and fails right away while `failing`, restarted whenever the
+    /// watchdog asks.
+    struct FakeCapture {
+        watchdog: Watchdog<Arc<FakeClock>>,
+        clock: Arc<FakeClock>,
+        healthy: bool,
+        failing: bool,
+        restarts: Vec<(Duration, u32)>,
+        recovered: Option<u32>,
+    }
+
+    impl FakeCapture {
+        fn new(stall_timeout: Option<Duration>) -> Self {
+            let (watchdog, clock) = watchdog(stall_timeout);
+            watchdog.arm();
+            Self {
+                watchdog,
+                clock,
+                healthy: true,
+                failing: false,
+                restarts: Vec::new(),
+                recovered: None,
+            }
+        }
+
+        /// Runs for `duration` in steps of the grabber's polling interval.
+        fn run(&mut self, duration: Duration) {
+            let step = Duration::from_millis(500);
+            for _ in 0..duration.as_millis() / step.as_millis() {
+                self.clock.advance(step);
+                if self.healthy {
+                    if let Some(attempts) = self.watchdog.activity() {
+                        self.recovered = Some(attempts);
+                    }
+                }
+                if self.failing {
+                    self.watchdog.failed("stream stopped");
+                }
+                if let Some(restart) = self.watchdog.poll() {
+                    self.restarts.push((self.clock.elapsed(), restart.attempt));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn backs_off_while_the_capture_stays_down() {
+        let mut capture = FakeCapture::new(Some(STALL_TIMEOUT));
+        capture.run(Duration::from_secs(30));
+        assert!(capture.restarts.is_empty());
+
+        capture.healthy = false;
+        capture.run(Duration::from_secs(60));
+        let secs: Vec<_> = capture
+            .restarts
+            .iter()
+            .map(|(at, attempt)| (at.as_secs(), *attempt))
+            .collect();
+        // Each restart gets a full timeout, and at least the backoff, before
+        // the next one.
+        assert_eq!(secs, [(40, 1), (50, 2), (60, 3), (70, 4), (80, 5)]);
+
+        capture.healthy = true;
+        capture.run(Duration::from_secs(1));
+        assert_eq!(capture.recovered, Some(5));
+        capture.run(Duration::from_secs(60));
+        assert_eq!(capture.restarts.len(), 5);
+    }
+
+    #[test]
+    fn spaces_restarts_after_failures_by_the_backoff() {
+        let mut capture = FakeCapture::new(None);
+        capture.healthy = false;
+        capture.failing = true;
+        capture.run(Duration::from_secs(200));
+        let gaps: Vec<_> = capture
+            .restarts
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).as_secs())
+            .collect();
+        assert_eq!(gaps, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(capture.restarts.last().unwrap().1, 9);
+    }
+}
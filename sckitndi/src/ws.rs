@@ -0,0 +1,243 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc::RecvTimeoutError, Arc},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tungstenite::{
+    handshake::server::{Callback, ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message, WebSocket,
+};
+
+use crate::{
+    control::Controller,
+    events::{Event, EventBus},
+    http::is_allowed_origin,
+};
+
+/// How long to wait for an event before checking what the client sent.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Streams [`Event`]s as JSON text messages to WebSocket clients.
+pub struct EventServer {
+    local_addr: SocketAddr,
+}
+
+impl EventServer {
+    pub fn bind(
+        addr: SocketAddr,
+        allowed_origins: Vec<String>,
+        events: Arc<EventBus>,
+        controller: Arc<dyn Controller>,
+    ) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+        let local_addr = listener.local_addr()?;
+        std::thread::Builder::new()
+            .name("ws".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let allowed_origins = allowed_origins.clone();
+                    let events = events.clone();
+                    let controller = controller.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = serve(stream, allowed_origins, &events, &*controller) {
+                            tracing::warn!("ws: {:#}", err);
+                        }
+                    });
+                }
+            })?;
+        Ok(Self { local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// Turns away handshakes from web pages on origins that aren't allowed.
+struct CheckOrigin(Vec<String>);
+
+impl Callback for CheckOrigin {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let origin = request
+            .headers()
+            .get("origin")
+            .map(|origin| origin.to_str().unwrap_or("invalid"));
+        if is_allowed_origin(origin, &self.0) {
+            return Ok(response);
+        }
+        let mut response = ErrorResponse::new(Some("Origin not allowed".to_string()));
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        Err(response)
+    }
+}
+
+fn send(websocket: &mut WebSocket<TcpStream>, event: &Event) -> Result<bool> {
+    match websocket.send(Message::Text(serde_json::to_string(event)?)) {
+        Ok(()) => Ok(true),
+        Err(tungstenite::Error::ConnectionClosed) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Handles whatever the client sent without waiting for more. Pings and
+/// closes are answered by tungstenite itself; anything else is ignored.
+/// Returns `false` once the connection is closed.
+fn poll_incoming(websocket: &mut WebSocket<TcpStream>) -> Result<bool> {
+    websocket.get_ref().set_nonblocking(true)?;
+    let ret = loop {
+        match websocket.read() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => {
+                break Ok(true)
+            }
+            Err(tungstenite::Error::ConnectionClosed) => break Ok(false),
+            Err(err) => break Err(err.into()),
+        }
+    };
+    websocket.get_ref().set_nonblocking(false)?;
+    ret
+}
+
+fn serve(
+    stream: TcpStream,
+    allowed_origins: Vec<String>,
+    events: &EventBus,
+    controller: &dyn Controller,
+) -> Result<()> {
+    let mut websocket = tungstenite::accept_hdr(stream, CheckOrigin(allowed_origins))?;
+    // Subscribe before taking the snapshot so that nothing in between is lost.
+    let subscription = events.subscribe();
+    let status = controller.status();
+    let snapshot = [
+        Event::State {
+            running: status.running,
+            paused: status.paused,
+            slate: status.slate,
+        },
+        Event::Source {
+            source: status.source,
+        },
+        Event::Permission {
+            permission: status.permission,
+        },
+        controller.tally().into(),
+    ];
+    for event in &snapshot {
+        if !send(&mut websocket, event)? {
+            return Ok(());
+        }
+    }
+    loop {
+        match subscription.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                if !send(&mut websocket, &event)? {
+                    return Ok(());
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        if !poll_incoming(&mut websocket)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::{client::IntoClientRequest, HandshakeError};
+
+    use super::*;
+    use crate::control::fake::FakeController;
+
+    fn server() -> (SocketAddr, Arc<EventBus>) {
+        let events = Arc::new(EventBus::new());
+        let server = EventServer::bind(
+            ([127, 0, 0, 1], 0).into(),
+            vec!["http://localhost:3000".to_string()],
+            events.clone(),
+            Arc::new(FakeController::default()),
+        )
+        .unwrap();
+        (server.local_addr(), events)
+    }
+
+    /// Connects, or returns the status the handshake was refused with.
+    fn connect(addr: SocketAddr, origin: Option<&str>) -> Result<WebSocket<TcpStream>, StatusCode> {
+        let mut request = format!("ws://{}/", addr).into_client_request().unwrap();
+        if let Some(origin) = origin {
+            request
+                .headers_mut()
+                .insert("origin", origin.parse().unwrap());
+        }
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        match tungstenite::client(request, stream) {
+            Ok((websocket, _)) => Ok(websocket),
+            Err(HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+                Err(response.status())
+            }
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn read_type(websocket: &mut WebSocket<TcpStream>) -> String {
+        let Message::Text(text) = websocket.read().unwrap() else {
+            panic!("expected a text message");
+        };
+        let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+        event["type"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn sends_a_snapshot_then_events() {
+        let (addr, events) = server();
+        let mut websocket = connect(addr, None).unwrap();
+        let snapshot: Vec<_> = (0..4).map(|_| read_type(&mut websocket)).collect();
+        assert_eq!(snapshot, ["state", "source", "permission", "tally"]);
+
+        events.publish(Event::Scene {
+            name: "wide".to_string(),
+        });
+        assert_eq!(read_type(&mut websocket), "scene");
+    }
+
+    #[test]
+    fn answers_pings_and_closes() {
+        let (addr, _events) = server();
+        let mut websocket = connect(addr, None).unwrap();
+        for _ in 0..4 {
+            read_type(&mut websocket);
+        }
+
+        websocket.send(Message::Ping(vec![1, 2])).unwrap();
+        assert_eq!(websocket.read().unwrap(), Message::Pong(vec![1, 2]));
+
+        websocket.close(None).unwrap();
+        assert!(matches!(websocket.read().unwrap(), Message::Close(_)));
+        assert!(matches!(
+            websocket.read(),
+            Err(tungstenite::Error::ConnectionClosed)
+        ));
+    }
+
+    #[test]
+    fn refuses_origins_that_are_not_allowed() {
+        let (addr, _events) = server();
+        assert_eq!(
+            connect(addr, Some("http://evil.example")).err(),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert!(connect(addr, Some("http://localhost:3000")).is_ok());
+    }
+}